@@ -1,5 +1,5 @@
 use axum::{response::Redirect, routing::get, Router};
-use log::info;
+use log::{error, info};
 use std::net::SocketAddr;
 use tower_http::cors::CorsLayer;
 use utoipa::OpenApi;
@@ -19,9 +19,33 @@ pub async fn start(port: u16) {
             router::files::files_create,
             router::files::files_update,
             router::files::files_delete,
+            router::history::history_index,
+            router::compliance::compliance_index,
+            router::baselines::baselines_index,
+            router::baselines::baselines_create,
+            router::baselines::baselines_delete,
+            router::baselines::baselines_diff,
+            router::schedules::schedules_index,
+            router::schedules::schedules_create,
+            router::schedules::schedules_update,
+            router::schedules::schedules_delete,
+            router::deployments::deployments_index,
+            router::deployments::deployments_show,
+            router::deployments::deployment_settings_index,
+            router::deployments::deployment_settings_update,
+            router::scans::scans_index,
+            router::scans::scan_settings_index,
+            router::scans::scan_settings_update,
+            router::outbox::outbox_index,
+            router::outbox::outbox_purge,
+            router::outbox::outbox_settings_index,
+            router::outbox::outbox_settings_update,
             router::settings::settings_index,
             router::settings::settings_update,
-            router::logs::logs_index
+            router::logs::logs_index,
+            router::metrics::metrics_index,
+            router::processes::processes_index,
+            router::revisions::revisions_index
         ),
         components(
             schemas(
@@ -29,19 +53,76 @@ pub async fn start(port: u16) {
                 router::files::File, 
                 router::files::FileUpdateParams, 
                 router::files::FileCreateParams, 
+                router::files::VersionSource,
+                router::files::CommandError,
+                router::files::VersionExtractor,
+                router::files::PackageFormat,
+                router::files::HashAlgorithm,
+                router::files::WatchMode,
+                router::files::FileLifecycle,
+                router::history::VersionHistoryEntry,
+                router::history::VersionChange,
+                router::compliance::CompliancePolicy,
+                router::compliance::Compliance,
+                router::compliance::ComplianceReport,
+                router::compliance::FileCompliance,
+                router::baselines::Baseline,
+                router::baselines::BaselineEntry,
+                router::baselines::BaselineCreateParams,
+                router::baselines::BaselineDiff,
+                router::baselines::DriftEntry,
+                router::baselines::DriftKind,
+                router::baselines::DiffFormat,
+                router::schedules::Schedule,
+                router::schedules::ScheduleCreateParams,
+                router::schedules::ScheduleUpdateParams,
+                router::schedules::ScheduleError,
+                router::deployments::Deployment,
+                router::deployments::FileTransition,
+                router::deployments::DeploymentSettings,
+                router::deployments::DeploymentSettingsUpdateParams,
+                router::scans::ScanStatus,
+                router::scans::ScanSettings,
+                router::scans::ScanSettingsUpdateParams,
+                router::outbox::Outbox,
+                router::outbox::OutboxMessage,
+                router::outbox::OutboxSettings,
+                router::outbox::OutboxSettingsUpdateParams,
+                router::outbox::OutboxPurgeTarget,
+                router::outbox::DeadLetterPolicy,
                 router::settings::Broker, 
                 router::settings::BrokerUpdateParams, 
+                router::settings::MqttQos, 
+                router::settings::BrokerStatus, 
+                router::settings::BrokerTls, 
+                router::settings::TlsVersion, 
+                router::settings::MqttVersion, 
+                router::settings::BrokerV5, 
                 router::settings::DBError, 
                 router::logs::Logs,
                 router::logs::ServerError,
-                router::logs::LogLevels
+                router::logs::LogLevels,
+                router::metrics::DebounceMetrics,
+                router::processes::ProcessState,
+                router::processes::ProcessInfo,
+                router::revisions::GitRevision
             )
         ),
         tags(
             (name = "info", description = "Information about this application"),
             (name = "files", description = "File items management API"),
+            (name = "history", description = "Version history API"),
+            (name = "compliance", description = "Version compliance API"),
+            (name = "baselines", description = "Baseline snapshots and drift reports API"),
+            (name = "schedules", description = "Scheduled rescans API"),
+            (name = "deployments", description = "Deployment sessions API"),
+            (name = "scans", description = "Scan worker pool API"),
+            (name = "outbox", description = "Queued MQTT messages API"),
             (name = "settings", description = "Application settings management API"),
-            (name = "logs", description = "Application logs API")
+            (name = "logs", description = "Application logs API"),
+            (name = "metrics", description = "File watcher metrics API"),
+            (name = "processes", description = "Running process monitoring API"),
+            (name = "revisions", description = "Git working copy revisions API")
         )
     )]
     struct ApiDoc;
@@ -70,13 +151,60 @@ pub async fn start(port: u16) {
         );
 
     // init plugins
-    plugins::init(app_state);
+    let plugins = plugins::init(app_state);
 
     // run it
     let addr = SocketAddr::from(([0, 0, 0, 0], port));
     info!("[Server] Server started, listening on {}", &addr);
     axum::Server::bind(&addr)
         .serve(app.into_make_service())
+        .with_graceful_shutdown(shutdown_signal())
         .await
         .unwrap();
+
+    // publish offline status before the application exits
+    info!("[Server] Shutting down..");
+    plugins.shutdown().await;
+}
+
+/// Resolves on Ctrl+C, on SIGTERM (unix) or when the console window gets closed or the system
+/// shuts down (windows)
+async fn shutdown_signal() {
+    tokio::select! {
+        _ = wait_for_signal("Ctrl+C", tokio::signal::ctrl_c()) => {}
+        _ = wait_for_signal("terminate", terminate_signal()) => {}
+    }
+}
+
+/// Waits for a signal, never resolves if the signal can not be listened for
+async fn wait_for_signal(
+    name: &str,
+    signal: impl std::future::Future<Output = std::io::Result<()>>,
+) {
+    if let Err(err) = signal.await {
+        error!("[Server] Could not listen for {name} signal: {err:?}");
+        // keep the server running without graceful shutdown on this signal
+        std::future::pending::<()>().await;
+    }
+}
+
+#[cfg(unix)]
+async fn terminate_signal() -> std::io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    signal(SignalKind::terminate())?.recv().await;
+    Ok(())
+}
+
+#[cfg(windows)]
+async fn terminate_signal() -> std::io::Result<()> {
+    use tokio::signal::windows::{ctrl_close, ctrl_shutdown};
+
+    let mut close = ctrl_close()?;
+    let mut shutdown = ctrl_shutdown()?;
+    tokio::select! {
+        _ = close.recv() => {}
+        _ = shutdown.recv() => {}
+    }
+    Ok(())
 }
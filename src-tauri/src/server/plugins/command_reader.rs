@@ -0,0 +1,162 @@
+use super::version_extractor::extract_version;
+use crate::server::router::files::{CommandError, VersionExtractor};
+use std::{
+    fmt,
+    io::Read,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Don't open a console window for each executed command
+#[cfg(windows)]
+const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::Spawn { message } => {
+                write!(f, "[Run Command] Could not start command: {message}")
+            }
+            CommandError::Timeout { secs } => {
+                write!(f, "[Run Command] Command timed out after {secs}s")
+            }
+            CommandError::ExitCode {
+                code: Some(code),
+                stderr,
+            } => {
+                write!(f, "[Run Command] Command exited with code {code}: {stderr}")
+            }
+            CommandError::ExitCode { code: None, stderr } => {
+                write!(f, "[Run Command] Command terminated by signal: {stderr}")
+            }
+            CommandError::Output { message } => write!(f, "{message}"),
+        }
+    }
+}
+
+/// Gets a version from the stdout of a command.
+///
+/// The command gets killed if it does not finish within the timeout.
+pub fn get_command_version(
+    path: &str,
+    args: &[String],
+    working_dir: &str,
+    timeout_secs: u64,
+    extractor: &VersionExtractor,
+) -> Result<String, CommandError> {
+    let mut command = Command::new(path);
+    command
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    // run command in binary directory if no working directory is configured
+    match (working_dir.is_empty(), Path::new(path).parent()) {
+        (false, _) => {
+            command.current_dir(working_dir);
+        }
+        (true, Some(parent)) if parent.is_dir() => {
+            command.current_dir(parent);
+        }
+        _ => {}
+    }
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+
+    let mut child = command.spawn().map_err(|err| CommandError::Spawn {
+        message: err.to_string(),
+    })?;
+
+    // read output in separate threads, otherwise a full pipe buffer blocks the command
+    let stdout = read_pipe(child.stdout.take());
+    let stderr = read_pipe(child.stderr.take());
+
+    let timeout = Duration::from_secs(timeout_secs);
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            // the pipe threads are not joined, a child process of the command can keep them open
+            Ok(None) if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(CommandError::Timeout { secs: timeout_secs });
+            }
+            Ok(None) => thread::sleep(Duration::from_millis(50)),
+            Err(err) => {
+                return Err(CommandError::Spawn {
+                    message: err.to_string(),
+                })
+            }
+        }
+    };
+
+    // the output is complete once all processes which inherited the pipes closed them
+    let stdout = wait_for_output(&stdout, deadline);
+    let stderr = wait_for_output(&stderr, deadline).unwrap_or_default();
+
+    if !status.success() {
+        return Err(CommandError::ExitCode {
+            code: status.code(),
+            stderr: stderr.trim().to_string(),
+        });
+    }
+
+    let stdout = stdout.ok_or(CommandError::Timeout { secs: timeout_secs })?;
+    extract_version(&stdout, extractor).map_err(|message| CommandError::Output { message })
+}
+
+/// Path of the executed binary, names without directory are searched in the search path like the
+/// command does (unchanged if not found)
+pub fn resolve_binary(path: &str) -> PathBuf {
+    let binary = Path::new(path);
+    let bare_name = binary
+        .parent()
+        .is_some_and(|parent| parent.as_os_str().is_empty());
+    if !bare_name {
+        return binary.to_path_buf();
+    }
+
+    // windows appends the executable extension to names without extension
+    let name = match cfg!(windows) && binary.extension().is_none() {
+        true => binary.with_extension("exe"),
+        false => binary.to_path_buf(),
+    };
+    std::env::var_os("PATH")
+        .and_then(|paths| {
+            std::env::split_paths(&paths)
+                .map(|dir| dir.join(&name))
+                .find(|candidate| candidate.is_file())
+        })
+        .unwrap_or_else(|| binary.to_path_buf())
+}
+
+fn read_pipe<R: Read + Send + 'static>(pipe: Option<R>) -> mpsc::Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut output = String::new();
+        if let Some(mut pipe) = pipe {
+            let mut buffer = Vec::new();
+            if pipe.read_to_end(&mut buffer).is_ok() {
+                output = String::from_utf8_lossy(&buffer).to_string();
+            }
+        }
+        let _ = sender.send(output);
+    });
+    receiver
+}
+
+/// Waits for the output of a pipe until the deadline, none if the pipe is still open
+fn wait_for_output(output: &mpsc::Receiver<String>, deadline: Instant) -> Option<String> {
+    output
+        .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        .ok()
+}
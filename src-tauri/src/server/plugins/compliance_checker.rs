@@ -0,0 +1,173 @@
+use super::version_compare::{compare_versions, matches_requirement};
+use crate::server::router::compliance::{Compliance, CompliancePolicy};
+use std::cmp::Ordering;
+
+/// Evaluates a version against the compliance policy of a file.
+pub fn evaluate(policy: &CompliancePolicy, version: &str) -> Compliance {
+    if version.is_empty() {
+        return Compliance::Unknown;
+    }
+
+    let compliant = match policy {
+        CompliancePolicy::None => None,
+        CompliancePolicy::Exact { version: expected } => Some(is_same_version(version, expected)),
+        CompliancePolicy::Requirement { requirement } => matches_requirement(version, requirement),
+        CompliancePolicy::Range { min, max } => in_range(version, min, max),
+        CompliancePolicy::Allowlist { versions } => Some(
+            versions
+                .iter()
+                .any(|expected| is_same_version(version, expected)),
+        ),
+    };
+
+    match compliant {
+        Some(true) => Compliance::Compliant,
+        Some(false) => Compliance::NonCompliant,
+        None => Compliance::Unknown,
+    }
+}
+
+/// Versions are the same if they are equal or have the same precedence (e.g. `1.2` and `1.2.0`)
+fn is_same_version(version: &str, expected: &str) -> bool {
+    version.trim() == expected.trim()
+        || compare_versions(version, expected) == Some(Ordering::Equal)
+}
+
+/// Checks an inclusive range, returns `None` if a bound can not be compared
+fn in_range(version: &str, min: &str, max: &str) -> Option<bool> {
+    if !min.is_empty() && compare_versions(version, min)? == Ordering::Less {
+        return Some(false);
+    }
+    if !max.is_empty() && compare_versions(version, max)? == Ordering::Greater {
+        return Some(false);
+    }
+    Some(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exact(version: &str) -> CompliancePolicy {
+        CompliancePolicy::Exact {
+            version: version.to_string(),
+        }
+    }
+
+    fn requirement(requirement: &str) -> CompliancePolicy {
+        CompliancePolicy::Requirement {
+            requirement: requirement.to_string(),
+        }
+    }
+
+    fn range(min: &str, max: &str) -> CompliancePolicy {
+        CompliancePolicy::Range {
+            min: min.to_string(),
+            max: max.to_string(),
+        }
+    }
+
+    #[test]
+    fn exact_version() {
+        assert_eq!(
+            evaluate(&exact("7.2.0.0"), "7.2.0.0"),
+            Compliance::Compliant
+        );
+        // same precedence in another notation
+        assert_eq!(evaluate(&exact("7.2"), "7.2.0.0"), Compliance::Compliant);
+        assert_eq!(
+            evaluate(&exact("7.2.0.0"), "7.2.0.1"),
+            Compliance::NonCompliant
+        );
+        // versions which can not be ordered are compared as text
+        assert_eq!(evaluate(&exact("1a2b3c"), "1a2b3c"), Compliance::Compliant);
+        assert_eq!(
+            evaluate(&exact("1a2b3c"), "4d5e6f"),
+            Compliance::NonCompliant
+        );
+    }
+
+    #[test]
+    fn requirement_comparators() {
+        let policy = requirement(">=7.2, <8");
+        assert_eq!(evaluate(&policy, "7.2.0.0"), Compliance::Compliant);
+        assert_eq!(evaluate(&policy, "7.9.1"), Compliance::Compliant);
+        assert_eq!(evaluate(&policy, "8.0"), Compliance::NonCompliant);
+        assert_eq!(evaluate(&policy, "7.1.9"), Compliance::NonCompliant);
+
+        assert_eq!(
+            evaluate(&requirement("^1.4"), "1.9.0"),
+            Compliance::Compliant
+        );
+        assert_eq!(
+            evaluate(&requirement("^1.4"), "2.0.0"),
+            Compliance::NonCompliant
+        );
+        assert_eq!(
+            evaluate(&requirement("~7.2"), "7.2.5"),
+            Compliance::Compliant
+        );
+        assert_eq!(
+            evaluate(&requirement("~7.2"), "7.3.0"),
+            Compliance::NonCompliant
+        );
+        assert_eq!(
+            evaluate(&requirement("7.2.*"), "7.2.9.1"),
+            Compliance::Compliant
+        );
+        assert_eq!(
+            evaluate(&requirement("7.2.*"), "7.3"),
+            Compliance::NonCompliant
+        );
+        // pre-releases are lower than the release
+        assert_eq!(
+            evaluate(&requirement(">=2.0"), "2.0.0-rc.1"),
+            Compliance::NonCompliant
+        );
+    }
+
+    #[test]
+    fn inclusive_range() {
+        let policy = range("7.2.0.0", "7.9.0.0");
+        assert_eq!(evaluate(&policy, "7.2.0.0"), Compliance::Compliant);
+        assert_eq!(evaluate(&policy, "7.9.0.0"), Compliance::Compliant);
+        assert_eq!(evaluate(&policy, "7.1.9.9"), Compliance::NonCompliant);
+        assert_eq!(evaluate(&policy, "7.9.0.1"), Compliance::NonCompliant);
+        // empty bounds are open
+        assert_eq!(evaluate(&range("", "2.0"), "0.1"), Compliance::Compliant);
+        assert_eq!(evaluate(&range("2.0", ""), "99.0"), Compliance::Compliant);
+    }
+
+    #[test]
+    fn allowlist() {
+        let policy = CompliancePolicy::Allowlist {
+            versions: vec!["7.2.0.0".to_string(), "7.2.1.0".to_string()],
+        };
+        assert_eq!(evaluate(&policy, "7.2.1"), Compliance::Compliant);
+        assert_eq!(evaluate(&policy, "7.2.2.0"), Compliance::NonCompliant);
+        let empty = CompliancePolicy::Allowlist {
+            versions: Vec::new(),
+        };
+        assert_eq!(evaluate(&empty, "7.2.0.0"), Compliance::NonCompliant);
+    }
+
+    #[test]
+    fn unknown_compliance() {
+        // no policy or no version
+        assert_eq!(
+            evaluate(&CompliancePolicy::None, "7.2.0.0"),
+            Compliance::Unknown
+        );
+        assert_eq!(evaluate(&exact("7.2.0.0"), ""), Compliance::Unknown);
+        // versions and requirements which can not be compared
+        assert_eq!(
+            evaluate(&requirement(">=7.2"), "1a2b3c"),
+            Compliance::Unknown
+        );
+        assert_eq!(evaluate(&requirement(">=abc"), "7.2"), Compliance::Unknown);
+        assert_eq!(
+            evaluate(&range("7.2", "7.9"), "1a2b3c"),
+            Compliance::Unknown
+        );
+    }
+}
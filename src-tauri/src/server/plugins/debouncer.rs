@@ -1,47 +1,110 @@
+use crate::server::router::metrics::DebounceMetrics;
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
 use tokio::task::JoinHandle;
+use tokio::time::Instant;
 
 type Func = Box<dyn FnMut() + Send + Sync>;
 
 pub struct Bouncer {
+    /// Default quiet period after the last event
     pub delay: Duration,
-    bouncer: HashMap<String, BouncerInstance>,
+    /// Max time between the first event and the execution during continuous events
+    pub max_wait: Duration,
+    bouncer: Arc<Mutex<HashMap<String, BouncerInstance>>>,
+    /// Id of the next delayed task, tasks only remove their own entry
+    next_id: u64,
+    metrics: Arc<RwLock<DebounceMetrics>>,
 }
 
 struct BouncerInstance {
+    id: u64,
     task: JoinHandle<()>,
+    first_event: Instant,
 }
 
 impl Bouncer {
-    pub fn new(delay: Duration) -> Self {
+    pub fn new(delay: Duration, max_wait: Duration, metrics: Arc<RwLock<DebounceMetrics>>) -> Self {
         return Bouncer {
             delay,
-            bouncer: HashMap::new(),
+            max_wait,
+            bouncer: Arc::new(Mutex::new(HashMap::new())),
+            next_id: 0,
+            metrics,
         };
     }
 
-    /// Debounce an inline functino execution with a specific duration. Calls only on last execution with specified delay.
-    pub fn debounce(&mut self, path: String, func: Func) -> Result<(), &str> {
-        let mut execute = func;
-        let delay = self.delay;
+    /// Debounce an inline function execution with a quiet period for this path. Calls only on last
+    /// execution with specified delay.
+    ///
+    /// The execution is forced once the max wait time since the first event of a burst is reached.
+    pub fn debounce(&mut self, path: String, delay: Duration, func: Func) -> Result<(), &str> {
+        // abort the pending execution (if there is one) and keep the start of the burst
+        let mut bouncer = self.bouncer.lock().unwrap();
+        let now = Instant::now();
+        let mut suppressed = false;
+        let first_event = match bouncer.get(&path) {
+            Some(debouncer) => {
+                debouncer.task.abort();
+                suppressed = true;
+                debouncer.first_event
+            }
+            None => now,
+        };
+
+        // continuous events do not postpone the execution longer than the max wait time
+        let max_deadline = first_event + self.max_wait;
+        let forced = now + delay > max_deadline;
+        let deadline = match forced {
+            true => max_deadline,
+            false => now + delay,
+        };
 
-        // start function in delayed task
+        // start function in delayed task, the entry is removed before the execution so events
+        // during the execution start a new burst
+        let id = self.next_id;
+        self.next_id += 1;
+        let mut execute = func;
+        let entries = self.bouncer.clone();
+        let entry_path = path.clone();
+        let metrics = self.metrics.clone();
         let task = tokio::spawn(async move {
-            tokio::time::sleep(delay).await;
+            tokio::time::sleep_until(deadline).await;
+            {
+                // a newer event replaced this task after the sleep (the abort came too late), the
+                // newer task executes instead
+                let mut entries = entries.lock().unwrap();
+                if entries.get(&entry_path).map(|debouncer| debouncer.id) != Some(id) {
+                    return;
+                }
+                entries.remove(&entry_path);
+                let mut metrics = metrics.write().unwrap();
+                metrics.pending = entries.len() as u64;
+                metrics.executions += 1;
+                if forced {
+                    metrics.forced_executions += 1;
+                }
+            }
             execute()
         });
 
-        // store task in local state and abort the old one (if there was one)
-        if let Some(mut debouncer) = self.bouncer.get_mut(&path) {
-            if !debouncer.task.is_finished() {
-                debouncer.task.abort();
-            }
-            debouncer.task = task;
-        } else {
-            // create new debouncer
-            self.bouncer.insert(path.clone(), BouncerInstance { task });
+        // store task in local state
+        bouncer.insert(
+            path,
+            BouncerInstance {
+                id,
+                task,
+                first_event,
+            },
+        );
+
+        let mut metrics = self.metrics.write().unwrap();
+        metrics.events += 1;
+        if suppressed {
+            metrics.suppressed += 1;
         }
+        metrics.pending = bouncer.len() as u64;
 
         Ok(())
     }
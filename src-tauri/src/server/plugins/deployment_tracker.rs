@@ -0,0 +1,215 @@
+use super::{
+    mqtt_client::MqttClient,
+    mqtt_outbox::{self, Delivery},
+};
+use crate::server::{
+    router::{
+        deployments::{Deployment, DeploymentSettings, Deployments, FileTransition},
+        files::File,
+        history::VersionChange,
+        settings::Broker,
+    },
+    store,
+};
+use chrono::SecondsFormat;
+use log::{error, info};
+use microkv::MicroKV;
+use serde_json::json;
+use std::{
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+use tokio::time::Instant;
+use uuid::Uuid;
+
+static DB_KEY: &str = "deployments";
+static DB_KEY_SETTINGS: &str = "deployment_settings";
+static DB_KEY_BROKER: &str = "broker";
+
+/// Max number of stored deployments
+const MAX_DEPLOYMENTS: usize = 100;
+
+/// Deployments which still collect changes
+static OPEN_SESSIONS: Mutex<Vec<Session>> = Mutex::new(Vec::new());
+
+struct Session {
+    deployment: Deployment,
+    last_change: Instant,
+    window: Duration,
+}
+
+/// Adds a version change to the open deployment of the file (a new one gets started if there is
+/// none), returns the deployment id.
+///
+/// First seen and unchanged versions are not part of deployments.
+pub fn record_change(
+    store: &Arc<RwLock<MicroKV>>,
+    mqtt_client: &MqttClient,
+    file: &File,
+    previous_version: &str,
+    change: VersionChange,
+) -> Option<Uuid> {
+    if matches!(change, VersionChange::FirstSeen | VersionChange::Unchanged) {
+        return None;
+    }
+    let settings = store::get::<DeploymentSettings>(&store.read().unwrap(), DB_KEY_SETTINGS)
+        .unwrap_or_default();
+    if !settings.enabled {
+        return None;
+    }
+
+    let group = match settings.by_group {
+        true => file.group.clone(),
+        false => String::new(),
+    };
+    let now = chrono::offset::Utc::now().to_string();
+    let transition = FileTransition {
+        id: file.id,
+        name: file.name.clone(),
+        previous_version: previous_version.to_string(),
+        version: file.last_version.clone(),
+        change,
+        timestamp_utc: now.clone(),
+    };
+
+    let mut sessions = OPEN_SESSIONS.lock().unwrap();
+    if let Some(session) = sessions
+        .iter_mut()
+        .find(|session| session.deployment.group == group)
+    {
+        session.deployment.transitions.push(transition);
+        session.deployment.end_utc = now;
+        session.last_change = Instant::now();
+        return Some(session.deployment.id);
+    }
+
+    let id = Uuid::new_v4();
+    sessions.push(Session {
+        deployment: Deployment {
+            id,
+            group: group.clone(),
+            start_utc: now.clone(),
+            end_utc: now,
+            transitions: vec![transition],
+        },
+        last_change: Instant::now(),
+        window: Duration::from_secs(settings.window_secs),
+    });
+    tokio::spawn(close_session(
+        store.clone(),
+        mqtt_client.clone(),
+        id,
+        settings.mqtt_topic,
+    ));
+
+    Some(id)
+}
+
+/// Returns true if changes of deployments are only published with the deployment summary
+pub fn summary_only(db: &MicroKV) -> bool {
+    let settings = store::get::<DeploymentSettings>(db, DB_KEY_SETTINGS).unwrap_or_default();
+    settings.enabled && settings.summary_only && !settings.mqtt_topic.is_empty()
+}
+
+/// Waits until the deployment had no changes for the configured window, then stores and publishes it
+async fn close_session(
+    store: Arc<RwLock<MicroKV>>,
+    mut mqtt_client: MqttClient,
+    id: Uuid,
+    mqtt_topic: String,
+) {
+    let deployment = loop {
+        let deadline = {
+            let mut sessions = OPEN_SESSIONS.lock().unwrap();
+            let index = match sessions
+                .iter()
+                .position(|session| session.deployment.id == id)
+            {
+                Some(index) => index,
+                None => return,
+            };
+            let deadline = sessions[index].last_change + sessions[index].window;
+            if deadline <= Instant::now() {
+                break sessions.remove(index).deployment;
+            }
+            deadline
+        };
+        tokio::time::sleep_until(deadline).await;
+    };
+
+    info!(
+        "[Deployments] Deployment {} finished with {} version changes ({} - {})",
+        &deployment.id,
+        deployment.transitions.len(),
+        &deployment.start_utc,
+        &deployment.end_utc
+    );
+
+    let lock = store.write().unwrap();
+    let mut deployments = store::get::<Deployments>(&lock, DB_KEY).unwrap_or_default();
+    deployments.push(deployment.clone());
+    if deployments.len() > MAX_DEPLOYMENTS {
+        deployments.drain(..deployments.len() - MAX_DEPLOYMENTS);
+    }
+    if let Err(err) = store::put(&lock, DB_KEY, &deployments) {
+        error!("Could not write deployment to local DB: {err:?}")
+    }
+
+    // publish one summary message for all changes
+    if mqtt_topic.is_empty() {
+        return;
+    }
+    match store::get::<Broker>(&lock, DB_KEY_BROKER) {
+        Ok(broker) => {
+            let (device_id, device_group) = {
+                let config = mqtt_client.current_client_config.read().unwrap();
+                (config.device_id.clone(), config.device_group.clone())
+            };
+            let changes = deployment
+                .transitions
+                .iter()
+                .map(|transition| {
+                    json!({
+                      "fileId": transition.id,
+                      "name": transition.name,
+                      "previousVersion": transition.previous_version,
+                      "version": transition.version,
+                      "change": transition.change,
+                      "timestamp": transition.timestamp_utc,
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            let mut message = mqtt_outbox::message(
+                &mqtt_topic,
+                json!({
+                  "deviceId": device_id,
+                  "timestamp": chrono::offset::Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+                  "group": device_group,
+                  "deploymentId": deployment.id,
+                  "fileGroup": deployment.group,
+                  "start": deployment.start_utc,
+                  "end": deployment.end_utc,
+                  "changes": changes,
+                }),
+                broker.qos,
+                broker.retain,
+            );
+            message
+                .properties
+                .insert("deploymentId".to_string(), deployment.id.to_string());
+            match mqtt_outbox::deliver(&lock, &mut mqtt_client, &broker, message) {
+                Delivery::Sent => {}
+                Delivery::Queued => info!(
+                    "[Deployments] Deployment {} queued, MQTT broker is not connected",
+                    &deployment.id
+                ),
+                Delivery::Dropped => error!(
+                    "[Deployments] Deployment {} not published, MQTT broker is not connected",
+                    &deployment.id
+                ),
+            }
+        }
+        Err(err) => error!("Could not get broker data due to: {err:?}"),
+    }
+}
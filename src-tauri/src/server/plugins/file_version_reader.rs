@@ -1,5 +1,54 @@
+use crate::server::{
+    router::files::{Files, HashAlgorithm, VersionSource},
+    store,
+};
+use log::{error, info};
+use microkv::MicroKV;
 use sha2::{Digest, Sha256};
-use std::io::Read;
+use std::{
+    collections::BTreeMap,
+    io::{BufReader, Read},
+    path::Path,
+    sync::{Arc, Mutex, RwLock},
+    time::UNIX_EPOCH,
+};
+
+static DB_KEY_FILES: &str = "files";
+static DB_KEY_HASH_FORMAT: &str = "hash_format";
+
+/// Current format of stored content hashes (0: sha256 of the debug formatted byte vector)
+const HASH_FORMAT: u32 = 1;
+/// Size of the chunks in which files get read for hashing
+const HASH_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Calculated hashes by path and algorithm, reused as long as the file identity does not change
+static HASH_CACHE: Mutex<BTreeMap<(String, HashAlgorithm), (FileIdentity, String)>> =
+    Mutex::new(BTreeMap::new());
+
+/// Size, modification time and inode (file index on windows) of a file
+#[derive(Debug, PartialEq)]
+struct FileIdentity {
+    size: u64,
+    modified: u128,
+    file_id: u64,
+}
+
+/// Gets the version of a local file.
+///
+/// Uses the file properties for `.exe` and `.dll` files and the file hash for all other files.
+pub fn get_file_version(path: &str, algorithm: &HashAlgorithm) -> Result<String, String> {
+    match has_file_properties(path) {
+        true => get_file_version_from_file_properties(path),
+        false => get_file_meta_hash(path, algorithm),
+    }
+}
+
+fn has_file_properties(path: &str) -> bool {
+    matches!(
+        Path::new(path).extension().and_then(|ext| ext.to_str()),
+        Some("exe") | Some("dll")
+    )
+}
 
 /// Gets a file version from the file properties.
 ///
@@ -14,31 +63,184 @@ pub fn get_file_version_from_file_properties(path: &str) -> Result<String, Strin
     }
 }
 
-/// Gets the file hash from the file metadata.
+/// Gets the file hash from the file content.
 ///
-/// Can be used for files without a specific file version.
-pub fn get_file_meta_hash(path: &str) -> Result<String, String> {
-    let file = std::fs::File::options().read(true).write(false).open(&path);
+/// Can be used for files without a specific file version. The content is read in chunks and the
+/// hash is cached until the size, modification time or file id changes.
+pub fn get_file_meta_hash(path: &str, algorithm: &HashAlgorithm) -> Result<String, String> {
+    let file = std::fs::File::options()
+        .read(true)
+        .write(false)
+        .open(path)
+        .map_err(|err| format!("[Get File Version] {err}"))?;
 
-    match file {
-        Ok(mut file) => {
-            // get file content
-            let mut file_content = Vec::new();
+    let identity = file_identity(&file)
+        .map_err(|err| format!("[Get File Version] Could not read file metadata: {err}"))?;
+    let cache_key = (path.to_string(), algorithm.clone());
+    if let Some((cached_identity, hash)) = HASH_CACHE.lock().unwrap().get(&cache_key) {
+        if cached_identity == &identity {
+            return Ok(hash.clone());
+        }
+    }
 
-            if let Err(err) = file.read_to_end(&mut file_content) {
+    let mut hasher = Hasher::new(algorithm);
+    let mut reader = BufReader::with_capacity(HASH_BUFFER_SIZE, file);
+    let mut buffer = vec![0u8; HASH_BUFFER_SIZE];
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(count) => hasher.update(&buffer[..count]),
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => {
                 return Err(format!(
-                    "[Get File Version] Could not read file content: {}",
-                    err.to_string()
-                ));
+                    "[Get File Version] Could not read file content: {err}"
+                ))
+            }
+        }
+    }
+    let hash = hasher.finalize();
+
+    HASH_CACHE
+        .lock()
+        .unwrap()
+        .insert(cache_key, (identity, hash.clone()));
+    Ok(hash)
+}
+
+/// Replaces content hashes stored by older application versions with the current hash format.
+///
+/// Hashes are only replaced if the file content did not change since the last check, otherwise
+/// the next check reports the change as usual.
+pub fn migrate_legacy_hashes(db: &Arc<RwLock<MicroKV>>) {
+    let lock = db.write().unwrap();
+    if store::get::<u32>(&lock, DB_KEY_HASH_FORMAT).unwrap_or(0) >= HASH_FORMAT {
+        return;
+    }
+
+    let mut files = match store::get::<Files>(&lock, DB_KEY_FILES) {
+        Ok(files) => files,
+        Err(err) => {
+            error!("Could not read file config from local DB: {err:?}");
+            return;
+        }
+    };
+    for file in files.values_mut() {
+        if file.source != VersionSource::File
+            || file.last_version.is_empty()
+            || has_file_properties(&file.path)
+        {
+            continue;
+        }
+
+        if get_legacy_meta_hash(&file.path).as_ref() == Ok(&file.last_version) {
+            match get_file_meta_hash(&file.path, &file.hash_algorithm) {
+                Ok(hash) => {
+                    info!("[Files] Migrated stored hash of file '{}'", &file.name);
+                    file.last_version = hash;
+                }
+                Err(err) => error!("Could not migrate hash of file '{}': {err}", &file.name),
+            }
+        }
+    }
+
+    if let Err(err) = store::put(&lock, DB_KEY_FILES, &files) {
+        error!("Could not write migrated hashes to local DB: {err:?}");
+        return;
+    }
+    if let Err(err) = store::put(&lock, DB_KEY_HASH_FORMAT, &HASH_FORMAT) {
+        error!("Could not write hash format to local DB: {err:?}")
+    }
+}
+
+/// Calculates the hash format of older application versions, which hashed the debug output of
+/// the content byte vector (`[1, 2, 3]`).
+fn get_legacy_meta_hash(path: &str) -> Result<String, String> {
+    let file = std::fs::File::open(path).map_err(|err| err.to_string())?;
+    let mut reader = BufReader::with_capacity(HASH_BUFFER_SIZE, file);
+    let mut buffer = vec![0u8; HASH_BUFFER_SIZE];
+
+    let mut hasher = Sha256::new();
+    let mut first = true;
+    hasher.update("[");
+    loop {
+        let count = reader.read(&mut buffer).map_err(|err| err.to_string())?;
+        if count == 0 {
+            break;
+        }
+
+        let mut formatted = String::with_capacity(count * 5);
+        for byte in &buffer[..count] {
+            if !first {
+                formatted.push_str(", ");
             }
+            formatted.push_str(&byte.to_string());
+            first = false;
+        }
+        hasher.update(formatted);
+    }
+    hasher.update("]");
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn file_identity(file: &std::fs::File) -> std::io::Result<FileIdentity> {
+    let metadata = file.metadata()?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|modified| modified.as_nanos())
+        .unwrap_or_default();
+
+    #[cfg(unix)]
+    let file_id = std::os::unix::fs::MetadataExt::ino(&metadata);
+    #[cfg(windows)]
+    let file_id = winapi_util::file::information(file)?.file_index();
+    #[cfg(not(any(unix, windows)))]
+    let file_id = 0;
+
+    Ok(FileIdentity {
+        size: metadata.len(),
+        modified,
+        file_id,
+    })
+}
+
+/// Hasher of the selected hash algorithm
+enum Hasher {
+    Sha256(Sha256),
+    Sha1(sha1::Sha1),
+    Md5(md5::Md5),
+    Blake3(Box<blake3::Hasher>),
+}
 
-            // hash file content
-            let mut hasher = Sha256::new();
-            hasher.update(format!("{file_content:?}"));
-            let hash = hasher.finalize();
+impl Hasher {
+    fn new(algorithm: &HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Sha1 => Hasher::Sha1(sha1::Sha1::new()),
+            HashAlgorithm::Md5 => Hasher::Md5(md5::Md5::new()),
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sha1(hasher) => hasher.update(data),
+            Hasher::Md5(hasher) => hasher.update(data),
+            Hasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
 
-            Ok(format!("{:x}", hash))
+    /// Returns the hash as lowercase hex string (like `sha256sum`)
+    fn finalize(self) -> String {
+        match self {
+            Hasher::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+            Hasher::Sha1(hasher) => format!("{:x}", hasher.finalize()),
+            Hasher::Md5(hasher) => format!("{:x}", hasher.finalize()),
+            Hasher::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
         }
-        Err(err) => Err(format!("[Get File Version] {}", err.to_string())),
     }
 }
@@ -1,84 +1,130 @@
-use super::{debouncer, mqtt_client::MqttClient};
-use crate::server::{plugins::handle_file_change, router::files::Files, store::AppState};
+use super::{command_reader, debouncer, git_reader};
+use crate::server::{
+    router::{
+        files::{File, FileLifecycle, Files, VersionSource},
+        metrics::DebounceMetrics,
+    },
+    store::{self, AppState},
+};
 use futures::{
     channel::mpsc::{channel, Receiver},
     SinkExt, StreamExt,
 };
 use log::{error, info, warn};
 use microkv::MicroKV;
-use notify::{Config, Event, RecommendedWatcher, RecursiveMode, Watcher};
+use notify::{
+    event::{ModifyKind, RenameMode},
+    Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+};
 use std::{
-    path::Path,
-    sync::{Arc, RwLock},
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
     time::Duration,
 };
 use tokio::sync::broadcast::Sender;
-use tokio::task::JoinHandle;
+use uuid::Uuid;
 
 static DB_KEY: &str = "files";
 
+/// Default quiet period after the last change event of a file
+const DEBOUNCE_DELAY: Duration = Duration::from_millis(500);
+/// Max time a file check gets postponed by continuous change events (e.g. during an installer run)
+const DEBOUNCE_MAX_WAIT: Duration = Duration::from_secs(30);
+
 pub struct FileWatcher {
     sender: Arc<RwLock<Sender<String>>>,
     store: Arc<RwLock<MicroKV>>,
-    watcher_thread: Arc<RwLock<JoinHandle<()>>>,
+    watch_state: Arc<Mutex<WatchState>>,
     current_file_config: Arc<RwLock<Files>>,
-    mqtt_client: MqttClient,
+}
+
+/// Native watches of all files, directories are only (un)watched if their reference count changes
+struct WatchState {
+    /// `None` if the native watcher could not be created
+    watcher: Option<RecommendedWatcher>,
+    /// watched directories with the number of files which depend on them
+    watched_dirs: HashMap<String, (PathBuf, usize)>,
+    /// watches of all enabled files by their id
+    watched_files: HashMap<Uuid, WatchedFile>,
+    db_path: String,
+}
+
+/// Watch relevant parts of a file config, the watches are only renewed if one of them changes
+#[derive(Debug, PartialEq)]
+struct WatchSpec {
+    path: String,
+    /// watched path on disk (binary of a command source resolved through the search path)
+    watch_path: String,
+    git: bool,
+    debounce_ms: u64,
+}
+
+struct WatchedFile {
+    spec: WatchSpec,
+    /// watched directories which are referenced by this file
+    dirs: Vec<String>,
+    /// git directories whose changes are mapped to the repository path
+    git_dirs: Vec<String>,
+    /// missing parent directory, its nearest existing ancestor is watched until it gets created
+    pending: Option<PathBuf>,
+}
+
+/// Watch state of a file which gets stored to the local DB
+enum WatchResult {
+    /// Parent directory does not exist yet
+    Waiting(String),
+    /// Watch could not be added
+    Failed(String),
 }
 
 impl FileWatcher {
     /// Init file watcher plugin
-    pub fn init(
-        sender: Arc<RwLock<Sender<String>>>,
-        app_state: &Arc<AppState>,
-        mqtt_client: &MqttClient,
-    ) -> Self {
-        // start file watcher
-        let watch_store = app_state.db.clone();
-        let watch_sender = sender.clone();
-        let watcher_thread = tokio::spawn(async move {
-            if let Err(e) = async_watch(watch_store, watch_sender).await {
-                error!("Watcher thread error: {:?}", e)
+    pub fn init(sender: Arc<RwLock<Sender<String>>>, app_state: &Arc<AppState>) -> Self {
+        // add current file state
+        let current_file_config =
+            store::get::<Files>(&app_state.db.read().unwrap(), DB_KEY).unwrap();
+
+        let (watcher, rx) = match async_watcher() {
+            Ok((watcher, rx)) => (Some(watcher), Some(rx)),
+            Err(err) => {
+                error!("Could not create file watcher: {err:?}");
+                (None, None)
             }
-        });
+        };
+        let mut watch_state = WatchState::new(watcher);
+        let results = watch_state.update(&current_file_config);
+        store_watch_results(&app_state.db, results);
+        let watch_state = Arc::new(Mutex::new(watch_state));
 
-        // add current file state
-        let current_file_config = app_state
-            .db
-            .read()
-            .unwrap()
-            .get_unwrap::<Files>(DB_KEY)
-            .unwrap();
+        // start file watcher
+        if let Some(rx) = rx {
+            tokio::spawn(async_watch(
+                watch_state.clone(),
+                rx,
+                app_state.db.clone(),
+                sender.clone(),
+                app_state.debounce_metrics.clone(),
+            ));
+        }
 
         FileWatcher {
             sender,
             store: app_state.db.clone(),
-            watcher_thread: Arc::new(RwLock::new(watcher_thread)),
+            watch_state,
             current_file_config: Arc::new(RwLock::new(current_file_config)),
-            mqtt_client: mqtt_client.clone(),
         }
     }
 
     /// Refresh currently watched files
     pub fn refresh(&mut self) {
         let current_files = self.current_file_config.read().unwrap().clone();
-        let new_files = self
-            .store
-            .read()
-            .unwrap()
-            .get_unwrap::<Files>(DB_KEY)
-            .unwrap();
+        let new_files = store::get::<Files>(&self.store.read().unwrap(), DB_KEY).unwrap();
         if current_files != new_files {
             info!("File watchers refreshed with new config.");
-            // first drop all active file watchers and end task
-            self.watcher_thread.write().unwrap().abort();
-            // add new file watchers by starting them in new task
-            let watch_store = self.store.clone();
-            let watch_sender = self.sender.clone();
-            let thread = tokio::spawn(async move {
-                if let Err(e) = async_watch(watch_store, watch_sender).await {
-                    error!("Watcher thread panicked: {:?}", e)
-                }
-            });
+            // only add and remove the watches of changed files, the others keep receiving events
+            let results = self.watch_state.lock().unwrap().update(&new_files);
+            store_watch_results(&self.store, results);
 
             // trigger reread of changed files (for example enable state)
             for (_uuid, new_file) in &new_files {
@@ -90,13 +136,14 @@ impl FileWatcher {
                 // check if there are any changes
                 if let Some(current_file) = current_files.get(_uuid) {
                     if new_file != current_file {
-                        handle_file_change(&current_file.path, &self.store, &mut self.mqtt_client);
+                        if let Err(err) = self.sender.read().unwrap().send(new_file.path.clone()) {
+                            error!("Could not send file change event due to: {err:?}")
+                        }
                     }
                 }
             }
 
             // store updated data to local state
-            *self.watcher_thread.write().unwrap() = thread;
             *self.current_file_config.write().unwrap() = new_files;
         }
     }
@@ -121,84 +168,371 @@ fn async_watcher() -> notify::Result<(RecommendedWatcher, Receiver<notify::Resul
     Ok((watcher, rx))
 }
 
-/// Start file watchers for all configured files
-async fn async_watch(
-    store: Arc<RwLock<MicroKV>>,
-    sender: Arc<RwLock<Sender<String>>>,
-) -> notify::Result<()> {
-    let (mut watcher, mut rx) = async_watcher()?;
-    let mut new_watch_paths: Vec<String> = Vec::new();
-    let mut active_watch_files: Vec<String> = Vec::new();
-
-    // get all files from db and loop through it to add the watchers
-    {
-        let lock = store.write().unwrap();
-        let files = lock.get_unwrap::<Files>("files");
-        match files {
-            Ok(mut files) => {
-                // if files found, watch the parent folders for changes
-                for (_uuid, mut file) in &mut files {
-                    // skip disabled file watchers
-                    if !&file.enabled {
-                        continue;
+impl WatchState {
+    fn new(watcher: Option<RecommendedWatcher>) -> Self {
+        let mut state = WatchState {
+            watcher,
+            watched_dirs: HashMap::new(),
+            watched_files: HashMap::new(),
+            db_path: format!(
+                "{}/{}.kv",
+                crate::server::store::FILE_DB_PATH,
+                crate::server::store::FILE_DB_NAME
+            ),
+        };
+
+        // always watch for local db file changes
+        let db_watch = match state.watcher.as_mut() {
+            Some(watcher) => watcher.watch(Path::new(&state.db_path), RecursiveMode::Recursive),
+            None => return state,
+        };
+        if let Err(err) = db_watch {
+            error!(
+                "Could not add local db watcher '{}' due to: {err:?}",
+                &state.db_path
+            );
+        };
+
+        state
+    }
+
+    /// Updates the watches to a new file config, returns the files which are waiting for their
+    /// directory or whose watches could not be added
+    fn update(&mut self, files: &Files) -> Vec<(Uuid, WatchResult)> {
+        // remove watches of deleted, disabled and changed files
+        let removed = self
+            .watched_files
+            .iter()
+            .filter(|(uuid, watched)| {
+                files.get(uuid).and_then(watch_spec).as_ref() != Some(&watched.spec)
+            })
+            .map(|(uuid, _)| *uuid)
+            .collect::<Vec<_>>();
+        let released = removed
+            .iter()
+            .filter_map(|uuid| self.watched_files.remove(uuid))
+            .flat_map(|watched| watched.dirs)
+            .collect::<Vec<_>>();
+
+        // add watches of new, enabled and changed files
+        let mut results = Vec::new();
+        for (uuid, file) in files {
+            let spec = match watch_spec(file) {
+                Some(spec) if !self.watched_files.contains_key(uuid) => spec,
+                _ => continue,
+            };
+            let mut watched = WatchedFile {
+                spec,
+                dirs: Vec::new(),
+                git_dirs: Vec::new(),
+                pending: None,
+            };
+
+            match self.add_watches(&mut watched) {
+                Ok(()) => {
+                    if let Some(pending) = &watched.pending {
+                        let pending = pending.to_string_lossy().to_string();
+                        info!("Waiting for path '{pending}' of file '{}'", &file.path);
+                        results.push((*uuid, WatchResult::Waiting(pending)));
                     }
+                }
+                Err(err) => {
+                    warn!("Could not add file watcher '{}' due to: {err}", &file.path);
+                    results.push((*uuid, WatchResult::Failed(err)));
+                }
+            }
+            // failed watches are kept as well, they get retried after a config change of the file
+            self.watched_files.insert(*uuid, watched);
+        }
 
-                    active_watch_files.push(file.path.clone().replace("\\", "/"));
+        // directories are released after adding the new watches, so shared ones are not re-created
+        for dir in released {
+            self.release(&dir);
+        }
 
-                    if let Some(folder_path) = Path::new(&file.path).parent() {
-                        // only add folder watcher if not added yet
-                        if !new_watch_paths.contains(&String::from(folder_path.to_string_lossy())) {
-                            if let Err(err) =
-                                watcher.watch(folder_path, RecursiveMode::NonRecursive)
-                            {
-                                warn!(
-                                    "Could not add file watcher '{folder_path:?}' due to: {err:?}"
-                                );
-                                // update file state in case of error and disable watcher
-                                file.update_state = err.to_string();
-                                file.enabled = false;
-                                continue;
-                            };
-                            new_watch_paths.push(String::from(folder_path.to_string_lossy()));
-                        }
-                    };
+        results
+    }
+
+    /// Moves the watches of files which are waiting for their directory closer to it, returns the
+    /// paths of files whose directory is watched now
+    fn upgrade_pending(&mut self) -> Vec<String> {
+        let pending = self
+            .watched_files
+            .iter()
+            .filter(|(_, watched)| watched.pending.is_some())
+            .map(|(uuid, _)| *uuid)
+            .collect::<Vec<_>>();
+
+        let mut ready = Vec::new();
+        for uuid in pending {
+            let mut watched = match self.watched_files.remove(&uuid) {
+                Some(watched) => watched,
+                None => continue,
+            };
+            // the new watch is added before the old one gets released (unchanged ancestors stay)
+            let released = std::mem::take(&mut watched.dirs);
+            watched.pending = None;
+            match self.add_watches(&mut watched) {
+                Ok(()) if watched.pending.is_none() => {
+                    info!("Path of file '{}' exists now", &watched.spec.path);
+                    ready.push(watched.spec.path.clone());
                 }
-                if let Err(err) = lock.put("files", &files) {
-                    error!("Could not update file state on local file db: {err:?}")
+                Ok(()) => {}
+                Err(err) => warn!(
+                    "Could not add file watcher '{}' due to: {err}",
+                    &watched.spec.path
+                ),
+            }
+            self.watched_files.insert(uuid, watched);
+            for dir in released {
+                self.release(&dir);
+            }
+        }
+
+        ready
+    }
+
+    fn add_watches(&mut self, watched: &mut WatchedFile) -> Result<(), String> {
+        // git sources are watched through their HEAD, index and refs
+        if watched.spec.git {
+            let (git_dir, common_dir) = git_reader::get_git_dirs(&watched.spec.path)?;
+            for (dir, mode) in [
+                (git_dir, RecursiveMode::NonRecursive),
+                (common_dir.join("refs"), RecursiveMode::Recursive),
+            ] {
+                let dir = self.acquire(&dir, mode)?;
+                watched.git_dirs.push(format!("{dir}/"));
+                watched.dirs.push(dir);
+            }
+            return Ok(());
+        }
+
+        // command binaries which are not found in the search path have no directory to watch, they
+        // are still scanned on schedule
+        let folder_path = Path::new(&watched.spec.watch_path)
+            .parent()
+            .filter(|folder_path| !folder_path.as_os_str().is_empty());
+        if let Some(folder_path) = folder_path {
+            // missing directories (e.g. of products which get installed later) are pending until
+            // they get created below their nearest existing ancestor
+            let watch_path = match folder_path.is_dir() {
+                true => folder_path,
+                false => {
+                    let ancestor = folder_path
+                        .ancestors()
+                        .skip(1)
+                        .find(|ancestor| ancestor.is_dir())
+                        .ok_or_else(|| format!("Path '{}' not found", folder_path.display()))?;
+                    watched.pending = Some(folder_path.to_path_buf());
+                    ancestor
                 }
+            };
+            let dir = self.acquire(watch_path, RecursiveMode::NonRecursive)?;
+            watched.dirs.push(dir);
+        }
+        Ok(())
+    }
+
+    /// Adds a reference to a directory, it is only watched by the first reference
+    fn acquire(&mut self, dir: &Path, mode: RecursiveMode) -> Result<String, String> {
+        let key = dir.to_string_lossy().replace('\\', "/");
+        match self.watched_dirs.get_mut(&key) {
+            Some((_, count)) => *count += 1,
+            None => {
+                self.watcher
+                    .as_mut()
+                    .ok_or("File watcher is not available")?
+                    .watch(dir, mode)
+                    .map_err(|err| err.to_string())?;
+                self.watched_dirs
+                    .insert(key.clone(), (dir.to_path_buf(), 1));
             }
-            Err(err) => {
-                // if no file found. skip this part
-                error!("Could not add file watcher because there are no configured ones. {err:?}")
+        }
+        Ok(key)
+    }
+
+    /// Removes a reference to a directory, it is unwatched with the last reference
+    fn release(&mut self, key: &str) {
+        let remove = match self.watched_dirs.get_mut(key) {
+            Some((_, count)) => {
+                *count -= 1;
+                *count == 0
+            }
+            None => false,
+        };
+        if let Some((dir, _)) = remove.then(|| self.watched_dirs.remove(key)).flatten() {
+            let unwatch = match self.watcher.as_mut() {
+                Some(watcher) => watcher.unwatch(&dir),
+                None => return,
+            };
+            if let Err(err) = unwatch {
+                warn!("Could not remove file watcher '{key}' due to: {err:?}");
             }
         }
     }
-    // always watch for local db file changes
-    let db_path = crate::server::store::FILE_DB_PATH;
-    let db_name = crate::server::store::FILE_DB_NAME;
-    let db_string = format!("{db_path}/{db_name}.kv");
-    if let Err(err) = watcher.watch(Path::new(&db_string), RecursiveMode::Recursive) {
-        error!("Could not add local db watcher '{db_string}' due to: {err:?}");
+
+    /// Returns the path which gets checked for an event path (file path or repository of a git directory)
+    fn event_path(&self, path: &str) -> Option<String> {
+        if path == self.db_path {
+            return Some(path.to_string());
+        }
+        // changes inside of a git directory are checked on the repository
+        let git_repo = self.watched_files.values().find(|watched| {
+            watched
+                .git_dirs
+                .iter()
+                .any(|git_dir| path.starts_with(git_dir))
+        });
+        if let Some(watched) = git_repo {
+            return Some(watched.spec.path.clone());
+        }
+        self.watched_files
+            .values()
+            .find(|watched| !watched.spec.git && watched.spec.watch_path == path)
+            .map(|watched| watched.spec.path.clone())
+    }
+
+    /// Returns the custom quiet period of a path (longest one if multiple entries share a path)
+    fn debounce_delay(&self, path: &str) -> Option<Duration> {
+        self.watched_files
+            .values()
+            .filter(|watched| watched.spec.path == path && watched.spec.debounce_ms > 0)
+            .map(|watched| Duration::from_millis(watched.spec.debounce_ms))
+            .max()
+    }
+}
+
+/// Returns the watch relevant config of a file (`None` for files without native watches)
+fn watch_spec(file: &File) -> Option<WatchSpec> {
+    // skip disabled file watchers, sources without a local path and polled files
+    if !file.enabled || !file.source.has_local_path() || !file.watch_mode.uses_events() {
+        return None;
+    }
+
+    let watch_path = match file.source {
+        VersionSource::Command { .. } => command_reader::resolve_binary(&file.path)
+            .to_string_lossy()
+            .to_string(),
+        _ => file.path.clone(),
     };
-    active_watch_files.push(db_string.clone());
+    Some(WatchSpec {
+        path: file.path.replace('\\', "/"),
+        watch_path: watch_path.replace('\\', "/"),
+        git: matches!(file.source, VersionSource::Git { .. }),
+        debounce_ms: file.debounce_ms,
+    })
+}
 
+/// Stores watch results to the file state, files with failed watches get disabled (polled files
+/// stay enabled)
+fn store_watch_results(store: &Arc<RwLock<MicroKV>>, results: Vec<(Uuid, WatchResult)>) {
+    if results.is_empty() {
+        return;
+    }
+
+    let lock = store.write().unwrap();
+    let mut files = match store::get::<Files>(&lock, DB_KEY) {
+        Ok(files) => files,
+        Err(err) => {
+            error!("Could not read file config from local DB: {err:?}");
+            return;
+        }
+    };
+    for (uuid, result) in results {
+        let file = match files.get_mut(&uuid) {
+            Some(file) => file,
+            None => continue,
+        };
+        match result {
+            WatchResult::Waiting(path) => {
+                file.update_state = format!("Waiting for path '{path}'");
+                // files which were deleted together with their directory stay missing
+                if file.lifecycle != FileLifecycle::Missing {
+                    file.lifecycle = FileLifecycle::WaitingForPath;
+                }
+            }
+            WatchResult::Failed(err) => {
+                file.update_state = err;
+                file.enabled = file.watch_mode.poll_interval().is_some();
+            }
+        }
+    }
+    if let Err(err) = store::put(&lock, DB_KEY, &files) {
+        error!("Could not update file state on local file db: {err:?}")
+    }
+}
+
+/// Pass the events of all watched paths to the debouncer
+async fn async_watch(
+    watch_state: Arc<Mutex<WatchState>>,
+    mut rx: Receiver<notify::Result<Event>>,
+    store: Arc<RwLock<MicroKV>>,
+    sender: Arc<RwLock<Sender<String>>>,
+    debounce_metrics: Arc<RwLock<DebounceMetrics>>,
+) {
     // check for changes on one of the watched paths
-    let mut debouncer = debouncer::Bouncer::new(Duration::from_millis(500));
+    let mut debouncer =
+        debouncer::Bouncer::new(DEBOUNCE_DELAY, DEBOUNCE_MAX_WAIT, debounce_metrics);
+    // old path of a rename which is reported as two events (e.g. on Windows)
+    let mut pending_rename: Option<(PathBuf, Option<usize>)> = None;
     while let Some(res) = rx.next().await {
         match res {
             Ok(event) => {
+                // created directories can be the missing parent of a pending file watch
+                if event.kind.is_create()
+                    || matches!(event.kind, EventKind::Modify(ModifyKind::Name(_)))
+                {
+                    let ready = watch_state.lock().unwrap().upgrade_pending();
+                    for path in ready {
+                        if let Err(err) = sender.read().unwrap().send(path) {
+                            error!("Could not send file change event due to: {err:?}")
+                        }
+                    }
+                }
+
+                // follow renames of files inside of their directory
+                let previous_rename = pending_rename.take();
+                let renamed = match (&event.kind, event.paths.as_slice()) {
+                    (EventKind::Modify(ModifyKind::Name(RenameMode::Both)), [from, to]) => {
+                        Some((from.clone(), to.clone()))
+                    }
+                    (EventKind::Modify(ModifyKind::Name(RenameMode::From)), [from]) => {
+                        pending_rename = Some((from.clone(), event.tracker()));
+                        None
+                    }
+                    (EventKind::Modify(ModifyKind::Name(RenameMode::To)), [to]) => previous_rename
+                        .filter(|(_, tracker)| *tracker == event.tracker())
+                        .map(|(from, _)| (from, to.clone())),
+                    _ => None,
+                };
+                if let Some(new_path) =
+                    renamed.and_then(|(from, to)| follow_rename(&store, &from, &to))
+                {
+                    if let Err(err) = sender.read().unwrap().send(new_path) {
+                        error!("Could not send file change event due to: {err:?}")
+                    }
+                }
+
                 for path in event.paths.iter() {
                     let path_string = String::from(path.to_string_lossy()).replace("\\", "/");
 
-                    // only pass event for enabled file paths
-                    if !active_watch_files.contains(&path_string) {
-                        continue;
-                    }
+                    // only pass event for enabled file paths or the repository of a git directory
+                    let (path_string, delay) = {
+                        let state = watch_state.lock().unwrap();
+                        match state.event_path(&path_string) {
+                            Some(path_string) => {
+                                let delay = state.debounce_delay(&path_string);
+                                (path_string, delay.unwrap_or(debouncer.delay))
+                            }
+                            None => continue,
+                        }
+                    };
                     // debounce change events from listener (separate for each file path)
                     let tmp_sender = sender.clone();
                     let tmp_path_string = path_string.clone();
                     if let Err(err) = debouncer.debounce(
                         path_string.clone(),
+                        delay,
                         Box::new(move || {
                             if let Err(err) = tmp_sender
                                 .clone()
@@ -217,6 +551,40 @@ async fn async_watch(
             Err(e) => error!("watch error: {:?}", e),
         }
     }
+}
 
-    Ok(())
+/// Updates the path of enabled files which follow renames, returns the new path if one was updated
+fn follow_rename(store: &Arc<RwLock<MicroKV>>, from: &Path, to: &Path) -> Option<String> {
+    // only renames inside of the watched directory are followed
+    if from.parent() != to.parent() {
+        return None;
+    }
+    let from_string = from.to_string_lossy().replace('\\', "/");
+    let to_string = to.to_string_lossy().to_string();
+
+    let lock = store.write().unwrap();
+    let mut files = store::get::<Files>(&lock, DB_KEY).ok()?;
+    let mut renamed = false;
+    for file in files.values_mut().filter(|file| {
+        file.enabled
+            && file.follow_renames
+            && !matches!(file.source, VersionSource::Git { .. })
+            && file.path.replace('\\', "/") == from_string
+    }) {
+        info!(
+            "[{}] Following rename from '{}' to '{to_string}'",
+            &file.name, &file.path
+        );
+        file.path = to_string.clone();
+        renamed = true;
+    }
+    if !renamed {
+        return None;
+    }
+
+    if let Err(err) = store::put(&lock, DB_KEY, &files) {
+        error!("Could not write renamed file path to local DB: {err:?}");
+        return None;
+    }
+    Some(to_string.replace('\\', "/"))
 }
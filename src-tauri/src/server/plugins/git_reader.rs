@@ -0,0 +1,1095 @@
+use crate::server::{
+    router::revisions::{GitRevision, GitRevisions},
+    store,
+};
+use flate2::read::ZlibDecoder;
+use log::error;
+use microkv::MicroKV;
+use sha1::{Digest, Sha1};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+static DB_KEY: &str = "revisions";
+
+/// Max number of commits which get walked to find the nearest tag
+const MAX_TAG_DISTANCE: usize = 1000;
+/// Max number of nested symbolic refs, annotated tags or deltas
+const MAX_DEPTH: usize = 50;
+
+/// Gets the checked out revision of a git working copy.
+///
+/// Reads `HEAD` (resolving symbolic refs and `packed-refs`), the nearest tag in the commit history
+/// and compares the index with the `HEAD` commit and the working tree to detect uncommitted
+/// changes (staged or not) like `git describe --dirty`.
+pub fn get_git_revision(path: &str) -> Result<GitRevision, String> {
+    let repo = Repository::open(Path::new(path))?;
+
+    let head = fs::read_to_string(repo.git_dir.join("HEAD"))
+        .map_err(|err| format!("[Get Git Revision] Could not read HEAD: {err}"))?;
+    let head = head.trim();
+    let (branch, commit) = match head.strip_prefix("ref: ") {
+        Some(ref_name) => (
+            ref_name.trim_start_matches("refs/heads/").to_string(),
+            repo.resolve_ref(ref_name)?,
+        ),
+        // detached HEAD
+        None => (String::new(), head.to_string()),
+    };
+
+    let (tag, distance) = match repo.nearest_tag(&commit)? {
+        Some((tag, distance)) => (tag, distance),
+        None => (String::new(), 0),
+    };
+
+    Ok(GitRevision {
+        dirty: repo.is_dirty(&commit)?,
+        commit,
+        branch,
+        tag,
+        distance,
+    })
+}
+
+/// Formats a revision like `git describe --tags --dirty`, e.g. `v1.2.0-3-g1a2b3c4-dirty`.
+pub fn format_version(revision: &GitRevision) -> String {
+    let short = &revision.commit[..revision.commit.len().min(7)];
+    let mut version = match (revision.tag.is_empty(), revision.distance) {
+        (true, _) => short.to_string(),
+        (false, 0) => revision.tag.clone(),
+        (false, distance) => format!("{}-{distance}-g{short}", revision.tag),
+    };
+
+    if revision.dirty {
+        version.push_str("-dirty");
+    }
+    version
+}
+
+/// Stores the revision details of a git source to the local DB
+pub fn update_git_revision(db: &MicroKV, uuid: &Uuid, revision: &GitRevision) {
+    let mut revisions = store::get::<GitRevisions>(db, DB_KEY).unwrap_or_default();
+    if revisions.get(uuid) == Some(revision) {
+        return;
+    }
+    revisions.insert(*uuid, revision.clone());
+
+    if let Err(err) = store::put(db, DB_KEY, &revisions) {
+        error!("Could not write git revision to local DB: {err:?}")
+    }
+}
+
+/// Returns the git directory (`HEAD`, `index`) and the common directory (`refs`, `objects`) of a
+/// working copy. Both are the same except for linked worktrees.
+pub fn get_git_dirs(path: &str) -> Result<(PathBuf, PathBuf), String> {
+    let repo = Repository::open(Path::new(path))?;
+    Ok((repo.git_dir, repo.common_dir))
+}
+
+struct Repository {
+    work_dir: PathBuf,
+    git_dir: PathBuf,
+    common_dir: PathBuf,
+    packs: Vec<Pack>,
+}
+
+/// Index of a pack file (`objects/pack/*.idx`, version 2)
+struct Pack {
+    pack_path: PathBuf,
+    ids: Vec<u8>,
+    offsets: Vec<u64>,
+}
+
+impl Repository {
+    fn open(work_dir: &Path) -> Result<Self, String> {
+        let dot_git = work_dir.join(".git");
+
+        // worktrees and submodules use a `.git` file pointing to the git directory
+        let git_dir = match fs::read_to_string(&dot_git) {
+            Ok(content) => match content.trim().strip_prefix("gitdir: ") {
+                Some(git_dir) => work_dir.join(git_dir),
+                None => return Err("[Get Git Revision] Invalid .git file.".to_string()),
+            },
+            Err(_) if dot_git.is_dir() => dot_git,
+            Err(_) => {
+                return Err(format!(
+                    "[Get Git Revision] '{}' is not a git working copy.",
+                    work_dir.display()
+                ))
+            }
+        };
+        let common_dir = match fs::read_to_string(git_dir.join("commondir")) {
+            Ok(common_dir) => git_dir.join(common_dir.trim()),
+            Err(_) => git_dir.clone(),
+        };
+
+        let mut packs = Vec::new();
+        if let Ok(entries) = fs::read_dir(common_dir.join("objects/pack")) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.extension().and_then(|ext| ext.to_str()) == Some("idx") {
+                    packs.push(Pack::open(&path)?);
+                }
+            }
+        }
+
+        Ok(Repository {
+            work_dir: work_dir.to_path_buf(),
+            git_dir,
+            common_dir,
+            packs,
+        })
+    }
+
+    /// Resolves a (symbolic) ref to a commit id
+    fn resolve_ref(&self, ref_name: &str) -> Result<String, String> {
+        let packed_refs = self.packed_refs();
+        let mut ref_name = ref_name.to_string();
+
+        for _ in 0..MAX_DEPTH {
+            let loose_ref = match ref_name.as_str() {
+                "HEAD" => fs::read_to_string(self.git_dir.join("HEAD")),
+                _ => fs::read_to_string(self.common_dir.join(&ref_name)),
+            };
+            match loose_ref {
+                Ok(content) => match content.trim().strip_prefix("ref: ") {
+                    Some(target) => ref_name = target.to_string(),
+                    None => return Ok(content.trim().to_string()),
+                },
+                Err(_) => {
+                    return match packed_refs.get(&ref_name) {
+                        Some((id, _peeled)) => Ok(id.clone()),
+                        // unborn branch, e.g. in a new repository without commits
+                        None => Err(format!(
+                            "[Get Git Revision] Could not resolve ref '{ref_name}'."
+                        )),
+                    };
+                }
+            }
+        }
+
+        Err(format!(
+            "[Get Git Revision] Too many nested refs for '{ref_name}'."
+        ))
+    }
+
+    /// Reads `packed-refs`: ref name -> (object id, peeled commit id of annotated tags)
+    fn packed_refs(&self) -> HashMap<String, (String, Option<String>)> {
+        let mut refs: HashMap<String, (String, Option<String>)> = HashMap::new();
+        let content = fs::read_to_string(self.common_dir.join("packed-refs")).unwrap_or_default();
+
+        let mut last_ref: Option<String> = None;
+        for line in content.lines() {
+            if line.starts_with('#') {
+                continue;
+            }
+            if let Some(peeled) = line.strip_prefix('^') {
+                if let Some(entry) = last_ref.as_ref().and_then(|name| refs.get_mut(name)) {
+                    entry.1 = Some(peeled.trim().to_string());
+                }
+                continue;
+            }
+            if let Some((id, name)) = line.split_once(' ') {
+                refs.insert(name.trim().to_string(), (id.to_string(), None));
+                last_ref = Some(name.trim().to_string());
+            }
+        }
+
+        refs
+    }
+
+    /// Returns all tags by the commit id they point to
+    fn tags(&self) -> Result<HashMap<String, Vec<String>>, String> {
+        let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+
+        for (name, (id, peeled)) in self.packed_refs() {
+            if let Some(tag) = name.strip_prefix("refs/tags/") {
+                let commit = match peeled {
+                    Some(peeled) => peeled,
+                    None => self.peel(&id)?,
+                };
+                tags.entry(commit).or_default().push(tag.to_string());
+            }
+        }
+
+        // loose tags override packed ones
+        let tags_dir = self.common_dir.join("refs/tags");
+        let mut dirs = vec![tags_dir.clone()];
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+                let path = entry.path();
+                if path.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+
+                let name = path
+                    .strip_prefix(&tags_dir)
+                    .map(|name| name.to_string_lossy().replace('\\', "/"))
+                    .unwrap_or_default();
+                if let Ok(id) = fs::read_to_string(&path) {
+                    for names in tags.values_mut() {
+                        names.retain(|tag| tag != &name);
+                    }
+                    let commit = self.peel(id.trim())?;
+                    tags.entry(commit).or_default().push(name);
+                }
+            }
+        }
+
+        Ok(tags)
+    }
+
+    /// Follows annotated tags to the tagged commit
+    fn peel(&self, id: &str) -> Result<String, String> {
+        let mut id = id.to_string();
+        for _ in 0..MAX_DEPTH {
+            match self.read_object(&id)? {
+                Some((TAG, data)) => match object_header(&data, "object") {
+                    Some(target) => id = target,
+                    None => return Ok(id),
+                },
+                _ => return Ok(id),
+            }
+        }
+        Ok(id)
+    }
+
+    /// Walks the commit history and returns the nearest tag and its distance in commits
+    fn nearest_tag(&self, commit: &str) -> Result<Option<(String, u32)>, String> {
+        let tags = self.tags()?;
+        if tags.is_empty() {
+            return Ok(None);
+        }
+
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::from([(commit.to_string(), 0)]);
+        while let Some((id, distance)) = queue.pop_front() {
+            if visited.len() >= MAX_TAG_DISTANCE {
+                break;
+            }
+            if !visited.insert(id.clone()) {
+                continue;
+            }
+
+            if let Some(names) = tags.get(&id) {
+                // use the highest tag name if a commit has multiple tags
+                return Ok(names.iter().max().map(|tag| (tag.clone(), distance)));
+            }
+
+            if let Some((COMMIT, data)) = self.read_object(&id)? {
+                for line in String::from_utf8_lossy(&data).lines() {
+                    match line.strip_prefix("parent ") {
+                        Some(parent) => queue.push_back((parent.to_string(), distance + 1)),
+                        None if line.is_empty() => break,
+                        None => {}
+                    }
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Reads a loose or packed object, returns its type and content
+    fn read_object(&self, id: &str) -> Result<Option<(u8, Vec<u8>)>, String> {
+        if id.len() != 40 {
+            return Ok(None);
+        }
+
+        let loose_path = self
+            .common_dir
+            .join("objects")
+            .join(&id[..2])
+            .join(&id[2..]);
+        if let Ok(file) = fs::File::open(loose_path) {
+            let mut data = Vec::new();
+            ZlibDecoder::new(file)
+                .read_to_end(&mut data)
+                .map_err(|err| format!("[Get Git Revision] Could not read object {id}: {err}"))?;
+
+            // loose objects start with a "<type> <size>\0" header
+            let header_end = data.iter().position(|b| *b == 0).unwrap_or(0);
+            let kind = match data
+                .get(..header_end)
+                .and_then(|h| h.split(|b| *b == b' ').next())
+            {
+                Some(b"commit") => COMMIT,
+                Some(b"tag") => TAG,
+                Some(b"tree") => TREE,
+                _ => BLOB,
+            };
+            return Ok(Some((kind, data.split_off(header_end + 1))));
+        }
+
+        let id = decode_hex(id)?;
+        for pack in &self.packs {
+            if let Some(offset) = pack.find(&id) {
+                return pack.read_object(self, offset, 0).map(Some);
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Compares the index with the files of the commit (staged changes) and with the working tree,
+    /// untracked files are ignored
+    fn is_dirty(&self, commit: &str) -> Result<bool, String> {
+        let index = match fs::read(self.git_dir.join("index")) {
+            Ok(index) => index,
+            // no index yet, e.g. in a bare checkout
+            Err(_) => return Ok(false),
+        };
+        if index.get(..4) != Some(&b"DIRC"[..]) {
+            return Err("[Get Git Revision] Invalid index file.".to_string());
+        }
+        let version = read_u32(&index, 4)?;
+        if version != 2 && version != 3 {
+            return Err(format!(
+                "[Get Git Revision] Index version {version} is not supported."
+            ));
+        }
+
+        // files of the commit which are not in the index are staged deletions
+        let mut commit_files = self.commit_files(commit)?;
+        let mut staged = false;
+
+        let mut offset = 12;
+        for _ in 0..read_u32(&index, 8)? {
+            let mtime = (
+                read_u32(&index, offset + 8)?,
+                read_u32(&index, offset + 12)?,
+            );
+            let mode = read_u32(&index, offset + 24)?;
+            let size = read_u32(&index, offset + 36)?;
+            let id = index
+                .get(offset + 40..offset + 60)
+                .ok_or_else(|| "[Get Git Revision] Invalid index file.".to_string())?;
+            let flags = read_u16(&index, offset + 60)?;
+
+            // extended flags (skip-worktree, intent-to-add) in version 3
+            let mut path_start = offset + 62;
+            let mut skip = flags & 0x8000 != 0; // assume-valid
+            if flags & 0x4000 != 0 {
+                skip |= read_u16(&index, path_start)? & 0x4000 != 0; // skip-worktree
+                path_start += 2;
+            }
+            let path_end = index
+                .get(path_start..)
+                .and_then(|rest| rest.iter().position(|b| *b == 0))
+                .map(|end| path_start + end)
+                .ok_or_else(|| "[Get Git Revision] Invalid index file.".to_string())?;
+            let path = String::from_utf8_lossy(&index[path_start..path_end]).to_string();
+
+            // entries are padded with 1-8 nul bytes to a multiple of 8
+            offset += (path_end - offset + 8) & !7;
+
+            // unmerged entries (stage 1-3) and entries which differ from the commit are staged
+            let committed = commit_files.remove(&path);
+            if flags & 0x3000 != 0 || committed.as_ref() != Some(&(mode, id.to_vec())) {
+                staged = true;
+            }
+
+            // skip submodules (gitlinks) and symlinks
+            if skip || mode & 0o170000 == 0o160000 || mode & 0o170000 == 0o120000 {
+                continue;
+            }
+            if self.is_modified(&path, mtime, size, id)? {
+                return Ok(true);
+            }
+        }
+
+        Ok(staged || !commit_files.is_empty())
+    }
+
+    /// Mode and blob id of all files of a commit by path
+    fn commit_files(&self, commit: &str) -> Result<HashMap<String, (u32, Vec<u8>)>, String> {
+        let tree = match self.read_object(commit)? {
+            Some((COMMIT, data)) => object_header(&data, "tree")
+                .ok_or_else(|| "[Get Git Revision] Invalid commit object.".to_string())?,
+            _ => return Err(format!("[Get Git Revision] Commit {commit} not found.")),
+        };
+
+        let mut files = HashMap::new();
+        let mut trees = vec![(tree, String::new())];
+        while let Some((id, prefix)) = trees.pop() {
+            let data = match self.read_object(&id)? {
+                Some((TREE, data)) => data,
+                _ => return Err(format!("[Get Git Revision] Tree {id} not found.")),
+            };
+
+            // entries are `<octal mode> <name>\0<20 byte id>`
+            let invalid = || "[Get Git Revision] Invalid tree object.".to_string();
+            let mut rest = data.as_slice();
+            while !rest.is_empty() {
+                let space = rest.iter().position(|b| *b == b' ').ok_or_else(invalid)?;
+                let nul = rest.iter().position(|b| *b == 0).ok_or_else(invalid)?;
+                let entry_id = rest.get(nul + 1..nul + 21).ok_or_else(invalid)?;
+                let mode = std::str::from_utf8(&rest[..space])
+                    .ok()
+                    .and_then(|mode| u32::from_str_radix(mode, 8).ok())
+                    .ok_or_else(invalid)?;
+                let path = format!("{prefix}{}", String::from_utf8_lossy(&rest[space + 1..nul]));
+
+                match mode {
+                    0o40000 => trees.push((encode_hex(entry_id), format!("{path}/"))),
+                    _ => {
+                        files.insert(path, (mode, entry_id.to_vec()));
+                    }
+                }
+                rest = &rest[nul + 21..];
+            }
+        }
+
+        Ok(files)
+    }
+
+    fn is_modified(
+        &self,
+        path: &str,
+        index_mtime: (u32, u32),
+        index_size: u32,
+        index_id: &[u8],
+    ) -> Result<bool, String> {
+        let file_path = self.work_dir.join(path);
+        let metadata = match fs::metadata(&file_path) {
+            Ok(metadata) => metadata,
+            // deleted file
+            Err(_) => return Ok(true),
+        };
+        if metadata.len() as u32 != index_size {
+            return Ok(true);
+        }
+
+        // unchanged stat data, the content does not need to be compared
+        if let Ok(modified) = metadata.modified() {
+            if let Ok(modified) = modified.duration_since(std::time::UNIX_EPOCH) {
+                if (modified.as_secs() as u32, modified.subsec_nanos()) == index_mtime {
+                    return Ok(false);
+                }
+            }
+        }
+
+        let content = fs::read(&file_path)
+            .map_err(|err| format!("[Get Git Revision] Could not read '{path}': {err}"))?;
+        if blob_id(&content) == index_id {
+            return Ok(false);
+        }
+
+        // files can be checked out with CRLF line endings (core.autocrlf)
+        let normalized = String::from_utf8_lossy(&content).replace("\r\n", "\n");
+        Ok(normalized.as_bytes() != content.as_slice()
+            && blob_id(normalized.as_bytes()) != index_id)
+    }
+}
+
+const COMMIT: u8 = 1;
+const TREE: u8 = 2;
+const BLOB: u8 = 3;
+const TAG: u8 = 4;
+const OFS_DELTA: u8 = 6;
+const REF_DELTA: u8 = 7;
+
+impl Pack {
+    fn open(idx_path: &Path) -> Result<Self, String> {
+        let idx = fs::read(idx_path)
+            .map_err(|err| format!("[Get Git Revision] Could not read pack index: {err}"))?;
+        if idx.get(..8) != Some(&[0xff, b't', b'O', b'c', 0, 0, 0, 2][..]) {
+            return Err("[Get Git Revision] Pack index version is not supported.".to_string());
+        }
+
+        let count = read_u32(&idx, 8 + 255 * 4)? as usize;
+        let ids_start = 8 + 256 * 4;
+        let offsets_start = ids_start + count * 24;
+        let large_offsets_start = offsets_start + count * 4;
+
+        let ids = idx
+            .get(ids_start..ids_start + count * 20)
+            .ok_or_else(|| "[Get Git Revision] Invalid pack index.".to_string())?
+            .to_vec();
+        let mut offsets = Vec::with_capacity(count);
+        for i in 0..count {
+            let offset = read_u32(&idx, offsets_start + i * 4)?;
+            // offsets with msb set point into the 64 bit offset table
+            offsets.push(match offset & 0x8000_0000 {
+                0 => offset as u64,
+                _ => {
+                    let position = large_offsets_start + (offset & 0x7FFF_FFFF) as usize * 8;
+                    ((read_u32(&idx, position)? as u64) << 32)
+                        | read_u32(&idx, position + 4)? as u64
+                }
+            });
+        }
+
+        Ok(Pack {
+            pack_path: idx_path.with_extension("pack"),
+            ids,
+            offsets,
+        })
+    }
+
+    /// Returns the pack offset of an object
+    fn find(&self, id: &[u8]) -> Option<u64> {
+        let count = self.offsets.len();
+        let (mut low, mut high) = (0, count);
+        while low < high {
+            let middle = (low + high) / 2;
+            match self.ids[middle * 20..middle * 20 + 20].cmp(id) {
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+                std::cmp::Ordering::Equal => return Some(self.offsets[middle]),
+            }
+        }
+        None
+    }
+
+    fn read_object(
+        &self,
+        repo: &Repository,
+        offset: u64,
+        depth: usize,
+    ) -> Result<(u8, Vec<u8>), String> {
+        if depth > MAX_DEPTH {
+            return Err("[Get Git Revision] Too many nested deltas.".to_string());
+        }
+        let error = |err: std::io::Error| format!("[Get Git Revision] Could not read pack: {err}");
+
+        let mut file = fs::File::open(&self.pack_path).map_err(error)?;
+        file.seek(SeekFrom::Start(offset)).map_err(error)?;
+        let mut reader = BufReader::new(file);
+
+        // object header: type and size as variable length integer
+        let mut byte = read_byte(&mut reader).map_err(error)?;
+        let kind = (byte >> 4) & 0x7;
+        while byte & 0x80 != 0 {
+            byte = read_byte(&mut reader).map_err(error)?;
+        }
+
+        let base =
+            match kind {
+                OFS_DELTA => {
+                    let invalid = || "[Get Git Revision] Invalid delta base offset.".to_string();
+                    let mut byte = read_byte(&mut reader).map_err(error)?;
+                    let mut relative = (byte & 0x7f) as u64;
+                    while byte & 0x80 != 0 {
+                        byte = read_byte(&mut reader).map_err(error)?;
+                        relative = relative
+                            .checked_add(1)
+                            .and_then(|relative| relative.checked_mul(0x80))
+                            .ok_or_else(invalid)?
+                            | (byte & 0x7f) as u64;
+                    }
+                    // the base object is stored before the delta in the same pack
+                    let base_offset = offset
+                        .checked_sub(relative)
+                        .filter(|base_offset| *base_offset < offset)
+                        .ok_or_else(invalid)?;
+                    Some(self.read_object(repo, base_offset, depth + 1)?)
+                }
+                REF_DELTA => {
+                    let mut base_id = [0u8; 20];
+                    reader.read_exact(&mut base_id).map_err(error)?;
+                    let base_id = encode_hex(&base_id);
+                    Some(repo.read_object(&base_id)?.ok_or_else(|| {
+                        format!("[Get Git Revision] Delta base {base_id} not found.")
+                    })?)
+                }
+                _ => None,
+            };
+
+        let mut data = Vec::new();
+        ZlibDecoder::new(reader)
+            .read_to_end(&mut data)
+            .map_err(error)?;
+
+        match base {
+            Some((base_kind, base_data)) => Ok((base_kind, apply_delta(&base_data, &data)?)),
+            None => Ok((kind, data)),
+        }
+    }
+}
+
+/// Applies a git delta (copy and insert instructions) to a base object
+fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>, String> {
+    let invalid = || "[Get Git Revision] Invalid delta.".to_string();
+    let mut position = 0;
+    let read_size = |position: &mut usize| -> Result<usize, String> {
+        let (mut size, mut shift) = (0, 0);
+        loop {
+            let byte = *delta.get(*position).ok_or_else(invalid)?;
+            *position += 1;
+            if shift >= usize::BITS {
+                return Err(invalid());
+            }
+            size |= ((byte & 0x7f) as usize) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Ok(size);
+            }
+        }
+    };
+    let _base_size = read_size(&mut position)?;
+    let result_size = read_size(&mut position)?;
+
+    // the result size is read from the pack, it is not trusted for the allocation
+    let mut result = Vec::with_capacity(result_size.min(base.len() + delta.len()));
+    while position < delta.len() {
+        let instruction = delta[position];
+        position += 1;
+
+        if instruction & 0x80 != 0 {
+            // copy from base: offset and size bytes are only present if their bit is set
+            let mut values = [0usize; 2];
+            for (bit, shift) in (0..7).map(|bit| (bit, (bit % 4) * 8)) {
+                if instruction & (1 << bit) != 0 {
+                    let byte = *delta.get(position).ok_or_else(invalid)? as usize;
+                    values[bit / 4] |= byte << shift;
+                    position += 1;
+                }
+            }
+            let (copy_offset, copy_size) = match values {
+                [offset, 0] => (offset, 0x10000),
+                [offset, size] => (offset, size),
+            };
+            result.extend_from_slice(
+                base.get(copy_offset..copy_offset + copy_size)
+                    .ok_or_else(invalid)?,
+            );
+        } else if instruction != 0 {
+            // insert new data
+            let size = instruction as usize;
+            result.extend_from_slice(delta.get(position..position + size).ok_or_else(invalid)?);
+            position += size;
+        } else {
+            return Err(invalid());
+        }
+    }
+
+    Ok(result)
+}
+
+/// Returns the value of a header line (e.g. `object <id>` of an annotated tag)
+fn object_header(data: &[u8], name: &str) -> Option<String> {
+    String::from_utf8_lossy(data)
+        .lines()
+        .take_while(|line| !line.is_empty())
+        .find_map(|line| {
+            line.strip_prefix(&format!("{name} "))
+                .map(|v| v.to_string())
+        })
+}
+
+/// Calculates the object id of a file content
+fn blob_id(content: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha1::new();
+    hasher.update(format!("blob {}\0", content.len()));
+    hasher.update(content);
+    hasher.finalize().to_vec()
+}
+
+fn decode_hex(id: &str) -> Result<Vec<u8>, String> {
+    (0..id.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(id.get(i..i + 2).unwrap_or_default(), 16)
+                .map_err(|_| format!("[Get Git Revision] Invalid object id '{id}'."))
+        })
+        .collect()
+}
+
+fn encode_hex(id: &[u8]) -> String {
+    id.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn read_byte<R: Read>(reader: &mut R) -> std::io::Result<u8> {
+    let mut byte = [0u8; 1];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, String> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+        .ok_or_else(|| "[Get Git Revision] Unexpected end of file.".to_string())
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, String> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or_else(|| "[Get Git Revision] Unexpected end of file.".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::ZlibEncoder, Compression};
+    use std::io::Write;
+
+    /// Minimal git directory which is written object by object
+    struct Fixture {
+        dir: PathBuf,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("git_reader_{}", Uuid::new_v4()));
+            fs::create_dir_all(dir.join(".git/refs/heads")).unwrap();
+            fs::create_dir_all(dir.join(".git/refs/tags")).unwrap();
+            fs::create_dir_all(dir.join(".git/objects/pack")).unwrap();
+            Fixture { dir }
+        }
+
+        fn path(&self) -> String {
+            self.dir.to_string_lossy().to_string()
+        }
+
+        fn write(&self, name: &str, content: &[u8]) {
+            fs::write(self.dir.join(".git").join(name), content).unwrap();
+        }
+
+        fn loose(&self, kind: &str, content: &[u8]) -> String {
+            let id = object_id(kind, content);
+            let mut data = format!("{kind} {}\0", content.len()).into_bytes();
+            data.extend_from_slice(content);
+
+            let dir = self.dir.join(".git/objects").join(&id[..2]);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join(&id[2..]), zlib(&data)).unwrap();
+            id
+        }
+
+        /// Writes a pack with its index
+        fn pack(&self, entries: &[(String, u8, Vec<u8>)]) {
+            let mut pack = b"PACK".to_vec();
+            pack.extend_from_slice(&2u32.to_be_bytes());
+            pack.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+
+            let mut offsets = Vec::new();
+            for (_id, kind, data) in entries {
+                offsets.push(pack.len() as u64);
+                pack.extend(entry(*kind, data));
+            }
+            pack.extend_from_slice(&[0; 20]);
+
+            let mut sorted = entries
+                .iter()
+                .zip(&offsets)
+                .map(|((id, _, _), offset)| (decode_hex(id).unwrap(), *offset))
+                .collect::<Vec<_>>();
+            sorted.sort();
+
+            let mut idx = vec![0xff, b't', b'O', b'c', 0, 0, 0, 2];
+            for byte in 0..=255u8 {
+                let count = sorted.iter().filter(|(id, _)| id[0] <= byte).count();
+                idx.extend_from_slice(&(count as u32).to_be_bytes());
+            }
+            for (id, _) in &sorted {
+                idx.extend_from_slice(id);
+            }
+            idx.extend(sorted.iter().flat_map(|_| [0u8; 4])); // crc32
+            for (_, offset) in &sorted {
+                idx.extend_from_slice(&(*offset as u32).to_be_bytes());
+            }
+            idx.extend_from_slice(&[0; 40]);
+
+            self.write("objects/pack/pack-test.pack", &pack);
+            self.write("objects/pack/pack-test.idx", &idx);
+        }
+
+        /// Writes the files to the working tree and adds them to the index (version 2)
+        fn index(&self, files: &[(&str, &[u8])]) {
+            let mut index = b"DIRC".to_vec();
+            index.extend_from_slice(&2u32.to_be_bytes());
+            index.extend_from_slice(&(files.len() as u32).to_be_bytes());
+            for (name, content) in files {
+                let path = self.dir.join(name);
+                fs::write(&path, content).unwrap();
+                let modified = fs::metadata(&path)
+                    .unwrap()
+                    .modified()
+                    .unwrap()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap();
+
+                let start = index.len();
+                index.extend_from_slice(&[0; 8]); // ctime
+                index.extend_from_slice(&(modified.as_secs() as u32).to_be_bytes());
+                index.extend_from_slice(&modified.subsec_nanos().to_be_bytes());
+                index.extend_from_slice(&[0; 8]); // dev, ino
+                index.extend_from_slice(&0o100644u32.to_be_bytes());
+                index.extend_from_slice(&[0; 8]); // uid, gid
+                index.extend_from_slice(&(content.len() as u32).to_be_bytes());
+                index.extend(blob_id(content));
+                index.extend_from_slice(&(name.len() as u16).to_be_bytes());
+                index.extend_from_slice(name.as_bytes());
+                index.extend(std::iter::repeat(0).take(8 - (index.len() - start) % 8));
+            }
+            self.write("index", &index);
+        }
+
+        /// Writes a tree of files (name and blob content) and a commit of it
+        fn commit_files(&self, files: &[(&str, &[u8])], message: &str) -> String {
+            let mut tree = Vec::new();
+            for (name, content) in files {
+                tree.extend_from_slice(format!("100644 {name}\0").as_bytes());
+                tree.extend(blob_id(content));
+            }
+            let tree = self.loose("tree", &tree);
+            let commit = format!(
+                "tree {tree}\nauthor Test <test@example.com> 0 +0000\ncommitter Test <test@example.com> 0 +0000\n\n{message}\n"
+            );
+            self.loose("commit", commit.as_bytes())
+        }
+
+        fn repository(&self) -> Repository {
+            Repository::open(&self.dir).unwrap()
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn zlib(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn object_id(kind: &str, content: &[u8]) -> String {
+        let mut hasher = Sha1::new();
+        hasher.update(format!("{kind} {}\0", content.len()));
+        hasher.update(content);
+        hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+
+    /// Pack entry: type and size header followed by the zlib data (delta entries include their
+    /// base reference in `data`, before the delta instructions)
+    fn entry(kind: u8, data: &[u8]) -> Vec<u8> {
+        let (base, delta) = match kind {
+            OFS_DELTA => {
+                let end = data.iter().position(|b| b & 0x80 == 0).unwrap() + 1;
+                data.split_at(end)
+            }
+            REF_DELTA => data.split_at(20),
+            _ => data.split_at(0),
+        };
+
+        let mut size = delta.len();
+        let mut header = vec![(kind << 4) | (size & 0x0f) as u8];
+        size >>= 4;
+        while size > 0 {
+            *header.last_mut().unwrap() |= 0x80;
+            header.push((size & 0x7f) as u8);
+            size >>= 7;
+        }
+        header.extend_from_slice(base);
+        header.extend(zlib(delta));
+        header
+    }
+
+    /// Base reference of an offset delta (distance to the base entry)
+    fn ofs_base(mut distance: u64) -> Vec<u8> {
+        let mut bytes = vec![(distance & 0x7f) as u8];
+        distance >>= 7;
+        while distance > 0 {
+            distance -= 1;
+            bytes.insert(0, 0x80 | (distance & 0x7f) as u8);
+            distance >>= 7;
+        }
+        bytes
+    }
+
+    /// Delta which copies the whole base and appends a suffix
+    fn append_delta(base: &[u8], suffix: &[u8]) -> Vec<u8> {
+        let mut delta = vec![base.len() as u8, (base.len() + suffix.len()) as u8];
+        delta.extend_from_slice(&[0x80 | 0x10, base.len() as u8]);
+        delta.push(suffix.len() as u8);
+        delta.extend_from_slice(suffix);
+        delta
+    }
+
+    fn commit(parent: Option<&str>, message: &str) -> Vec<u8> {
+        let parent = parent
+            .map(|parent| format!("parent {parent}\n"))
+            .unwrap_or_default();
+        format!(
+            "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n{parent}author Test <test@example.com> 0 +0000\ncommitter Test <test@example.com> 0 +0000\n\n{message}\n"
+        )
+        .into_bytes()
+    }
+
+    #[test]
+    fn reads_loose_objects_and_branch() {
+        let fixture = Fixture::new();
+        let id = fixture.loose("commit", &commit(None, "initial"));
+        fixture.write("HEAD", b"ref: refs/heads/main\n");
+        fixture.write("refs/heads/main", format!("{id}\n").as_bytes());
+
+        let revision = get_git_revision(&fixture.path()).unwrap();
+        assert_eq!(revision.commit, id);
+        assert_eq!(revision.branch, "main");
+        assert_eq!(revision.tag, "");
+        assert!(!revision.dirty);
+        assert_eq!(format_version(&revision), id[..7]);
+    }
+
+    #[test]
+    fn reads_detached_head() {
+        let fixture = Fixture::new();
+        let id = fixture.loose("commit", &commit(None, "initial"));
+        fixture.write("HEAD", format!("{id}\n").as_bytes());
+
+        let revision = get_git_revision(&fixture.path()).unwrap();
+        assert_eq!(revision.commit, id);
+        assert_eq!(revision.branch, "");
+    }
+
+    #[test]
+    fn reads_packed_refs_and_tag_distance() {
+        let fixture = Fixture::new();
+        let first = fixture.loose("commit", &commit(None, "initial"));
+        let second = fixture.loose("commit", &commit(Some(&first), "second"));
+        let tag = fixture.loose(
+            "tag",
+            format!("object {first}\ntype commit\ntag v1.0.0\n\nrelease\n").as_bytes(),
+        );
+        fixture.write("HEAD", b"ref: refs/heads/main\n");
+        fixture.write(
+            "packed-refs",
+            format!(
+                "# pack-refs with: peeled fully-peeled sorted\n{second} refs/heads/main\n{tag} refs/tags/v1.0.0\n^{first}\n"
+            )
+            .as_bytes(),
+        );
+
+        let revision = get_git_revision(&fixture.path()).unwrap();
+        assert_eq!(revision.commit, second);
+        assert_eq!(revision.branch, "main");
+        assert_eq!(revision.tag, "v1.0.0");
+        assert_eq!(revision.distance, 1);
+        assert_eq!(
+            format_version(&revision),
+            format!("v1.0.0-1-g{}", &second[..7])
+        );
+    }
+
+    #[test]
+    fn reads_packed_objects() {
+        let fixture = Fixture::new();
+        let content = commit(None, "packed");
+        let id = object_id("commit", &content);
+        fixture.pack(&[(id.clone(), COMMIT, content.clone())]);
+        fixture.write("HEAD", format!("{id}\n").as_bytes());
+
+        let repo = fixture.repository();
+        assert_eq!(repo.read_object(&id).unwrap(), Some((COMMIT, content)));
+        assert_eq!(get_git_revision(&fixture.path()).unwrap().commit, id);
+    }
+
+    #[test]
+    fn reads_offset_deltas() {
+        let fixture = Fixture::new();
+        let base = b"base content\n".to_vec();
+        let target = b"base content\nappended\n".to_vec();
+        let base_id = object_id("blob", &base);
+        let target_id = object_id("blob", &target);
+
+        // the delta entry directly follows the base entry
+        let mut delta = ofs_base(entry(BLOB, &base).len() as u64);
+        delta.extend(append_delta(&base, b"appended\n"));
+        fixture.pack(&[
+            (base_id.clone(), BLOB, base.clone()),
+            (target_id.clone(), OFS_DELTA, delta),
+        ]);
+
+        let repo = fixture.repository();
+        assert_eq!(repo.read_object(&base_id).unwrap(), Some((BLOB, base)));
+        assert_eq!(repo.read_object(&target_id).unwrap(), Some((BLOB, target)));
+    }
+
+    #[test]
+    fn reads_ref_deltas() {
+        let fixture = Fixture::new();
+        let base = b"loose base\n".to_vec();
+        let target = b"loose base\nfrom pack\n".to_vec();
+        let base_id = fixture.loose("blob", &base);
+        let target_id = object_id("blob", &target);
+
+        let mut delta = decode_hex(&base_id).unwrap();
+        delta.extend(append_delta(&base, b"from pack\n"));
+        fixture.pack(&[(target_id.clone(), REF_DELTA, delta)]);
+
+        let repo = fixture.repository();
+        assert_eq!(repo.read_object(&target_id).unwrap(), Some((BLOB, target)));
+    }
+
+    #[test]
+    fn rejects_invalid_delta_base_offset() {
+        let fixture = Fixture::new();
+        let target_id = object_id("blob", b"broken");
+
+        // base offset points before the start of the pack
+        let mut delta = ofs_base(1 << 20);
+        delta.extend(append_delta(b"x", b"y"));
+        fixture.pack(&[(target_id.clone(), OFS_DELTA, delta)]);
+
+        let repo = fixture.repository();
+        assert!(repo.read_object(&target_id).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_deltas() {
+        // copy beyond the end of the base
+        assert!(apply_delta(b"abc", &[3, 10, 0x80 | 0x10, 10]).is_err());
+        // size without end
+        assert!(apply_delta(b"abc", &[0xff; 16]).is_err());
+        // reserved instruction
+        assert!(apply_delta(b"abc", &[3, 3, 0]).is_err());
+        assert_eq!(
+            apply_delta(b"abc", &append_delta(b"abc", b"d")).unwrap(),
+            b"abcd"
+        );
+    }
+
+    #[test]
+    fn rejects_missing_working_copy() {
+        let fixture = Fixture::new();
+        let path = fixture.dir.join("missing").to_string_lossy().to_string();
+        assert!(get_git_revision(&path).is_err());
+    }
+
+    #[test]
+    fn detects_staged_and_unstaged_changes() {
+        let fixture = Fixture::new();
+        let id = fixture.commit_files(&[("a.txt", b"one\n"), ("b.txt", b"two\n")], "initial");
+        fixture.write("HEAD", b"ref: refs/heads/main\n");
+        fixture.write("refs/heads/main", format!("{id}\n").as_bytes());
+
+        // clean working copy
+        fixture.index(&[("a.txt", b"one\n"), ("b.txt", b"two\n")]);
+        assert!(!get_git_revision(&fixture.path()).unwrap().dirty);
+
+        // unstaged change of the working tree
+        fs::write(fixture.dir.join("a.txt"), b"changed\n").unwrap();
+        assert!(get_git_revision(&fixture.path()).unwrap().dirty);
+
+        // staged change, the working tree matches the index
+        fixture.index(&[("a.txt", b"staged\n"), ("b.txt", b"two\n")]);
+        assert!(get_git_revision(&fixture.path()).unwrap().dirty);
+
+        // staged deletion
+        fixture.index(&[("a.txt", b"one\n")]);
+        assert!(get_git_revision(&fixture.path()).unwrap().dirty);
+    }
+}
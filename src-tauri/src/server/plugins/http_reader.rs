@@ -0,0 +1,45 @@
+use super::version_extractor::extract_version;
+use crate::server::router::files::VersionExtractor;
+use std::{collections::HashMap, time::Duration};
+
+/// Max number of followed redirects
+const MAX_REDIRECTS: u32 = 10;
+
+/// Gets a version from the response body of an http endpoint.
+///
+/// Supports `http://` and `https://` urls, redirects are followed.
+pub fn get_http_version(
+    url: &str,
+    method: &str,
+    headers: &HashMap<String, String>,
+    timeout_secs: u64,
+    extractor: &VersionExtractor,
+) -> Result<String, String> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(timeout_secs))
+        .redirects(MAX_REDIRECTS)
+        .build();
+
+    let method = match method.is_empty() {
+        true => "GET".to_string(),
+        false => method.to_uppercase(),
+    };
+    let mut request = agent.request(&method, url);
+    for (name, value) in headers {
+        request = request.set(name, value);
+    }
+
+    match request.call() {
+        Ok(response) => {
+            let body = response
+                .into_string()
+                .map_err(|err| format!("[Http Request] Could not read response: {err}"))?;
+            extract_version(&body, extractor)
+        }
+        Err(ureq::Error::Status(code, response)) => Err(format!(
+            "[Http Request] Endpoint responded with status {code} {}",
+            response.status_text()
+        )),
+        Err(err) => Err(format!("[Http Request] {err}")),
+    }
+}
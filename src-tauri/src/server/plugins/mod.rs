@@ -1,210 +1,842 @@
-use super::{router::settings::Broker, store::AppState};
-use crate::server::router::files::Files;
-use chrono::{self, SecondsFormat};
-use log::{error, info};
+use super::{
+    router::settings::Broker,
+    store::{self, AppState},
+};
+use crate::server::router::{
+    compliance::Compliance,
+    files::{CommandError, File, FileLifecycle, Files, VersionSource},
+    history::{VersionChange, VersionHistory, VersionHistoryEntry},
+    processes::ProcessInfo,
+    revisions::GitRevision,
+};
+use chrono::{self, DateTime, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use log::{error, info, warn};
 use microkv::MicroKV;
+use mqtt_outbox::Delivery;
+use scan_pool::{ScanAttempt, ScanJob, ScanPool};
 use serde_json::json;
 use std::{
-    path::Path,
-    sync::{Arc, RwLock},
+    collections::BTreeMap,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, SystemTime},
 };
-use tokio::sync::broadcast;
+use tokio::sync::broadcast::{self, error::RecvError};
+use uuid::Uuid;
 
+pub(crate) mod command_reader;
+pub(crate) mod compliance_checker;
 mod debouncer;
+mod deployment_tracker;
 mod file_version_reader;
 mod file_watcher;
+mod git_reader;
+mod http_reader;
 mod mqtt_client;
+mod mqtt_outbox;
+mod mqtt_tls;
+mod package_reader;
+mod poller;
+mod process_reader;
+mod registry_hive_reader;
+mod scan_pool;
+mod scheduler;
+mod version_compare;
+mod version_extractor;
 
 static DB_KEY: &str = "broker";
+static DB_KEY_HISTORY: &str = "history";
+
+/// Max number of version changes stored per file
+const MAX_HISTORY_ENTRIES: usize = 100;
+/// Max number of reads of a locked or partially written file per scan
+const MAX_SCAN_ATTEMPTS: u32 = 5;
+/// Delay before the first retry, doubled on each further attempt
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Current scan of each file, retries of older scans are dropped
+static SCAN_GENERATIONS: Mutex<BTreeMap<Uuid, u64>> = Mutex::new(BTreeMap::new());
+
+/// Running plugins, stopped on application shutdown
+pub struct Plugins {
+    mqtt_client: mqtt_client::MqttClient,
+}
 
-pub fn init(app_state: Arc<AppState>) {
+impl Plugins {
+    /// Sends the offline status and disconnects from the mqtt broker
+    pub async fn shutdown(&self) {
+        self.mqtt_client.shutdown().await;
+    }
+}
+
+pub fn init(app_state: Arc<AppState>) -> Plugins {
     // Instantiate shared channel
     let (tx, mut rx) = broadcast::channel::<String>(40);
     let tx_file_watcher = Arc::new(RwLock::new(tx.clone()));
 
     // init mqtt client
     let mut client = mqtt_client::MqttClient::init(&app_state);
+    let plugins = Plugins {
+        mqtt_client: client.clone(),
+    };
+
+    // init worker pool which runs all scans
+    let scan_pool = scan_pool::ScanPool::init(&app_state, &client);
 
     // init file listener
-    let mut file_watcher = file_watcher::FileWatcher::init(tx_file_watcher, &app_state, &client);
-
-    // check all enabled file versions on application start
-    let files = app_state
-        .db
-        .read()
-        .unwrap()
-        .get_unwrap::<Files>("files")
-        .unwrap();
-    for (_uuid, file) in files {
+    let mut file_watcher = file_watcher::FileWatcher::init(tx_file_watcher.clone(), &app_state);
+
+    // init poller for scheduled version sources and files without (reliable) file system events
+    let mut poller = poller::Poller::init(tx_file_watcher, &app_state);
+
+    // init scheduler for cron based rescans
+    let mut scheduler = scheduler::Scheduler::init(&app_state, &scan_pool);
+
+    // convert content hashes stored by older versions before comparing new ones
+    file_version_reader::migrate_legacy_hashes(&app_state.db);
+
+    // check all enabled file versions on application start (paths shared by multiple entries
+    // are scanned once)
+    let files = store::get::<Files>(&app_state.db.read().unwrap(), "files").unwrap();
+    for file in files.values() {
         // skip disabled file watchers
         if !&file.enabled {
             continue;
         }
-        handle_file_change(&file.path, &app_state.db, &mut client);
+        scan_pool.enqueue(ScanJob::path(&file.path, true));
     }
+    // entries of the last db change, used to detect deleted entries
+    let mut known_files = files;
+    let db = app_state.db.clone();
 
     // Create global listener - execute version and mqtt logic here
     tokio::spawn(async move {
-        while let Ok(path) = rx.recv().await {
+        let db_path = crate::server::store::FILE_DB_PATH;
+        let db_name = crate::server::store::FILE_DB_NAME;
+        let db_string = format!("{db_path}/{db_name}.kv");
+        loop {
+            let path = match rx.recv().await {
+                Ok(path) => path,
+                // changes got lost in a burst, reload the config and rescan all files
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("[Listener] Missed {skipped} changes, rescanning all files");
+                    rescan_files(&db, &scan_pool, "", false);
+                    db_string.clone()
+                }
+                Err(RecvError::Closed) => break,
+            };
+
             // handle local db changes
-            let db_path = crate::server::store::FILE_DB_PATH;
-            let db_name = crate::server::store::FILE_DB_NAME;
-            let db_string = format!("{db_path}/{db_name}.kv");
             if path == db_string {
                 // refresh watcher if file is new/deleted or path is changed
                 file_watcher.refresh();
+                poller.refresh();
+                scheduler.refresh();
+                scan_pool.refresh();
                 // update mqtt client on settings change (client only)
                 client.refresh();
+                // remove retained messages of deleted entries
+                clear_deleted_files(&db, &mut client, &mut known_files);
 
                 continue;
             }
 
-            // TODO: handle other file changes
-            handle_file_change(&path, &app_state.db, &mut client);
+            // scan the changed path on the worker pool
+            scan_pool.enqueue(ScanJob::path(&path, true));
         }
     });
+
+    plugins
 }
 
-/// Gets the new file version on file change and stores it to the local DB
-fn handle_file_change(
-    path: &String,
+/// Runs a queued scan: all enabled entries of the path or a single entry on retries
+fn run_scan_job(
     db: &Arc<RwLock<MicroKV>>,
     mqtt_client: &mut mqtt_client::MqttClient,
+    scan_pool: &ScanPool,
+    job: &ScanJob,
 ) {
-    if let Some(os_str) = Path::new(&path).extension() {
-        if let Some(extension) = os_str.to_str() {
-            // handle different file types
-            match extension {
-                "exe" | "dll" => {
-                    // get file version from file properties
-                    let file_version =
-                        file_version_reader::get_file_version_from_file_properties(&path);
-                    match file_version {
-                        Ok(version) => {
-                            update_file_version(db, mqtt_client, path.clone(), version.clone());
-                        }
-                        Err(err) => {
-                            error!(
-                                "Could not get file version from path '{}' due to: {err:?}",
-                                &path
-                            );
-                            update_file_error(db, path.clone(), err);
-                        }
-                    }
-                }
-                _ => {
-                    // get current time stamp and file hash - no file version available
-                    let hash = file_version_reader::get_file_meta_hash(&path);
-                    match hash {
-                        Ok(hash) => {
-                            update_file_version(db, mqtt_client, path.clone(), hash.clone());
-                        }
-                        Err(err) => {
-                            error!(
-                                "Could not get file version from path '{}' due to: {err:?}",
-                                &path
-                            );
-                            update_file_error(db, path.clone(), err);
-                        }
-                    }
-                }
+    let files = match store::get::<Files>(&db.read().unwrap(), "files") {
+        Ok(files) => files,
+        Err(err) => {
+            error!("Could not read file config from local DB: {err:?}");
+            return;
+        }
+    };
+
+    // multiple entries can share the same path with different version sources (e.g. registry hives)
+    for (uuid, file) in files {
+        // skip disabled entries and changes if path does not match
+        if !file.enabled || file.path.replace('\\', "/") != job.path {
+            continue;
+        }
+
+        match job.retry {
+            Some((retry_uuid, attempt)) if retry_uuid == uuid => scan_file(
+                db,
+                mqtt_client,
+                scan_pool,
+                &uuid,
+                &file,
+                job.publish_unchanged,
+                attempt,
+            ),
+            Some(_) => continue,
+            None if !job.group.is_empty() && file.group != job.group => continue,
+            None => check_file(
+                db,
+                mqtt_client,
+                scan_pool,
+                &uuid,
+                &file,
+                job.publish_unchanged,
+            ),
+        }
+    }
+}
+
+/// Queues rescans of all enabled files of a group (empty group for all files).
+///
+/// Unchanged versions only get published again if `force_publish` is set.
+fn rescan_files(db: &Arc<RwLock<MicroKV>>, scan_pool: &ScanPool, group: &str, force_publish: bool) {
+    let files = match store::get::<Files>(&db.read().unwrap(), "files") {
+        Ok(files) => files,
+        Err(err) => {
+            error!("Could not read file config from local DB: {err:?}");
+            return;
+        }
+    };
+
+    for file in files.into_values() {
+        if !file.enabled || (!group.is_empty() && file.group != group) {
+            continue;
+        }
+        scan_pool.enqueue(ScanJob {
+            group: group.to_string(),
+            ..ScanJob::path(&file.path, force_publish)
+        });
+    }
+}
+
+/// Reads the version of a single file entry and stores the result
+fn check_file(
+    db: &Arc<RwLock<MicroKV>>,
+    mqtt_client: &mut mqtt_client::MqttClient,
+    scan_pool: &ScanPool,
+    uuid: &Uuid,
+    file: &File,
+    publish_unchanged: bool,
+) {
+    // a new scan replaces pending retries of the previous one
+    let generation = {
+        let mut scans = SCAN_GENERATIONS.lock().unwrap();
+        let generation = scans.entry(*uuid).or_default();
+        *generation += 1;
+        *generation
+    };
+    let attempt = ScanAttempt {
+        generation,
+        attempt: 1,
+    };
+    scan_file(
+        db,
+        mqtt_client,
+        scan_pool,
+        uuid,
+        file,
+        publish_unchanged,
+        attempt,
+    );
+}
+
+/// Single scan attempt, locked or partially written local files get retried with exponential backoff
+fn scan_file(
+    db: &Arc<RwLock<MicroKV>>,
+    mqtt_client: &mut mqtt_client::MqttClient,
+    scan_pool: &ScanPool,
+    uuid: &Uuid,
+    file: &File,
+    publish_unchanged: bool,
+    attempt: ScanAttempt,
+) {
+    // retries of older scans are dropped
+    if SCAN_GENERATIONS.lock().unwrap().get(uuid) != Some(&attempt.generation) {
+        return;
+    }
+
+    // deleted local sources are tracked as missing instead of a read error (binaries of command
+    // sources can be resolved from the search path)
+    let is_local_file =
+        file.source.has_local_path() && !matches!(file.source, VersionSource::Command { .. });
+    if is_local_file && !std::path::Path::new(&file.path).exists() {
+        update_file_missing(db, mqtt_client, uuid);
+        return;
+    }
+
+    // local files are only stable if size and modification time did not change while reading,
+    // all file IO happens before the DB gets locked for writing the result
+    let state_before = get_file_state(&file.path);
+    // a failed content hash is retried like a failed read, so the last hash is never cleared
+    let (version, source_state) = get_version(file);
+    let result = version.and_then(|(version, warning)| {
+        Ok(ScanResult {
+            version,
+            warning,
+            hash: get_content_hash(file)?,
+            modified: is_modified_since_last_update(file),
+        })
+    });
+    let stable =
+        !is_local_file || (state_before.is_some() && state_before == get_file_state(&file.path));
+
+    let reason = match (result, stable) {
+        (Ok(result), true) => {
+            if let Some(warning) = &result.warning {
+                warn!("[{}] {warning}", &file.name);
+            }
+            update_file_version(
+                db,
+                mqtt_client,
+                uuid,
+                result,
+                source_state,
+                publish_unchanged,
+            );
+            return;
+        }
+        (Ok(_), false) => "File is still being written".to_string(),
+        (Err(err), _) => err,
+    };
+
+    if !is_local_file || attempt.attempt >= MAX_SCAN_ATTEMPTS {
+        error!(
+            "Could not get file version from path '{}' due to: {reason:?}",
+            &file.path
+        );
+        update_file_error(db, uuid, reason, source_state);
+        return;
+    }
+
+    let delay = RETRY_BASE_DELAY * 2u32.pow(attempt.attempt - 1);
+    warn!(
+        "[{}] Scan attempt {} of {MAX_SCAN_ATTEMPTS} failed, retry in {delay:?}: {reason}",
+        &file.name, attempt.attempt
+    );
+    update_file_state(
+        db,
+        uuid,
+        format!(
+            "Pending retry ({}/{MAX_SCAN_ATTEMPTS}): {reason}",
+            attempt.attempt
+        ),
+    );
+
+    // the retry gets queued after the delay, so it does not block a worker in the meantime
+    let retry = ScanJob {
+        retry: Some((
+            *uuid,
+            ScanAttempt {
+                attempt: attempt.attempt + 1,
+                ..attempt
+            },
+        )),
+        ..ScanJob::path(&file.path, publish_unchanged)
+    };
+    let scan_pool = scan_pool.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(delay).await;
+        scan_pool.enqueue(retry);
+    });
+}
+
+/// Result of a successful scan which gets written to the local DB at once
+struct ScanResult {
+    version: String,
+    /// warning which is stored as update state
+    warning: Option<String>,
+    hash: String,
+    /// source was modified since the last scan
+    modified: bool,
+}
+
+/// Version and optional warning read from a version source
+type SourceVersion = Result<(String, Option<String>), String>;
+
+/// Source specific details of a scan which get stored together with the scan result
+enum SourceState {
+    /// Running processes of process sources
+    Processes(Vec<ProcessInfo>),
+    /// Commit, branch and tag details of git sources
+    Git(GitRevision),
+    /// Typed error of command sources (none if the command succeeded)
+    Command(Option<CommandError>),
+}
+
+impl SourceState {
+    fn store(&self, db: &MicroKV, uuid: &Uuid, file: &mut File) {
+        match self {
+            SourceState::Processes(processes) => {
+                process_reader::update_process_state(db, uuid, &file.name, processes)
             }
+            SourceState::Git(revision) => git_reader::update_git_revision(db, uuid, revision),
+            SourceState::Command(error) => file.command_error = error.clone(),
         }
     }
 }
 
-/// Write the new file version to the local DB
+/// Returns the modification time and size of a file (`None` if it can not be read)
+fn get_file_state(path: &str) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Reads the current version of a file entry from its configured version source.
+///
+/// Returns the version and an optional warning which is stored as update state, process and git
+/// sources additionally return their details (process details also if the version read failed).
+fn get_version(file: &File) -> (SourceVersion, Option<SourceState>) {
+    let version = match &file.source {
+        VersionSource::File => {
+            file_version_reader::get_file_version(&file.path, &file.hash_algorithm)
+        }
+        VersionSource::RegistryHive {
+            key_path,
+            value_name,
+        } => registry_hive_reader::get_registry_value(&file.path, key_path, value_name),
+        // command sources additionally store the typed error of the command
+        VersionSource::Command {
+            args,
+            working_dir,
+            timeout_secs,
+            extractor,
+            ..
+        } => {
+            let version = command_reader::get_command_version(
+                &file.path,
+                args,
+                working_dir,
+                *timeout_secs,
+                extractor,
+            );
+            let error = version.as_ref().err().cloned();
+            return (
+                version
+                    .map(|version| (version, None))
+                    .map_err(|err| err.to_string()),
+                Some(SourceState::Command(error)),
+            );
+        }
+        VersionSource::Http {
+            method,
+            headers,
+            timeout_secs,
+            extractor,
+            ..
+        } => http_reader::get_http_version(&file.path, method, headers, *timeout_secs, extractor),
+        VersionSource::Package {
+            package_name,
+            format,
+        } => package_reader::get_package_version(&file.path, package_name, format),
+        // process sources additionally track the matching running processes
+        VersionSource::Process {
+            cmdline_pattern, ..
+        } => {
+            let processes = match process_reader::get_processes(&file.path, cmdline_pattern) {
+                Ok(processes) => processes,
+                Err(err) => return (Err(err), None),
+            };
+            let version = process_reader::get_process_version(&processes, &file.hash_algorithm);
+            return (version, Some(SourceState::Processes(processes)));
+        }
+        // git sources additionally store the commit, branch and tag details
+        VersionSource::Git { .. } => {
+            let revision = match git_reader::get_git_revision(&file.path) {
+                Ok(revision) => revision,
+                Err(err) => return (Err(err), None),
+            };
+            let version = git_reader::format_version(&revision);
+            return (Ok((version, None)), Some(SourceState::Git(revision)));
+        }
+    };
+
+    (version.map(|version| (version, None)), None)
+}
+
+/// Returns the content hash of sources which read a local file (empty for directories, urls and processes)
+fn get_content_hash(file: &File) -> Result<String, String> {
+    if !file.source.has_local_path() || !std::path::Path::new(&file.path).is_file() {
+        return Ok(String::new());
+    }
+
+    file_version_reader::get_file_meta_hash(&file.path, &file.hash_algorithm)
+        .map_err(|err| format!("Could not hash file content: {err}"))
+}
+
+/// Write the new file version to the local DB.
+///
+/// All results of a scan are written in one short critical section, the deployment and the mqtt
+/// message are handled after the lock is released.
 fn update_file_version(
     db: &Arc<RwLock<MicroKV>>,
     mqtt_client: &mut mqtt_client::MqttClient,
-    path: String,
-    version: String,
+    uuid: &Uuid,
+    result: ScanResult,
+    source_state: Option<SourceState>,
+    publish_unchanged: bool,
 ) {
+    let ScanResult {
+        version,
+        warning,
+        hash,
+        modified,
+    } = result;
+
     // Update file state with version
-    let lock = db.write().unwrap();
-    let mut files = lock.get_unwrap::<Files>("files").unwrap();
-    // update file version for all files with matching path
-    let files_iterator = &mut files;
-    for (_uuid, file) in files_iterator {
-        // skip changes if path does not match
-        if file.path.replace("\\", "/") != path.replace("\\", "/") {
-            continue;
+    let (file, previous_version, change, restored, broker) = {
+        let lock = db.write().unwrap();
+        let mut files = store::get::<Files>(&lock, "files").unwrap();
+        let file = match files.get_mut(uuid) {
+            Some(file) => file,
+            None => return,
+        };
+
+        // classify and record the change before the previous version gets replaced
+        let previous_version = file.last_version.clone();
+        let change = version_compare::classify_change(&previous_version, &version, modified);
+        if change != VersionChange::Unchanged {
+            add_history_entry(&lock, uuid, &previous_version, &version, change);
+        }
+        if let Some(source_state) = &source_state {
+            source_state.store(&lock, uuid, file);
+        }
+
+        // a missing file is marked as restored once, afterwards it is present again
+        let restored = file.lifecycle == FileLifecycle::Missing;
+        match restored {
+            true => {
+                info!(
+                    "[{}] File restored (missing since {})",
+                    &file.name, &file.missing_since_utc
+                );
+                file.lifecycle = FileLifecycle::Restored;
+            }
+            false => {
+                file.lifecycle = FileLifecycle::Present;
+                file.missing_since_utc = "".to_string();
+            }
         }
 
         file.last_version = version.clone();
+        file.last_hash = hash;
         file.last_update_utc = chrono::offset::Utc::now().to_string();
         file.update_state = "Success".to_string();
+        file.compliance = compliance_checker::evaluate(&file.policy, &version);
+        // warnings are stored with the version instead of a separate write
+        if let Some(warning) = warning {
+            file.update_state = warning;
+            file.compliance = Compliance::Unknown;
+        }
+        if file.compliance == Compliance::NonCompliant {
+            warn!(
+                "[{}] Version '{}' does not match the compliance policy",
+                &file.name, &version
+            );
+        }
+        let file = file.clone();
 
-        // only send mqtt message if broker is connected
-        let broker = lock.get_unwrap::<Broker>(DB_KEY);
-        match broker {
-            Ok(broker) => {
-                // log info about new file version
-                info!(
-                    "[{}] Got version change from file '{}' to version '{}'",
-                    &broker.device_id, &file.name, &version
-                );
+        // store data to local db
+        if let Err(err) = store::put(&lock, "files", &files) {
+            error!("Could not write new file version to local DB: {err:?}")
+        }
+
+        let broker = store::get::<Broker>(&lock, DB_KEY);
+        (file, previous_version, change, restored, broker)
+    };
+
+    // changes of multiple files in a short time (e.g. by one installer run) form a deployment
+    let deployment_id =
+        deployment_tracker::record_change(db, mqtt_client, &file, &previous_version, change);
+
+    let broker = match broker {
+        Ok(broker) => broker,
+        Err(err) => {
+            error!("Could not get broker data due to: {err:?}");
+            return;
+        }
+    };
+
+    // log info about new file version
+    info!(
+        "[{}] Got version change ({change:?}) from file '{}' to version '{}'",
+        &broker.device_id, &file.name, &version
+    );
+
+    if !publish_unchanged && !restored && change == VersionChange::Unchanged {
+        // unchanged versions of scheduled rescans are only published if forced
+        return;
+    }
+    if deployment_id.is_some() && deployment_tracker::summary_only(&db.read().unwrap()) {
+        // changes of a deployment are published with its summary
+        return;
+    }
+
+    // send mqtt message (queued while the broker is not connected)
+    let (device_id, device_group) = {
+        let config = mqtt_client.current_client_config.read().unwrap();
+        (config.device_id.clone(), config.device_group.clone())
+    };
+    let (qos, retain) = file.mqtt_options(&broker);
+
+    let mut message = mqtt_outbox::message(
+        &file.mqtt_topic,
+        json!({
+          "deviceId": device_id,
+          "timestamp": format!("{}", chrono::offset::Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)),
+          "group": device_group,
+          "change": change,
+          "previousVersion": previous_version,
+          "compliance": file.compliance,
+          "state": file.lifecycle,
+          "missingSince": file.missing_since_utc,
+          "deploymentId": deployment_id,
+          "measures": {
+            format!("{}", &file.name): &file.last_version,
+            format!("{}DataType", &file.name): "String",
+          },
+        }),
+        qos,
+        retain,
+    );
+    let change = serde_json::to_value(change)
+        .ok()
+        .and_then(|change| change.as_str().map(|change| change.to_string()))
+        .unwrap_or_default();
+    message
+        .properties
+        .insert("fileId".to_string(), file.id.to_string());
+    message.properties.insert("change".to_string(), change);
+
+    // the db is only locked for queueing the message in the outbox, publishing happens in a task
+    let delivery = mqtt_outbox::deliver(&db.write().unwrap(), mqtt_client, &broker, message);
+    match delivery {
+        Delivery::Sent => {}
+        Delivery::Queued => update_file_state(
+            db,
+            uuid,
+            "MQTT broker not connected, message queued".to_string(),
+        ),
+        Delivery::Dropped => {
+            update_file_state(db, uuid, "MQTT broker connection failed".to_string())
+        }
+    }
+}
+
+/// Marks a file as missing and publishes the missing state once
+fn update_file_missing(
+    db: &Arc<RwLock<MicroKV>>,
+    mqtt_client: &mut mqtt_client::MqttClient,
+    uuid: &Uuid,
+) {
+    let lock = db.write().unwrap();
+    let mut files = store::get::<Files>(&lock, "files").unwrap();
+    let file = match files.get_mut(uuid) {
+        Some(file) if file.lifecycle == FileLifecycle::WaitingForPath => {
+            // files which are waiting for their directory are missing once the directory exists
+            let parent_exists = std::path::Path::new(&file.path)
+                .parent()
+                .is_some_and(|parent| parent.is_dir());
+            match parent_exists {
+                true => file,
+                false => return,
+            }
+        }
+        Some(file) if file.lifecycle != FileLifecycle::Missing => file,
+        _ => return,
+    };
 
-                if broker.connected {
-                    // send mqtt message
-                    let device_id = mqtt_client
-                        .current_client_config
-                        .read()
-                        .unwrap()
-                        .device_id
-                        .clone();
-                    let device_group = mqtt_client
-                        .current_client_config
-                        .read()
-                        .unwrap()
-                        .device_group
-                        .clone();
-
-                    mqtt_client.publish(
-                        &file.mqtt_topic,
-                        json!({
-                          "deviceId": device_id,
-                          "timestamp": format!("{}", chrono::offset::Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)),
-                          "group": device_group,
-                          "measures": {
-                            format!("{}", &file.name): &file.last_version,
-                            format!("{}DataType", &file.name): "String",
-                          },
-                        }),
-                    );
-                } else {
+    let now = chrono::offset::Utc::now();
+    warn!("[{}] File '{}' is missing", &file.name, &file.path);
+    file.lifecycle = FileLifecycle::Missing;
+    file.missing_since_utc = now.to_string();
+    file.last_update_utc = now.to_string();
+    file.update_state = "File is missing".to_string();
+    file.compliance = Compliance::Unknown;
+
+    // send mqtt message (queued while the broker is not connected)
+    match store::get::<Broker>(&lock, DB_KEY) {
+        Ok(broker) => {
+            let (device_id, device_group) = {
+                let config = mqtt_client.current_client_config.read().unwrap();
+                (config.device_id.clone(), config.device_group.clone())
+            };
+            let (qos, retain) = file.mqtt_options(&broker);
+
+            let mut message = mqtt_outbox::message(
+                &file.mqtt_topic,
+                json!({
+                  "deviceId": device_id,
+                  "timestamp": now.to_rfc3339_opts(SecondsFormat::Millis, true),
+                  "group": device_group,
+                  "state": file.lifecycle,
+                  "missingSince": file.missing_since_utc,
+                  "previousVersion": file.last_version,
+                }),
+                qos,
+                retain,
+            );
+            message
+                .properties
+                .insert("fileId".to_string(), file.id.to_string());
+            message
+                .properties
+                .insert("change".to_string(), "missing".to_string());
+            match mqtt_outbox::deliver(&lock, mqtt_client, &broker, message) {
+                Delivery::Sent => {}
+                Delivery::Queued => {
+                    file.update_state = "File is missing, MQTT message queued".to_string()
+                }
+                Delivery::Dropped => {
                     file.update_state = "MQTT broker connection failed".to_string()
                 }
             }
-            Err(err) => error!("Could not get broker data due to: {err:?}"),
         }
+        Err(err) => error!("Could not get broker data due to: {err:?}"),
     }
 
     // store data to local db
-    if let Err(err) = lock.put("files", &files) {
-        error!("Could not write new file version to local DB: {err:?}")
+    if let Err(err) = store::put(&lock, "files", &files) {
+        error!("Could not write missing file state to local DB: {err:?}")
     }
 }
 
-/// Writes a new file error to the local DB
-fn update_file_error(db: &Arc<RwLock<MicroKV>>, path: String, error: String) {
-    // Update file state with version
+/// Clears the retained mqtt messages of deleted entries if the broker is configured to do so.
+///
+/// Topics which are still used by another entry are kept.
+fn clear_deleted_files(
+    db: &Arc<RwLock<MicroKV>>,
+    mqtt_client: &mut mqtt_client::MqttClient,
+    known_files: &mut Files,
+) {
     let lock = db.write().unwrap();
-    let mut files = lock.get_unwrap::<Files>("files").unwrap();
-    // update file version for all files with matching path
-    let files_iterator = &mut files;
-    for (_uuid, file) in files_iterator {
-        // skip changes if path does not match
-        if file.path.replace("\\", "/") != path.replace("\\", "/") {
+    let files = match store::get::<Files>(&lock, "files") {
+        Ok(files) => files,
+        Err(_) => return,
+    };
+    let known = std::mem::replace(known_files, files);
+    let broker = match store::get::<Broker>(&lock, DB_KEY) {
+        Ok(broker) if broker.clear_retained_on_delete => broker,
+        _ => return,
+    };
+
+    for (uuid, file) in known {
+        if known_files.contains_key(&uuid) || file.mqtt_topic.is_empty() {
             continue;
         }
+        let (qos, retain) = file.mqtt_options(&broker);
+        let topic_in_use = known_files
+            .values()
+            .any(|remaining| remaining.mqtt_topic == file.mqtt_topic);
+        if !retain || topic_in_use {
+            continue;
+        }
+        info!(
+            "[{}] Entry deleted, clearing retained message of topic '{}'",
+            &file.name, &file.mqtt_topic
+        );
+        // an empty retained message clears the topic, queued while the broker is not connected
+        let message = mqtt_outbox::message(&file.mqtt_topic, serde_json::Value::Null, qos, true);
+        if mqtt_outbox::deliver(&lock, mqtt_client, &broker, message) == Delivery::Dropped {
+            error!(
+                "[{}] Retained message of topic '{}' not cleared, MQTT broker is not connected",
+                &file.name, &file.mqtt_topic
+            );
+        }
+    }
+}
+
+/// Returns true if a local file source was modified after its last version check
+fn is_modified_since_last_update(file: &File) -> bool {
+    if !file.source.has_local_path() {
+        return false;
+    }
+    let last_update = match parse_utc_timestamp(&file.last_update_utc) {
+        Some(last_update) => last_update,
+        None => return false,
+    };
+
+    std::fs::metadata(&file.path)
+        .and_then(|metadata| metadata.modified())
+        .map(|modified| DateTime::<Utc>::from(modified) > last_update)
+        .unwrap_or(false)
+}
+
+/// Parses timestamps which are stored in the local DB (e.g. `2023-02-28 12:00:00.123 UTC`)
+fn parse_utc_timestamp(timestamp: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(timestamp.trim_end_matches(" UTC"), "%Y-%m-%d %H:%M:%S%.f")
+        .ok()
+        .map(|timestamp| Utc.from_utc_datetime(&timestamp))
+}
+
+/// Appends a version change to the history of a file
+fn add_history_entry(
+    db: &MicroKV,
+    uuid: &Uuid,
+    previous_version: &str,
+    version: &str,
+    change: VersionChange,
+) {
+    let mut history = store::get::<VersionHistory>(db, DB_KEY_HISTORY).unwrap_or_default();
+    let entries = history.entry(*uuid).or_default();
+    entries.push(VersionHistoryEntry {
+        version: version.to_string(),
+        previous_version: previous_version.to_string(),
+        change,
+        timestamp_utc: chrono::offset::Utc::now().to_string(),
+    });
+    if entries.len() > MAX_HISTORY_ENTRIES {
+        entries.drain(..entries.len() - MAX_HISTORY_ENTRIES);
+    }
 
+    if let Err(err) = store::put(db, DB_KEY_HISTORY, &history) {
+        error!("Could not write version history to local DB: {err:?}")
+    }
+}
+
+/// Writes the update state of a file to the local DB (e.g. a pending retry or a queued message,
+/// compliance of the last scan stays)
+fn update_file_state(db: &Arc<RwLock<MicroKV>>, uuid: &Uuid, state: String) {
+    let lock = db.write().unwrap();
+    let mut files = store::get::<Files>(&lock, "files").unwrap();
+    if let Some(file) = files.get_mut(uuid) {
+        file.update_state = state;
+    }
+
+    // store data to local db
+    if let Err(err) = store::put(&lock, "files", &files) {
+        error!("Could not write file state to local DB: {err:?}")
+    }
+}
+
+/// Writes a new file error (and the source details which were read anyway) to the local DB
+fn update_file_error(
+    db: &Arc<RwLock<MicroKV>>,
+    uuid: &Uuid,
+    error: String,
+    source_state: Option<SourceState>,
+) {
+    // Update file state with error
+    let lock = db.write().unwrap();
+    let mut files = store::get::<Files>(&lock, "files").unwrap();
+    if let Some(file) = files.get_mut(uuid) {
+        if let Some(source_state) = &source_state {
+            source_state.store(&lock, uuid, file);
+        }
         file.last_update_utc = chrono::offset::Utc::now().to_string();
-        file.update_state = error.clone();
+        file.update_state = error;
+        file.compliance = Compliance::Unknown;
     }
 
     // store data to local db
-    if let Err(err) = lock.put("files", &files) {
+    if let Err(err) = store::put(&lock, "files", &files) {
         error!("Could not write new file version to local DB: {err:?}")
     }
 }
@@ -1,27 +1,216 @@
+use http::{uri::Authority, HeaderName, HeaderValue, Request, Uri};
 use log::{debug, error, info, warn};
 use microkv::MicroKV;
+use rumqttc::v5::{
+    self,
+    mqttbytes::v5::{LastWillProperties, Packet, PublishProperties},
+};
 use rumqttc::{
-    AsyncClient, ConnectionError, Event, EventLoop, Incoming, MqttOptions, Outgoing, QoS, TlsError,
-    Transport,
+    AsyncClient, ConnectionError, Event, EventLoop, Incoming, LastWill, MqttOptions, Outgoing, QoS,
+    TlsError, Transport,
 };
-use rustls::{Certificate, ClientConfig, RootCertStore};
-use rustls_native_certs::load_native_certs;
+use serde_json::json;
+use std::collections::BTreeMap;
 use std::io::ErrorKind;
+use std::net::Ipv4Addr;
 use std::sync::{Arc, RwLock};
 use std::time::Duration;
+use sysinfo::{System, SystemExt};
+use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
 
-use crate::server::router::settings::Broker;
-use crate::server::store::AppState;
+use super::{
+    mqtt_outbox::{self, PublisherEvent},
+    mqtt_tls,
+};
+use crate::server::router::{
+    outbox::OutboxMessage,
+    settings::{Broker, MqttQos, MqttVersion},
+};
+use crate::server::store::{self, AppState};
 
 static DB_KEY: &str = "broker";
 
+/// Content type of all published MQTT 5 messages
+static CONTENT_TYPE: &str = "application/json";
+
+/// MQTT 3.1.1 or MQTT 5 client, depending on the configured protocol version
+#[derive(Clone)]
+pub enum Client {
+    V311(AsyncClient),
+    V5(v5::AsyncClient, Arc<MessageProperties>),
+}
+
+impl Client {
+    /// Publishes a message, MQTT 5 messages carry the message properties and the given user
+    /// properties
+    pub async fn publish(
+        &self,
+        topic: &str,
+        payload: String,
+        qos: MqttQos,
+        retain: bool,
+        user_properties: &BTreeMap<String, String>,
+    ) -> Result<(), String> {
+        self.publish_with(topic, payload, qos, retain, |properties| {
+            properties.publish(user_properties)
+        })
+        .await
+    }
+
+    /// Publishes a retained status message of this device, it does not expire like the last will
+    pub async fn publish_status(
+        &self,
+        topic: &str,
+        payload: &serde_json::Value,
+    ) -> Result<(), String> {
+        self.publish_with(
+            topic,
+            payload.to_string(),
+            MqttQos::AtLeastOnce,
+            true,
+            MessageProperties::status,
+        )
+        .await
+    }
+
+    async fn publish_with(
+        &self,
+        topic: &str,
+        payload: String,
+        qos: MqttQos,
+        retain: bool,
+        properties: impl FnOnce(&MessageProperties) -> PublishProperties,
+    ) -> Result<(), String> {
+        match self {
+            Client::V311(client) => client
+                .publish(topic, to_qos(qos), retain, payload)
+                .await
+                .map_err(|err| err.to_string()),
+            // empty messages (which clear retained messages) carry no properties
+            Client::V5(client, _) if payload.is_empty() => client
+                .publish(topic, to_qos_v5(qos), retain, payload)
+                .await
+                .map_err(|err| err.to_string()),
+            Client::V5(client, message_properties) => client
+                .publish_with_properties(
+                    topic,
+                    to_qos_v5(qos),
+                    retain,
+                    payload,
+                    properties(message_properties),
+                )
+                .await
+                .map_err(|err| err.to_string()),
+        }
+    }
+
+    /// Publishes a queued message
+    pub async fn publish_message(&self, message: &OutboxMessage) -> Result<(), String> {
+        // null payloads are sent as empty messages (e.g. to clear a retained message)
+        let payload = match &message.payload {
+            serde_json::Value::Null => String::new(),
+            payload => payload.to_string(),
+        };
+        self.publish(
+            &message.topic,
+            payload,
+            message.qos,
+            message.retain,
+            &message.properties,
+        )
+        .await
+    }
+
+    pub async fn disconnect(&self) -> Result<(), String> {
+        match self {
+            Client::V311(client) => client.disconnect().await.map_err(|err| err.to_string()),
+            Client::V5(client, _) => client.disconnect().await.map_err(|err| err.to_string()),
+        }
+    }
+
+    pub fn try_disconnect(&self) -> Result<(), String> {
+        match self {
+            Client::V311(client) => client.try_disconnect().map_err(|err| err.to_string()),
+            Client::V5(client, _) => client.try_disconnect().map_err(|err| err.to_string()),
+        }
+    }
+}
+
+/// Properties which are added to all MQTT 5 messages of this device
+pub struct MessageProperties {
+    device_id: String,
+    message_expiry_interval: Option<u32>,
+    response_topic: Option<String>,
+}
+
+impl MessageProperties {
+    fn new(broker: &Broker) -> Self {
+        let response_topic = broker
+            .v5
+            .response_topic
+            .replace("{device_id}", &broker.device_id)
+            .replace("{device_group}", &broker.device_group);
+
+        MessageProperties {
+            device_id: broker.device_id.clone(),
+            message_expiry_interval: Some(broker.v5.message_expiry_secs).filter(|secs| *secs > 0),
+            response_topic: Some(response_topic).filter(|topic| !topic.is_empty()),
+        }
+    }
+
+    /// User properties of a message, the device id comes first
+    fn user_properties(&self, user_properties: &BTreeMap<String, String>) -> Vec<(String, String)> {
+        let mut properties = vec![("deviceId".to_string(), self.device_id.clone())];
+        properties.extend(user_properties.clone());
+        properties
+    }
+
+    fn publish(&self, user_properties: &BTreeMap<String, String>) -> PublishProperties {
+        PublishProperties {
+            payload_format_indicator: Some(1), // UTF-8 payload
+            message_expiry_interval: self.message_expiry_interval,
+            response_topic: self.response_topic.clone(),
+            user_properties: self.user_properties(user_properties),
+            content_type: Some(CONTENT_TYPE.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Properties of status messages, they do not expire
+    fn status(&self) -> PublishProperties {
+        PublishProperties {
+            payload_format_indicator: Some(1),
+            message_expiry_interval: None,
+            user_properties: self.user_properties(&BTreeMap::new()),
+            content_type: Some(CONTENT_TYPE.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Properties of the last will, status messages do not expire
+    fn last_will(&self) -> LastWillProperties {
+        LastWillProperties {
+            delay_interval: None,
+            payload_format_indicator: Some(1),
+            message_expiry_interval: None,
+            content_type: Some(CONTENT_TYPE.to_string()),
+            response_topic: None,
+            correlation_data: None,
+            user_properties: self.user_properties(&BTreeMap::new()),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct MqttClient {
-    pub client: Arc<RwLock<AsyncClient>>,
+    pub client: Arc<RwLock<Client>>,
     store: Arc<RwLock<MicroKV>>,
     event_loop_task: Arc<RwLock<JoinHandle<()>>>,
     pub current_client_config: Arc<RwLock<Broker>>,
+    /// Messages are published by one task, so they reach the broker in the order they were created
+    publisher: mpsc::UnboundedSender<PublisherEvent>,
+    publisher_task: Arc<JoinHandle<()>>,
 }
 
 impl MqttClient {
@@ -30,152 +219,399 @@ impl MqttClient {
         // load initial broker config from local db
         let store = app_state.db.clone();
 
-        // create client
-        let (client, eventloop_task, current_client_config) = create_mqtt_client(&store);
+        // create client, the eventloop reports the connection state and acknowledgements to the
+        // publisher
+        let (publisher, events) = mpsc::unbounded_channel();
+        let (client, eventloop_task, current_client_config) =
+            create_mqtt_client(&store, &publisher);
+        let client = Arc::new(RwLock::new(client));
+        let publisher_task = tokio::spawn(mqtt_outbox::run_publisher(
+            store.clone(),
+            client.clone(),
+            events,
+        ));
 
         MqttClient {
-            client: Arc::new(RwLock::new(client)),
+            client,
             event_loop_task: Arc::new(RwLock::new(eventloop_task)),
             current_client_config: Arc::new(RwLock::new(current_client_config)),
             store,
+            publisher,
+            publisher_task: Arc::new(publisher_task),
         }
     }
 
     /// Refresh broker connection to latest config values
     pub fn refresh(&mut self) {
         let current = self.current_client_config.read().unwrap().clone();
-        let new = self
-            .store
-            .read()
-            .unwrap()
-            .get_unwrap::<Broker>(DB_KEY)
-            .unwrap();
+        let new = store::get::<Broker>(&self.store.read().unwrap(), DB_KEY).unwrap();
 
         // check if something has changed in config
         if current != new {
             info!("Update broker connection with new settings.");
-            // stop current eventloop task
-            self.event_loop_task.write().unwrap().abort();
-
             // recreate client and event loop
-            let (client, eventloop_task, current_client_config) = create_mqtt_client(&self.store);
+            let (client, eventloop_task, current_client_config) =
+                create_mqtt_client(&self.store, &self.publisher);
 
             // store updated data to local state
-            *self.client.write().unwrap() = client;
-            *self.event_loop_task.write().unwrap() = eventloop_task;
+            let old_client = std::mem::replace(&mut *self.client.write().unwrap(), client);
+            let old_task =
+                std::mem::replace(&mut *self.event_loop_task.write().unwrap(), eventloop_task);
             *self.current_client_config.write().unwrap() = current_client_config;
+
+            // disconnect cleanly, so the broker does not publish the last will of the old
+            // connection, then stop the old eventloop task
+            let _ = old_client.try_disconnect();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                old_task.abort();
+            });
+        }
+    }
+
+    /// Publish new mqtt message which is not stored in the outbox
+    pub fn publish(&mut self, message: OutboxMessage) {
+        self.notify(PublisherEvent::Message(message));
+    }
+
+    /// Passes an event to the publisher task
+    pub fn notify(&self, event: PublisherEvent) {
+        if self.publisher.send(event).is_err() {
+            error!("[MQTT] Publisher task is not running");
         }
     }
 
-    /// Publish new mqtt message
-    pub fn publish(&mut self, topic: &str, payload: serde_json::Value) {
+    /// Publishes the offline status and disconnects from the broker (on application shutdown)
+    pub async fn shutdown(&self) {
+        // messages which were not acknowledged yet stay in the outbox
+        self.publisher_task.abort();
         let client = self.client.read().unwrap().clone();
-        let topic = topic.to_string();
+        let broker = store::get::<Broker>(&self.store.read().unwrap(), DB_KEY);
 
-        tokio::spawn(async move {
-            // let topic = topic.clone();
-            // let payload = payload.clone();
-            if let Err(err) = client
-                .publish(&topic, QoS::AtMostOnce, false, payload.to_string())
-                .await
+        if let Ok(broker) = broker {
+            if let (true, Some((topic, payload))) =
+                (broker.connected, status_message(&broker, false))
             {
-                warn!("Could not publish mqtt message {payload:?} to topic {topic} due to: {err:?}")
+                if let Err(err) = client.publish_status(&topic, &payload).await {
+                    warn!("Could not publish offline status to topic {topic} due to: {err}")
+                }
+            }
+        }
+        if let Err(err) = client.disconnect().await {
+            warn!("Could not disconnect from mqtt broker due to: {err}")
+        }
+
+        // wait until the eventloop has sent the pending messages
+        for _ in 0..20 {
+            if self.event_loop_task.read().unwrap().is_finished() {
+                break;
             }
-        });
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        info!("[MQTT] Disconnected from broker");
+    }
+}
+
+/// Topic and payload of the status message of this device, none if status messages are disabled
+fn status_message(broker: &Broker, online: bool) -> Option<(String, serde_json::Value)> {
+    let status = &broker.status;
+    if !status.enabled || status.topic.is_empty() {
+        return None;
+    }
+
+    let topic = status
+        .topic
+        .replace("{device_id}", &broker.device_id)
+        .replace("{device_group}", &broker.device_group);
+    let state = match online {
+        true => &status.online_payload,
+        false => &status.offline_payload,
+    };
+    let payload = json!({
+      "deviceId": broker.device_id,
+      "group": broker.device_group,
+      "state": state,
+      "version": env!("CARGO_PKG_VERSION"),
+      "hostname": System::new().host_name().unwrap_or_default(),
+    });
+
+    Some((topic, payload))
+}
+
+fn to_qos(qos: MqttQos) -> QoS {
+    match qos {
+        MqttQos::AtMostOnce => QoS::AtMostOnce,
+        MqttQos::AtLeastOnce => QoS::AtLeastOnce,
+        MqttQos::ExactlyOnce => QoS::ExactlyOnce,
+    }
+}
+
+fn to_qos_v5(qos: MqttQos) -> v5::mqttbytes::QoS {
+    match qos {
+        MqttQos::AtMostOnce => v5::mqttbytes::QoS::AtMostOnce,
+        MqttQos::AtLeastOnce => v5::mqttbytes::QoS::AtLeastOnce,
+        MqttQos::ExactlyOnce => v5::mqttbytes::QoS::ExactlyOnce,
     }
 }
 
 /// creates a new mqtt client
 fn create_mqtt_client(
     store: &Arc<RwLock<MicroKV>>,
-) -> (rumqttc::AsyncClient, tokio::task::JoinHandle<()>, Broker) {
+    publisher: &mpsc::UnboundedSender<PublisherEvent>,
+) -> (Client, tokio::task::JoinHandle<()>, Broker) {
     // default broker values
-    let mut username = "".to_string();
-    let mut password = "".to_string();
-    let mut client_id = "test-client-01".to_string();
-    let mut host = "localhost".to_string();
-    let mut port = 1883;
-    let mut protocol = "mqtt://".to_string();
-    let mut device_group = "autogroup_Monitor".to_string();
-    let mut device_id = "FC_0103".to_string();
+    let mut current_client_config = Broker {
+        client_id: "test-client-01".to_string(),
+        device_group: "autogroup_Monitor".to_string(),
+        device_id: "FC_0103".to_string(),
+        host: "localhost".to_string(),
+        port: 1883,
+        protocol: "mqtt://".to_string(),
+        state: "Server started".to_string(),
+        connected: false,
+        ..Default::default()
+    };
 
     // update default values
-    let broker_data = store.read().unwrap().get_unwrap::<Broker>(DB_KEY);
+    let broker_data = store::get::<Broker>(&store.read().unwrap(), DB_KEY);
     match broker_data {
         Ok(broker) => {
-            username = broker.username;
-            password = broker.password;
-            client_id = broker.client_id;
-            host = broker.host;
-            port = broker.port;
-            protocol = broker.protocol;
-            device_group = broker.device_group;
-            device_id = broker.device_id;
+            current_client_config.username = broker.username;
+            current_client_config.password = broker.password;
+            current_client_config.client_id = broker.client_id;
+            current_client_config.host = broker.host;
+            current_client_config.port = broker.port;
+            current_client_config.protocol = broker.protocol;
+            current_client_config.device_group = broker.device_group;
+            current_client_config.device_id = broker.device_id;
+            current_client_config.status = broker.status;
+            current_client_config.tls = broker.tls;
+            current_client_config.ws_path = broker.ws_path;
+            current_client_config.ws_headers = broker.ws_headers;
+            current_client_config.protocol_version = broker.protocol_version;
+            current_client_config.v5 = broker.v5;
         }
         Err(err) => {
             error!("Could not override default broker settings from local file db: {err:?}")
         }
     }
+    let Broker {
+        client_id,
+        host,
+        port,
+        username,
+        password,
+        protocol,
+        ws_path,
+        ..
+    } = &current_client_config;
 
-    // create mqtt client
-    let mut mqttoptions = MqttOptions::new(&client_id, &host, port);
-    mqttoptions.set_keep_alive(Duration::from_secs(30));
-
-    // use auth if provided
-    if !&username.is_empty() && !&password.is_empty() {
-        mqttoptions.set_credentials(&username, &password);
-    }
+    // websocket connections need the full url as broker address
+    let broker_addr = match protocol.as_str() {
+        "ws://" | "wss://" => {
+            let path = ws_path.trim_start_matches('/');
+            let path = if path.is_empty() { "mqtt" } else { path };
+            format!("{protocol}{host}:{port}/{path}")
+        }
+        _ => host.to_string(),
+    };
 
-    // Use rustls-native-certs to load root certificates from the operating system.
-    if &protocol == "mqtts://" {
-        let mut root_cert_store = RootCertStore::empty();
-        for cert in load_native_certs().expect("could not load platform certs") {
-            root_cert_store
-                .add(&Certificate(cert.0))
-                .expect("could not add cert to temporary application store.");
+    // use system and/or configured certificates for encrypted connections (mqtts and wss)
+    let tls_config = || {
+        mqtt_tls::client_config(&current_client_config.tls)
+            .map_err(|err| format!("TLS configuration error: {err}"))
+    };
+    let server_name = mqtt_tls::server_name(&current_client_config.tls)
+        .map_err(|err| format!("TLS configuration error: {err}"));
+    let mut sni_relay = None;
+    let transport = match protocol.as_str() {
+        "mqtt://" => Ok(Transport::Tcp),
+        "mqtts://" => tls_config().and_then(|config| match server_name.clone()? {
+            // rumqttc sends the host as SNI, the relay sends the configured server name
+            Some(server_name) => {
+                sni_relay = Some(mqtt_tls::SniRelay::start(host, *port, server_name, config)?);
+                Ok(Transport::Tcp)
+            }
+            None => Ok(Transport::tls_with_config(config.into())),
+        }),
+        "ws://" => Ok(Transport::Ws),
+        "wss://" => tls_config().map(|config| Transport::wss_with_config(config.into())),
+        _ => Err(format!("Unsupported protocol '{protocol}'")),
+    };
+    let ws_request = match protocol.as_str() {
+        "ws://" => parse_ws_headers(&current_client_config.ws_headers)
+            .map(|headers| WsRequest::new(headers, None)),
+        "wss://" => parse_ws_headers(&current_client_config.ws_headers).and_then(|headers| {
+            let authority = server_name?
+                .map(|_| ws_authority(&current_client_config.tls.server_name, *port))
+                .transpose()?;
+            Ok(WsRequest::new(headers, authority))
+        }),
+        _ => Ok(WsRequest::new(Vec::new(), None)),
+    };
+    // the client stays disconnected if it is not set up correctly
+    let transport = match ws_request.and_then(|request| Ok((transport?, request))) {
+        Ok(transport) => Some(transport),
+        Err(err) => {
+            error!("[MQTT] {err}");
+            update_broker_state(store, false, &err);
+            None
         }
+    };
 
-        let client_config = ClientConfig::builder()
-            .with_safe_defaults()
-            .with_root_certificates(root_cert_store)
-            .with_no_client_auth();
+    // broker sets the device offline if the connection is lost
+    let last_will = status_message(&current_client_config, false);
+    let birth = status_message(&current_client_config, true);
 
-        mqttoptions.set_transport(Transport::tls_with_config(client_config.into()));
-    }
+    // a mqtts connection with server name goes through the local relay
+    let (broker_addr, port) = match &sni_relay {
+        Some(relay) => (Ipv4Addr::LOCALHOST.to_string(), relay.port),
+        None => (broker_addr, *port),
+    };
 
-    let (client, eventloop) = AsyncClient::new(mqttoptions, 10);
+    // create mqtt client and spawn new eventloop task
+    let (client, eventloop_task) = match current_client_config.protocol_version {
+        MqttVersion::V311 => {
+            let mut mqttoptions = MqttOptions::new(client_id, broker_addr, port);
+            mqttoptions.set_keep_alive(Duration::from_secs(30));
 
-    // spawn new eventloop task
-    let eventloop_task = spawn_eventloop_task(&store, eventloop);
+            // use auth if provided
+            if !username.is_empty() && !password.is_empty() {
+                mqttoptions.set_credentials(username, password);
+            }
+            if let Some((topic, payload)) = last_will {
+                mqttoptions.set_last_will(LastWill::new(
+                    topic,
+                    payload.to_string(),
+                    QoS::AtLeastOnce,
+                    true,
+                ));
+            }
+            if let Some((transport, request)) = &transport {
+                mqttoptions.set_transport(transport.clone());
+                if request.is_modified() {
+                    mqttoptions.set_request_modifier(request.clone().modifier());
+                }
+            }
 
-    // store current config
-    let current_client_config = Broker {
-        client_id,
-        device_group,
-        device_id,
-        host,
-        password,
-        port,
-        protocol,
-        username,
-        state: "Server started".to_string(),
-        connected: false,
+            let (client, eventloop) = AsyncClient::new(mqttoptions, 10);
+            let client = Client::V311(client);
+            let eventloop_task = match transport {
+                Some(_) => spawn_eventloop_task(store, publisher, eventloop, birth, sni_relay),
+                None => tokio::spawn(async {}),
+            };
+            (client, eventloop_task)
+        }
+        MqttVersion::V5 => {
+            let properties = Arc::new(MessageProperties::new(&current_client_config));
+            let mut mqttoptions = v5::MqttOptions::new(client_id, broker_addr, port);
+            mqttoptions.set_keep_alive(Duration::from_secs(30));
+
+            // use auth if provided
+            if !username.is_empty() && !password.is_empty() {
+                mqttoptions.set_credentials(username, password);
+            }
+            if let Some((topic, payload)) = last_will {
+                mqttoptions.set_last_will(v5::mqttbytes::v5::LastWill::new(
+                    topic,
+                    payload.to_string(),
+                    v5::mqttbytes::QoS::AtLeastOnce,
+                    true,
+                    Some(properties.last_will()),
+                ));
+            }
+            if let Some((transport, request)) = &transport {
+                mqttoptions.set_transport(transport.clone());
+                if request.is_modified() {
+                    mqttoptions.set_request_modifier(request.clone().modifier());
+                }
+            }
+
+            let (client, eventloop) = v5::AsyncClient::new(mqttoptions, 10);
+            let client = Client::V5(client, properties);
+            let eventloop_task = match transport {
+                Some(_) => spawn_eventloop_task_v5(store, publisher, eventloop, birth, sni_relay),
+                None => tokio::spawn(async {}),
+            };
+            (client, eventloop_task)
+        }
     };
 
     (client, eventloop_task, current_client_config)
 }
 
+/// Validates the configured websocket handshake headers
+fn parse_ws_headers(
+    headers: &BTreeMap<String, String>,
+) -> Result<Vec<(HeaderName, HeaderValue)>, String> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let header_name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| format!("Invalid websocket header name '{name}'"))?;
+            let header_value = HeaderValue::from_str(value)
+                .map_err(|_| format!("Invalid value of websocket header '{name}'"))?;
+            Ok((header_name, header_value))
+        })
+        .collect()
+}
+
+/// Url authority with the configured server name
+fn ws_authority(server_name: &str, port: u16) -> Result<Authority, String> {
+    format!("{server_name}:{port}")
+        .parse()
+        .map_err(|_| format!("Invalid server name '{server_name}'"))
+}
+
+/// Changes of the websocket handshake request
+#[derive(Clone)]
+struct WsRequest {
+    headers: Vec<(HeaderName, HeaderValue)>,
+    /// Configured server name (and port), the TLS connection takes the SNI from the request url
+    authority: Option<Authority>,
+}
+
+impl WsRequest {
+    fn new(headers: Vec<(HeaderName, HeaderValue)>, authority: Option<Authority>) -> Self {
+        WsRequest { headers, authority }
+    }
+
+    fn is_modified(&self) -> bool {
+        !self.headers.is_empty() || self.authority.is_some()
+    }
+
+    /// Adds the configured headers and server name, the host header keeps the broker host
+    fn modifier(
+        self,
+    ) -> impl Fn(Request<()>) -> std::future::Ready<Request<()>> + Send + Sync + 'static {
+        move |mut request| {
+            for (name, value) in &self.headers {
+                request.headers_mut().insert(name.clone(), value.clone());
+            }
+            if let Some(authority) = &self.authority {
+                let mut uri = request.uri().clone().into_parts();
+                uri.authority = Some(authority.clone());
+                match Uri::from_parts(uri) {
+                    Ok(uri) => *request.uri_mut() = uri,
+                    Err(err) => error!("[MQTT] Could not set server name due to: {err}"),
+                }
+            }
+            std::future::ready(request)
+        }
+    }
+}
+
 /// Save new broker connection state to local file db
 fn update_broker_state(store: &Arc<RwLock<MicroKV>>, connected: bool, state: &str) {
     let lock = store.write().unwrap();
-    let mut broker = lock.get_unwrap::<Broker>(DB_KEY);
+    let mut broker = store::get::<Broker>(&lock, DB_KEY);
     match broker {
         Ok(ref mut broker) => {
             broker.state = state.to_string();
             broker.connected = connected;
 
             // write to file db
-            if let Err(err) = lock.put(DB_KEY, &broker.clone()) {
+            if let Err(err) = store::put(&lock, DB_KEY, &broker.clone()) {
                 error!("Could not update broker state on local file db: {err:?}")
             }
         }
@@ -189,18 +625,40 @@ fn update_broker_state(store: &Arc<RwLock<MicroKV>>, connected: bool, state: &st
 // handle mqtt client in separate task
 pub fn spawn_eventloop_task(
     store1: &Arc<RwLock<MicroKV>>,
+    publisher: &mpsc::UnboundedSender<PublisherEvent>,
     mut eventloop: EventLoop,
+    birth: Option<(String, serde_json::Value)>,
+    sni_relay: Option<mqtt_tls::SniRelay>,
 ) -> tokio::task::JoinHandle<()> {
     let store = store1.clone();
+    let publisher = publisher.clone();
     tokio::spawn(async move {
+        // the relay is stopped with the eventloop
+        let _sni_relay = sni_relay;
         loop {
             match eventloop.poll().await {
                 // set client connected status
-                Ok(Event::Incoming(Incoming::PingResp))
-                | Ok(Event::Incoming(Incoming::ConnAck(_))) => {
+                Ok(Event::Incoming(Incoming::PingResp)) => {
                     debug!("[MQTT] Connection successful");
                     update_broker_state(&store, true, "Connected");
                 }
+                // publish online status, then send messages which were queued while the broker was
+                // not connected
+                Ok(Event::Incoming(Incoming::ConnAck(_))) => {
+                    debug!("[MQTT] Connection successful");
+                    update_broker_state(&store, true, "Connected");
+                    let _ = publisher.send(PublisherEvent::Connected(birth.clone()));
+                }
+                // acknowledged messages are removed from the outbox
+                Ok(Event::Outgoing(Outgoing::Publish(pkid))) => {
+                    let _ = publisher.send(PublisherEvent::Sent(pkid));
+                }
+                Ok(Event::Incoming(Incoming::PubAck(ack))) => {
+                    let _ = publisher.send(PublisherEvent::Acked(ack.pkid));
+                }
+                Ok(Event::Incoming(Incoming::PubComp(ack))) => {
+                    let _ = publisher.send(PublisherEvent::Acked(ack.pkid));
+                }
                 Ok(Event::Incoming(Incoming::Publish(p))) => {
                     info!("[MQTT] Topic: {}, Payload: {:?}", p.topic, p.payload);
                 }
@@ -208,8 +666,14 @@ pub fn spawn_eventloop_task(
                     info!("[MQTT] Incoming = {:?}", i);
                 }
                 Ok(Event::Outgoing(Outgoing::PingReq)) => {}
+                // clean disconnect (shutdown or changed settings), the connection is not restored
+                Ok(Event::Outgoing(Outgoing::Disconnect)) => {
+                    debug!("[MQTT] Disconnected");
+                    break;
+                }
                 Ok(Event::Outgoing(o)) => debug!("Outgoing = {:?}", o),
                 Err(e) => {
+                    let _ = publisher.send(PublisherEvent::Disconnected);
                     match e {
                         ConnectionError::MqttState(e) => {
                             warn!("[MQTT] Pause eventloop task due to: {}", e);
@@ -260,7 +724,7 @@ pub fn spawn_eventloop_task(
                             update_broker_state(&store, false, &format!("{:?}", e));
                             break;
                         }
-                        ConnectionError::Timeout(e) => {
+                        ConnectionError::NetworkTimeout | ConnectionError::FlushTimeout => {
                             warn!("[MQTT] Timeout: {}", e);
                             update_broker_state(&store, false, "Timeout");
                         }
@@ -275,3 +739,123 @@ pub fn spawn_eventloop_task(
         }
     })
 }
+
+// handle mqtt 5 client in separate task, connack and disconnect reason codes are stored as state
+pub fn spawn_eventloop_task_v5(
+    store1: &Arc<RwLock<MicroKV>>,
+    publisher: &mpsc::UnboundedSender<PublisherEvent>,
+    mut eventloop: v5::EventLoop,
+    birth: Option<(String, serde_json::Value)>,
+    sni_relay: Option<mqtt_tls::SniRelay>,
+) -> tokio::task::JoinHandle<()> {
+    let store = store1.clone();
+    let publisher = publisher.clone();
+    tokio::spawn(async move {
+        // the relay is stopped with the eventloop
+        let _sni_relay = sni_relay;
+        let mut connected_state = "Connected".to_string();
+        // reason of the last disconnect by the broker, shown instead of the following socket error
+        let mut disconnect_reason: Option<String> = None;
+        loop {
+            match eventloop.poll().await {
+                Ok(v5::Event::Incoming(Packet::PingResp(_))) => {
+                    debug!("[MQTT] Connection successful");
+                    update_broker_state(&store, true, &connected_state);
+                }
+                // publish online status, then send messages which were queued while the broker was
+                // not connected
+                Ok(v5::Event::Incoming(Packet::ConnAck(connack))) => {
+                    debug!("[MQTT] Connection successful ({:?})", connack.code);
+                    connected_state = match connack.properties.and_then(|p| p.reason_string) {
+                        Some(reason) => format!("Connected ({:?}: {reason})", connack.code),
+                        None => format!("Connected ({:?})", connack.code),
+                    };
+                    disconnect_reason = None;
+                    update_broker_state(&store, true, &connected_state);
+                    let _ = publisher.send(PublisherEvent::Connected(birth.clone()));
+                }
+                // acknowledged messages are removed from the outbox
+                Ok(v5::Event::Outgoing(Outgoing::Publish(pkid))) => {
+                    let _ = publisher.send(PublisherEvent::Sent(pkid));
+                }
+                Ok(v5::Event::Incoming(Packet::PubAck(ack))) => {
+                    let _ = publisher.send(PublisherEvent::Acked(ack.pkid));
+                }
+                Ok(v5::Event::Incoming(Packet::PubComp(ack))) => {
+                    let _ = publisher.send(PublisherEvent::Acked(ack.pkid));
+                }
+                // the broker closes the connection, it is restored after a pause
+                Ok(v5::Event::Incoming(Packet::Disconnect(disconnect))) => {
+                    let reason = match disconnect.properties.and_then(|p| p.reason_string) {
+                        Some(reason) => {
+                            format!(
+                                "Disconnected by broker ({:?}: {reason})",
+                                disconnect.reason_code
+                            )
+                        }
+                        None => format!("Disconnected by broker ({:?})", disconnect.reason_code),
+                    };
+                    warn!("[MQTT] {reason}");
+                    update_broker_state(&store, false, &reason);
+                    let _ = publisher.send(PublisherEvent::Disconnected);
+                    disconnect_reason = Some(reason);
+                    tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+                }
+                Ok(v5::Event::Incoming(Packet::Publish(p))) => {
+                    info!("[MQTT] Topic: {:?}, Payload: {:?}", p.topic, p.payload);
+                }
+                Ok(v5::Event::Incoming(i)) => {
+                    info!("[MQTT] Incoming = {:?}", i);
+                }
+                Ok(v5::Event::Outgoing(Outgoing::PingReq)) => {}
+                // clean disconnect (shutdown or changed settings), the connection is not restored
+                Ok(v5::Event::Outgoing(Outgoing::Disconnect)) => {
+                    debug!("[MQTT] Disconnected");
+                    break;
+                }
+                Ok(v5::Event::Outgoing(o)) => debug!("Outgoing = {:?}", o),
+                Err(e) => {
+                    let _ = publisher.send(PublisherEvent::Disconnected);
+                    match e {
+                        v5::ConnectionError::MqttState(e) => {
+                            warn!("[MQTT] Pause eventloop task due to: {}", e);
+                            let state = disconnect_reason.take().unwrap_or(e.to_string());
+                            update_broker_state(&store, false, &state);
+                            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                        }
+                        v5::ConnectionError::Tls(e) => {
+                            warn!("[MQTT] End eventloop task due to: {}", e);
+                            update_broker_state(&store, false, &e.to_string());
+                            break;
+                        }
+                        v5::ConnectionError::Io(e) => {
+                            warn!("[MQTT] Pause eventloop task due to: {}", e);
+                            let state = disconnect_reason.take().unwrap_or(e.to_string());
+                            update_broker_state(&store, false, &state);
+                            tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+                        }
+                        v5::ConnectionError::ConnectionRefused(code) => {
+                            // e.g. ConnectionRefused(BadUserNamePassword)
+                            warn!("[MQTT] End eventloop task due to: {:?}", code);
+                            update_broker_state(
+                                &store,
+                                false,
+                                &format!("Connection refused ({code:?})"),
+                            );
+                            break;
+                        }
+                        v5::ConnectionError::Timeout(e) => {
+                            warn!("[MQTT] Timeout: {}", e);
+                            update_broker_state(&store, false, "Timeout");
+                        }
+                        e => {
+                            error!("[MQTT] End eventloop task due to: {}", e);
+                            update_broker_state(&store, false, &e.to_string());
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    })
+}
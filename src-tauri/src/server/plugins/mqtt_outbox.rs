@@ -0,0 +1,358 @@
+use super::{
+    mqtt_client::{Client, MqttClient},
+    parse_utc_timestamp,
+};
+use crate::server::{
+    router::{
+        outbox::{DeadLetterPolicy, Outbox, OutboxMessage, OutboxSettings},
+        settings::{Broker, MqttQos},
+    },
+    store,
+};
+use log::{error, info, warn};
+use microkv::MicroKV;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::{sync::mpsc, time::Instant};
+use uuid::Uuid;
+
+static DB_KEY: &str = "mqtt_outbox";
+static DB_KEY_SETTINGS: &str = "outbox_settings";
+
+/// Max number of kept dead letters
+const MAX_DEAD_LETTERS: usize = 1000;
+
+/// Time to wait for the acknowledgement of a message while connected, it is sent again afterwards
+const ACK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Result of a message delivery
+#[derive(Debug, PartialEq)]
+pub enum Delivery {
+    /// Broker is connected, the message is published in order (and kept in the outbox until the
+    /// broker acknowledged it)
+    Sent,
+    /// Queued until the broker is connected
+    Queued,
+    /// Broker is not connected and the outbox is disabled
+    Dropped,
+}
+
+/// New messages and connection events, handled by the publisher task in order
+#[derive(Debug)]
+pub enum PublisherEvent {
+    /// A message was added to the outbox
+    Queued,
+    /// Message which is not stored in the outbox (outbox disabled)
+    Message(OutboxMessage),
+    /// Broker acknowledged the connection, the status message (topic and payload, if any) is
+    /// published first
+    Connected(Option<(String, serde_json::Value)>),
+    /// Connection to the broker was lost
+    Disconnected,
+    /// Eventloop wrote a publish packet with this packet id (0 for QoS 0)
+    Sent(u16),
+    /// Broker acknowledged the publish with this packet id (PubAck or PubComp)
+    Acked(u16),
+}
+
+/// Creates a new outbox message, the payload keeps its original timestamp on replays
+pub fn message(
+    topic: &str,
+    payload: serde_json::Value,
+    qos: MqttQos,
+    retain: bool,
+) -> OutboxMessage {
+    OutboxMessage {
+        id: Uuid::new_v4(),
+        topic: topic.to_string(),
+        payload,
+        qos,
+        retain,
+        queued_utc: chrono::offset::Utc::now().to_string(),
+        attempts: 0,
+        reason: String::new(),
+        properties: BTreeMap::new(),
+    }
+}
+
+/// Stores a message in the outbox and hands it to the publisher, which sends the outbox messages
+/// in order once the broker is connected.
+///
+/// Without outbox messages are only published while the broker is connected.
+pub fn deliver(
+    db: &MicroKV,
+    mqtt_client: &mut MqttClient,
+    broker: &Broker,
+    message: OutboxMessage,
+) -> Delivery {
+    let settings = store::get::<OutboxSettings>(db, DB_KEY_SETTINGS).unwrap_or_default();
+    if !settings.enabled {
+        if !broker.connected {
+            return Delivery::Dropped;
+        }
+        mqtt_client.publish(message);
+        return Delivery::Sent;
+    }
+
+    let mut outbox = store::get::<Outbox>(db, DB_KEY).unwrap_or_default();
+    push(&mut outbox, &settings, message);
+    if let Err(err) = store::put(db, DB_KEY, &outbox) {
+        error!("Could not write mqtt outbox to local DB: {err:?}")
+    }
+    mqtt_client.notify(PublisherEvent::Queued);
+
+    match broker.connected {
+        true => Delivery::Sent,
+        false => Delivery::Queued,
+    }
+}
+
+/// Publishes the outbox messages (and messages which are not stored) one after another.
+///
+/// A message is removed from the outbox after the broker acknowledged it (QoS 1 and 2) or the
+/// eventloop wrote it to the connection (QoS 0), so it survives a restart until then.
+pub async fn run_publisher(
+    store: Arc<RwLock<MicroKV>>,
+    client: Arc<RwLock<Client>>,
+    mut events: mpsc::UnboundedReceiver<PublisherEvent>,
+) {
+    let mut connected = false;
+    // status messages (flagged) and messages of a disabled outbox
+    let mut unstored = VecDeque::<(OutboxMessage, bool)>::new();
+    let mut sent = 0;
+    loop {
+        let next = match connected {
+            true => unstored
+                .front()
+                .cloned()
+                .map(|(message, status)| (message, false, status))
+                .or_else(|| first_message(&store).map(|message| (message, true, false))),
+            false => None,
+        };
+        let (message, stored, status) = match next {
+            Some(next) => next,
+            None => {
+                if sent > 0 {
+                    info!("[Outbox] Published {sent} queued mqtt messages");
+                    sent = 0;
+                }
+                match events.recv().await {
+                    Some(event) => handle_event(event, &mut connected, &mut unstored),
+                    None => break,
+                }
+                continue;
+            }
+        };
+
+        let current_client = client.read().unwrap().clone();
+        let published = match status {
+            true => {
+                current_client
+                    .publish_status(&message.topic, &message.payload)
+                    .await
+            }
+            false => current_client.publish_message(&message).await,
+        };
+        if let Err(err) = published {
+            // the eventloop has ended, the message is sent again on the next connection
+            warn!(
+                "[Outbox] Could not publish mqtt message to topic {} due to: {err}",
+                &message.topic
+            );
+            connected = false;
+            continue;
+        }
+
+        let acknowledged =
+            match wait_for_ack(&mut events, message.qos, &mut connected, &mut unstored).await {
+                Some(acknowledged) => acknowledged,
+                None => break,
+            };
+        match (acknowledged, stored) {
+            (true, true) => {
+                remove_message(&store, &message.id);
+                sent += 1;
+            }
+            (true, false) => unstored.retain(|(pending, _)| pending.id != message.id),
+            (false, true) => {
+                warn!(
+                    "[Outbox] Mqtt message to topic {} was not acknowledged, sending it again",
+                    &message.topic
+                );
+                count_attempt(&store, &message.id);
+            }
+            (false, false) => {
+                warn!(
+                    "[Outbox] Mqtt message to topic {} was not acknowledged, giving it up",
+                    &message.topic
+                );
+                unstored.retain(|(pending, _)| pending.id != message.id);
+            }
+        }
+    }
+}
+
+fn handle_event(
+    event: PublisherEvent,
+    connected: &mut bool,
+    unstored: &mut VecDeque<(OutboxMessage, bool)>,
+) {
+    match event {
+        PublisherEvent::Message(message) => unstored.push_back((message, false)),
+        PublisherEvent::Connected(status) => {
+            *connected = true;
+            // the status of the new connection replaces one which was not sent yet
+            if let Some((topic, payload)) = status {
+                unstored.retain(|(_, status)| !status);
+                let status = message(&topic, payload, MqttQos::AtLeastOnce, true);
+                unstored.push_front((status, true));
+            }
+        }
+        PublisherEvent::Disconnected => *connected = false,
+        PublisherEvent::Queued | PublisherEvent::Sent(_) | PublisherEvent::Acked(_) => {}
+    }
+}
+
+/// Waits until the published message was acknowledged (true) or the timeout passed while the
+/// broker was connected (false), none if the client is gone.
+///
+/// Only one message is in flight at a time, so the packet id of the next publish is the one of
+/// this message (also if the eventloop sends it again after a reconnect).
+async fn wait_for_ack(
+    events: &mut mpsc::UnboundedReceiver<PublisherEvent>,
+    qos: MqttQos,
+    connected: &mut bool,
+    unstored: &mut VecDeque<(OutboxMessage, bool)>,
+) -> Option<bool> {
+    let mut pkid = None;
+    let mut deadline = Instant::now() + ACK_TIMEOUT;
+    loop {
+        let event = match tokio::time::timeout_at(deadline, events.recv()).await {
+            Ok(Some(event)) => event,
+            Ok(None) => return None,
+            Err(_) if *connected => return Some(false),
+            // the eventloop sends pending messages again once it is reconnected
+            Err(_) => {
+                deadline = Instant::now() + ACK_TIMEOUT;
+                continue;
+            }
+        };
+        match event {
+            PublisherEvent::Sent(_) if qos == MqttQos::AtMostOnce => return Some(true),
+            PublisherEvent::Sent(id) => {
+                pkid.get_or_insert(id);
+            }
+            PublisherEvent::Acked(id) if pkid == Some(id) => return Some(true),
+            PublisherEvent::Connected(_) => {
+                handle_event(event, connected, unstored);
+                deadline = Instant::now() + ACK_TIMEOUT;
+            }
+            event => handle_event(event, connected, unstored),
+        }
+    }
+}
+
+/// Oldest message of the outbox, expired messages are given up first
+fn first_message(store: &Arc<RwLock<MicroKV>>) -> Option<OutboxMessage> {
+    let lock = store.write().unwrap();
+    let settings = store::get::<OutboxSettings>(&lock, DB_KEY_SETTINGS).unwrap_or_default();
+    let mut outbox = store::get::<Outbox>(&lock, DB_KEY).unwrap_or_default();
+    let count = outbox.messages.len() + outbox.dead_letters.len();
+    expire(&mut outbox, &settings);
+    if outbox.messages.len() + outbox.dead_letters.len() != count {
+        if let Err(err) = store::put(&lock, DB_KEY, &outbox) {
+            error!("Could not write mqtt outbox to local DB: {err:?}")
+        }
+    }
+
+    outbox.messages.first().cloned()
+}
+
+/// Removes an acknowledged message from the outbox
+fn remove_message(store: &Arc<RwLock<MicroKV>>, id: &Uuid) {
+    let lock = store.write().unwrap();
+    let mut outbox = store::get::<Outbox>(&lock, DB_KEY).unwrap_or_default();
+    outbox.messages.retain(|message| &message.id != id);
+    if let Err(err) = store::put(&lock, DB_KEY, &outbox) {
+        error!("Could not write mqtt outbox to local DB: {err:?}")
+    }
+}
+
+/// Counts a failed attempt of a message, it is given up after the configured attempts
+fn count_attempt(store: &Arc<RwLock<MicroKV>>, id: &Uuid) {
+    let lock = store.write().unwrap();
+    let settings = store::get::<OutboxSettings>(&lock, DB_KEY_SETTINGS).unwrap_or_default();
+    let mut outbox = store::get::<Outbox>(&lock, DB_KEY).unwrap_or_default();
+    let index = match outbox.messages.iter().position(|message| &message.id == id) {
+        Some(index) => index,
+        None => return,
+    };
+
+    outbox.messages[index].attempts += 1;
+    if outbox.messages[index].attempts >= settings.max_attempts {
+        let message = outbox.messages.remove(index);
+        let reason = format!("Not acknowledged {} times", message.attempts);
+        dead_letter(&mut outbox, &settings, message, reason);
+    }
+    if let Err(err) = store::put(&lock, DB_KEY, &outbox) {
+        error!("Could not write mqtt outbox to local DB: {err:?}")
+    }
+}
+
+/// Appends a message, the oldest messages are given up if the outbox is full
+fn push(outbox: &mut Outbox, settings: &OutboxSettings, message: OutboxMessage) {
+    expire(outbox, settings);
+    outbox.messages.push(message);
+
+    while outbox.messages.len() > settings.max_messages.max(1) {
+        let oldest = outbox.messages.remove(0);
+        dead_letter(outbox, settings, oldest, "Outbox full".to_string());
+    }
+}
+
+/// Gives up messages which are older than the configured max age
+fn expire(outbox: &mut Outbox, settings: &OutboxSettings) {
+    if settings.max_age_secs == 0 {
+        return;
+    }
+
+    let max_age = chrono::Duration::seconds(settings.max_age_secs as i64);
+    let now = chrono::offset::Utc::now();
+    let (expired, messages) = std::mem::take(&mut outbox.messages)
+        .into_iter()
+        .partition::<Vec<_>, _>(|message| {
+            parse_utc_timestamp(&message.queued_utc)
+                .map(|queued| now - queued > max_age)
+                .unwrap_or(false)
+        });
+    outbox.messages = messages;
+
+    for message in expired {
+        dead_letter(outbox, settings, message, "Expired".to_string());
+    }
+}
+
+/// Applies the dead letter policy to a message which was given up
+fn dead_letter(
+    outbox: &mut Outbox,
+    settings: &OutboxSettings,
+    mut message: OutboxMessage,
+    reason: String,
+) {
+    warn!(
+        "[Outbox] Gave up mqtt message to topic {} queued at {}: {reason}",
+        &message.topic, &message.queued_utc
+    );
+    if settings.dead_letter_policy == DeadLetterPolicy::Drop {
+        return;
+    }
+
+    message.reason = reason;
+    outbox.dead_letters.push(message);
+    if outbox.dead_letters.len() > MAX_DEAD_LETTERS {
+        outbox
+            .dead_letters
+            .drain(..outbox.dead_letters.len() - MAX_DEAD_LETTERS);
+    }
+}
@@ -0,0 +1,296 @@
+use crate::server::router::settings::{BrokerTls, TlsVersion};
+use log::{error, warn};
+use p12_keystore::KeyStore;
+use rustls::{
+    client::{
+        danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        WebPkiServerVerifier,
+    },
+    crypto::{ring::default_provider, verify_tls12_signature, verify_tls13_signature},
+    pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName, UnixTime},
+    ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme, SupportedProtocolVersion,
+};
+use rustls_native_certs::load_native_certs;
+use rustls_pemfile::Item;
+use std::{net::Ipv4Addr, sync::Arc};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    task::{JoinHandle, JoinSet},
+};
+use tokio_rustls::TlsConnector;
+
+/// Creates the rustls config of a `mqtts://` connection.
+///
+/// Returns a readable error (stored as broker state) if a certificate or key can not be used.
+pub fn client_config(tls: &BrokerTls) -> Result<ClientConfig, String> {
+    let versions: &[&SupportedProtocolVersion] = match tls.min_version {
+        TlsVersion::Tls12 => &[&rustls::version::TLS13, &rustls::version::TLS12],
+        TlsVersion::Tls13 => &[&rustls::version::TLS13],
+    };
+    let builder = ClientConfig::builder_with_protocol_versions(versions).dangerous();
+
+    let verifier: Arc<dyn ServerCertVerifier> = match tls.insecure {
+        true => {
+            warn!("[MQTT] TLS certificate verification is disabled (insecure mode)");
+            Arc::new(AcceptAnyCertificate)
+        }
+        false => WebPkiServerVerifier::builder(Arc::new(root_certificates(tls)?))
+            .build()
+            .map_err(|err| format!("Invalid CA certificates: {err}"))?,
+    };
+    let builder = builder.with_custom_certificate_verifier(verifier);
+
+    match client_identity(tls)? {
+        Some((certs, key)) => builder
+            .with_client_auth_cert(certs, key)
+            .map_err(|err| format!("Invalid client certificate: {err}")),
+        None => Ok(builder.with_no_client_auth()),
+    }
+}
+
+/// Configured server name override (sent as SNI and verified), none if the host is used
+pub fn server_name(tls: &BrokerTls) -> Result<Option<ServerName<'static>>, String> {
+    match tls.server_name.is_empty() {
+        true => Ok(None),
+        false => ServerName::try_from(tls.server_name.clone())
+            .map(Some)
+            .map_err(|_| format!("Invalid server name '{}'", &tls.server_name)),
+    }
+}
+
+/// Local relay of a `mqtts://` connection with a server name override.
+///
+/// rumqttc always sends the broker host as SNI, so it connects in plain TCP to this relay which
+/// opens the TLS connection to the broker with the configured server name. The relay only listens
+/// on the loopback interface, serves one connection at a time and is stopped when dropped.
+pub struct SniRelay {
+    pub port: u16,
+    task: JoinHandle<()>,
+}
+
+impl SniRelay {
+    pub fn start(
+        host: &str,
+        port: u16,
+        server_name: ServerName<'static>,
+        config: ClientConfig,
+    ) -> Result<Self, String> {
+        let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+            .and_then(TcpListener::from_std)
+            .map_err(|err| format!("Could not start TLS relay: {err}"))?;
+        let local_port = listener
+            .local_addr()
+            .map_err(|err| format!("Could not start TLS relay: {err}"))?
+            .port();
+
+        let broker = format!("{host}:{port}");
+        let connector = TlsConnector::from(Arc::new(config));
+        let task = tokio::spawn(async move {
+            let mut connections = JoinSet::new();
+            loop {
+                let local = match listener.accept().await {
+                    Ok((local, _)) => local,
+                    Err(err) => {
+                        warn!("[MQTT] TLS relay could not accept connection: {err}");
+                        continue;
+                    }
+                };
+                // the eventloop only reconnects after the previous connection was lost
+                connections.shutdown().await;
+                connections.spawn(relay(
+                    local,
+                    broker.clone(),
+                    server_name.clone(),
+                    connector.clone(),
+                ));
+            }
+        });
+
+        Ok(SniRelay {
+            port: local_port,
+            task,
+        })
+    }
+}
+
+impl Drop for SniRelay {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Copies the data of the local connection to the TLS connection of the broker and back
+async fn relay(
+    mut local: TcpStream,
+    broker: String,
+    server_name: ServerName<'static>,
+    connector: TlsConnector,
+) {
+    let result = async {
+        let remote = TcpStream::connect(&broker).await?;
+        let mut remote = connector.connect(server_name, remote).await?;
+        tokio::io::copy_bidirectional(&mut local, &mut remote).await
+    };
+    if let Err(err) = result.await {
+        error!("[MQTT] TLS connection to {broker} ended due to: {err}");
+    }
+}
+
+/// Trusted CA certificates (system certificates and/or configured CA bundle)
+fn root_certificates(tls: &BrokerTls) -> Result<RootCertStore, String> {
+    let mut roots = RootCertStore::empty();
+
+    if tls.native_roots {
+        match load_native_certs() {
+            Ok(certs) => {
+                for cert in certs {
+                    // single broken system certificates are skipped
+                    if let Err(err) = roots.add(cert) {
+                        warn!("[MQTT] Skipped system certificate due to: {err:?}")
+                    }
+                }
+            }
+            Err(err) => warn!("[MQTT] Could not load system certificates due to: {err}"),
+        }
+    }
+
+    if !tls.ca.is_empty() {
+        for cert in read_certificates(&tls.ca)? {
+            roots
+                .add(cert)
+                .map_err(|err| format!("Invalid CA certificate in '{}': {err:?}", &tls.ca))?;
+        }
+    }
+
+    if roots.is_empty() {
+        return Err("No trusted CA certificates".to_string());
+    }
+
+    Ok(roots)
+}
+
+/// Client certificate chain and key for mutual TLS, none if not configured
+fn client_identity(
+    tls: &BrokerTls,
+) -> Result<Option<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)>, String> {
+    if !tls.client_pkcs12.is_empty() {
+        let data = std::fs::read(&tls.client_pkcs12)
+            .map_err(|err| format!("Could not read '{}': {err}", &tls.client_pkcs12))?;
+        let keystore = KeyStore::from_pkcs12(&data, &tls.client_pkcs12_password)
+            .map_err(|err| format!("Could not open '{}': {err:?}", &tls.client_pkcs12))?;
+        let (_alias, chain) = keystore
+            .private_key_chain()
+            .ok_or(format!("No private key found in '{}'", &tls.client_pkcs12))?;
+
+        let certs = chain
+            .chain()
+            .iter()
+            .map(|cert| CertificateDer::from(cert.as_der().to_vec()))
+            .collect();
+        let key = PrivatePkcs8KeyDer::from(chain.key().to_vec());
+        return Ok(Some((certs, key.into())));
+    }
+
+    match (tls.client_cert.is_empty(), tls.client_key.is_empty()) {
+        (true, true) => Ok(None),
+        (false, true) => Err("Client certificate is configured without a key".to_string()),
+        (true, false) => Err("Client key is configured without a certificate".to_string()),
+        (false, false) => Ok(Some((
+            read_certificates(&tls.client_cert)?,
+            read_private_key(&tls.client_key)?,
+        ))),
+    }
+}
+
+/// Reads PEM text, or the file if the value is a path
+fn read_pem(source: &str) -> Result<Vec<Item>, String> {
+    let data = match source.contains("-----BEGIN") {
+        true => source.as_bytes().to_vec(),
+        false => {
+            std::fs::read(source).map_err(|err| format!("Could not read '{source}': {err}"))?
+        }
+    };
+
+    rustls_pemfile::read_all(&mut data.as_slice())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| format!("Invalid PEM data in '{}': {err}", pem_name(source)))
+}
+
+fn read_certificates(source: &str) -> Result<Vec<CertificateDer<'static>>, String> {
+    let certs = read_pem(source)?
+        .into_iter()
+        .filter_map(|item| match item {
+            Item::X509Certificate(der) => Some(der),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    match certs.is_empty() {
+        true => Err(format!("No certificates found in '{}'", pem_name(source))),
+        false => Ok(certs),
+    }
+}
+
+fn read_private_key(source: &str) -> Result<PrivateKeyDer<'static>, String> {
+    read_pem(source)?
+        .into_iter()
+        .find_map(|item| match item {
+            Item::Pkcs8Key(der) => Some(der.into()),
+            Item::Pkcs1Key(der) => Some(der.into()),
+            Item::Sec1Key(der) => Some(der.into()),
+            _ => None,
+        })
+        .ok_or(format!("No private key found in '{}'", pem_name(source)))
+}
+
+/// Name of a certificate source for error messages (PEM text is not repeated)
+fn pem_name(source: &str) -> &str {
+    match source.contains("-----BEGIN") {
+        true => "PEM",
+        false => source,
+    }
+}
+
+/// Insecure mode, any broker certificate is accepted (handshake signatures are still checked)
+#[derive(Debug)]
+struct AcceptAnyCertificate;
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        let algorithms = default_provider().signature_verification_algorithms;
+        verify_tls12_signature(message, cert, dss, &algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        let algorithms = default_provider().signature_verification_algorithms;
+        verify_tls13_signature(message, cert, dss, &algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        default_provider()
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
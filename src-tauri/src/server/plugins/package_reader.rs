@@ -0,0 +1,270 @@
+use crate::server::router::files::PackageFormat;
+
+/// Gets the version of an installed package from a package database file.
+pub fn get_package_version(
+    path: &str,
+    package_name: &str,
+    format: &PackageFormat,
+) -> Result<String, String> {
+    let content =
+        std::fs::read_to_string(path).map_err(|err| format!("[Get Package Version] {err}"))?;
+
+    let version = match format {
+        PackageFormat::Dpkg => find_dpkg_version(&content, package_name),
+        PackageFormat::RpmExport => find_rpm_version(&content, package_name),
+        PackageFormat::Inventory => find_inventory_version(&content, package_name)?,
+    };
+
+    version
+        .ok_or_else(|| format!("[Get Package Version] Package '{package_name}' is not installed."))
+}
+
+/// Searches the dpkg status file for an installed package.
+///
+/// Entries are separated by blank lines and contain `Package`, `Status` and `Version` fields.
+fn find_dpkg_version(content: &str, package_name: &str) -> Option<String> {
+    for entry in content.replace("\r\n", "\n").split("\n\n") {
+        let mut name = None;
+        let mut status = None;
+        let mut version = None;
+        for line in entry.lines() {
+            if let Some((key, value)) = line.split_once(':') {
+                match key {
+                    "Package" => name = Some(value.trim()),
+                    "Status" => status = Some(value.trim()),
+                    "Version" => version = Some(value.trim()),
+                    _ => {}
+                }
+            }
+        }
+
+        // status is "<want> <flag> <status>", e.g. "install ok installed"
+        let installed = status
+            .map(|status| status.split_whitespace().last() == Some("installed"))
+            .unwrap_or(false);
+        if name == Some(package_name) && installed {
+            return version.map(|version| version.to_string());
+        }
+    }
+
+    None
+}
+
+/// Searches a rpm export with one `<name> <version>-<release>` entry per line.
+fn find_rpm_version(content: &str, package_name: &str) -> Option<String> {
+    content.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        match (parts.next(), parts.next()) {
+            (Some(name), Some(version)) if name == package_name => Some(version.to_string()),
+            _ => None,
+        }
+    })
+}
+
+/// Searches a csv inventory export with `Name` and `Version` columns.
+///
+/// Package names are compared case insensitive, comment lines (e.g. `#TYPE`) are skipped.
+fn find_inventory_version(content: &str, package_name: &str) -> Result<Option<String>, String> {
+    let mut lines = content
+        .trim_start_matches('\u{feff}')
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'));
+
+    let header = split_csv_line(lines.next().unwrap_or_default());
+    let column = |column: &str| {
+        header
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(column))
+            .ok_or_else(|| format!("[Get Package Version] Inventory has no '{column}' column."))
+    };
+    let name_column = column("Name")?;
+    let version_column = column("Version")?;
+
+    Ok(lines.find_map(|line| {
+        let fields = split_csv_line(line);
+        match (fields.get(name_column), fields.get(version_column)) {
+            (Some(name), Some(version)) if name.eq_ignore_ascii_case(package_name) => {
+                Some(version.clone())
+            }
+            _ => None,
+        }
+    }))
+}
+
+/// Splits a csv line into its fields, supports quoted fields with escaped quotes (`""`).
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' | ';' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    fields.into_iter().map(|f| f.trim().to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DPKG_STATUS: &str = "\
+Package: openssl
+Status: install ok installed
+Priority: optional
+Version: 3.0.2-0ubuntu1.10
+Description: Secure Sockets Layer toolkit
+ This package contains the openssl binary.
+ Package: not-a-field
+Homepage: https://www.openssl.org/
+
+Package: removed-tool
+Status: deinstall ok config-files
+Version: 1.0.0-1
+
+malformed line without separator
+Package: example-service
+Version: 2.4.1
+Status: install ok installed
+";
+
+    const RPM_EXPORT: &str = "\
+openssl 3.0.7-16.el9
+incomplete-line
+example-service   2.4.1-1.el9
+
+kernel 5.14.0-284.11.1.el9_2
+";
+
+    const INVENTORY: &str = "\u{feff}#TYPE Microsoft.PackageManagement.Packaging.SoftwareIdentity
+\"Name\",\"Version\",\"ProviderName\"
+\"Example Service\",\"2.4.1.0\",\"msi\"
+\"Vendor \"\"Quoted\"\" App\";\"1.0\";\"msi\"
+\"Broken line\"
+\"7-Zip 22.01 (x64)\",\"22.01\",\"Programs\"
+";
+
+    fn write_fixture(content: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("packages_{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn reads_dpkg_status() {
+        assert_eq!(
+            find_dpkg_version(DPKG_STATUS, "openssl").as_deref(),
+            Some("3.0.2-0ubuntu1.10")
+        );
+        // fields can be in any order
+        assert_eq!(
+            find_dpkg_version(DPKG_STATUS, "example-service").as_deref(),
+            Some("2.4.1")
+        );
+        // removed packages and continuation lines do not count as installed
+        assert_eq!(find_dpkg_version(DPKG_STATUS, "removed-tool"), None);
+        assert_eq!(find_dpkg_version(DPKG_STATUS, "not-a-field"), None);
+        assert_eq!(find_dpkg_version(DPKG_STATUS, "missing"), None);
+    }
+
+    #[test]
+    fn reads_dpkg_status_with_crlf() {
+        let content = DPKG_STATUS.replace('\n', "\r\n");
+        assert_eq!(
+            find_dpkg_version(&content, "example-service").as_deref(),
+            Some("2.4.1")
+        );
+    }
+
+    #[test]
+    fn reads_rpm_export() {
+        assert_eq!(
+            find_rpm_version(RPM_EXPORT, "openssl").as_deref(),
+            Some("3.0.7-16.el9")
+        );
+        assert_eq!(
+            find_rpm_version(RPM_EXPORT, "example-service").as_deref(),
+            Some("2.4.1-1.el9")
+        );
+        assert_eq!(find_rpm_version(RPM_EXPORT, "incomplete-line"), None);
+        assert_eq!(find_rpm_version(RPM_EXPORT, "missing"), None);
+    }
+
+    #[test]
+    fn reads_inventory() {
+        assert_eq!(
+            find_inventory_version(INVENTORY, "example service").unwrap(),
+            Some("2.4.1.0".to_string())
+        );
+        assert_eq!(
+            find_inventory_version(INVENTORY, "Vendor \"Quoted\" App").unwrap(),
+            Some("1.0".to_string())
+        );
+        assert_eq!(
+            find_inventory_version(INVENTORY, "7-Zip 22.01 (x64)").unwrap(),
+            Some("22.01".to_string())
+        );
+        assert_eq!(
+            find_inventory_version(INVENTORY, "Broken line").unwrap(),
+            None
+        );
+        assert_eq!(find_inventory_version(INVENTORY, "missing").unwrap(), None);
+    }
+
+    #[test]
+    fn inventory_without_columns_is_an_error() {
+        let err = find_inventory_version("\"Name\",\"Publisher\"\n\"App\",\"Vendor\"\n", "App")
+            .unwrap_err();
+        assert!(err.contains("'Version'"), "{err}");
+        assert!(find_inventory_version("", "App").is_err());
+    }
+
+    #[test]
+    fn reads_package_files() {
+        let fixtures = [
+            (
+                DPKG_STATUS,
+                PackageFormat::Dpkg,
+                "openssl",
+                "3.0.2-0ubuntu1.10",
+            ),
+            (
+                RPM_EXPORT,
+                PackageFormat::RpmExport,
+                "kernel",
+                "5.14.0-284.11.1.el9_2",
+            ),
+            (
+                INVENTORY,
+                PackageFormat::Inventory,
+                "Example Service",
+                "2.4.1.0",
+            ),
+        ];
+        for (content, format, package, version) in fixtures {
+            let path = write_fixture(content);
+            let path = path.to_string_lossy();
+            assert_eq!(
+                get_package_version(&path, package, &format).unwrap(),
+                version
+            );
+            let err = get_package_version(&path, "missing", &format).unwrap_err();
+            assert!(err.contains("'missing' is not installed"), "{err}");
+            let _ = std::fs::remove_file(path.as_ref());
+        }
+
+        assert!(
+            get_package_version("/nonexistent/status", "openssl", &PackageFormat::Dpkg).is_err()
+        );
+    }
+}
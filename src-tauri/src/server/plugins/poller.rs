@@ -0,0 +1,262 @@
+use crate::server::{
+    router::files::{Files, VersionSource},
+    store::{self, AppState},
+};
+use log::{error, info};
+use microkv::MicroKV;
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+use tokio::{sync::broadcast::Sender, task::JoinHandle};
+
+static DB_KEY: &str = "files";
+
+/// Triggers version checks for sources which need to be polled on a schedule (e.g. commands, urls,
+/// processes) and for files which are not (only) watched by file system events.
+pub struct Poller {
+    sender: Arc<RwLock<Sender<String>>>,
+    store: Arc<RwLock<MicroKV>>,
+    poll_tasks: HashMap<PollSpec, JoinHandle<()>>,
+    current_file_config: Files,
+}
+
+/// Poll task of a path, the task is only restarted if its spec changes
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct PollSpec {
+    path: String,
+    kind: PollKind,
+    interval_secs: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum PollKind {
+    /// Version check on every interval
+    Schedule,
+    /// Version check if the modification time or size of the file changed
+    Modification,
+}
+
+impl Poller {
+    /// Init poller plugin
+    pub fn init(sender: Arc<RwLock<Sender<String>>>, app_state: &Arc<AppState>) -> Self {
+        let current_file_config =
+            store::get::<Files>(&app_state.db.read().unwrap(), DB_KEY).unwrap();
+
+        let mut poller = Poller {
+            sender,
+            store: app_state.db.clone(),
+            poll_tasks: HashMap::new(),
+            current_file_config,
+        };
+        poller.update();
+
+        poller
+    }
+
+    /// Refresh poll tasks if the file config has changed
+    pub fn refresh(&mut self) {
+        let new_files = store::get::<Files>(&self.store.read().unwrap(), DB_KEY).unwrap();
+        if self.current_file_config != new_files {
+            info!("Pollers refreshed with new config.");
+            self.current_file_config = new_files;
+            self.update();
+        }
+    }
+
+    /// Stops the poll tasks which are not needed anymore and starts the new ones, unchanged tasks
+    /// keep their schedule
+    fn update(&mut self) {
+        let specs = poll_specs(&self.current_file_config);
+        self.poll_tasks.retain(|spec, task| {
+            let keep = specs.contains(spec);
+            if !keep {
+                task.abort();
+            }
+            keep
+        });
+
+        for spec in specs {
+            if self.poll_tasks.contains_key(&spec) {
+                continue;
+            }
+            let task = match spec.kind {
+                PollKind::Schedule => {
+                    tokio::spawn(poll_schedule(spec.clone(), self.sender.clone()))
+                }
+                PollKind::Modification => {
+                    tokio::spawn(poll_modification(spec.clone(), self.sender.clone()))
+                }
+            };
+            self.poll_tasks.insert(spec, task);
+        }
+    }
+}
+
+/// Returns the poll tasks of all enabled files, the shortest interval is used if multiple entries
+/// share a path
+fn poll_specs(files: &Files) -> Vec<PollSpec> {
+    let mut intervals: HashMap<(String, PollKind), u64> = HashMap::new();
+    let mut add = |path: String, kind: PollKind, interval_secs: u64| {
+        let interval = intervals.entry((path, kind)).or_insert(interval_secs);
+        *interval = (*interval).min(interval_secs);
+    };
+    for file in files.values() {
+        if !file.enabled {
+            continue;
+        }
+        if let Some(interval_secs) = poll_interval(&file.source) {
+            add(file.path.clone(), PollKind::Schedule, interval_secs);
+        }
+        if let Some(interval_secs) = file
+            .watch_mode
+            .poll_interval()
+            .filter(|_| file.source.has_local_path())
+        {
+            add(
+                file.path.replace('\\', "/"),
+                PollKind::Modification,
+                interval_secs,
+            );
+        }
+    }
+
+    intervals
+        .into_iter()
+        .map(|((path, kind), interval_secs)| PollSpec {
+            path,
+            kind,
+            interval_secs,
+        })
+        .collect()
+}
+
+/// Sends the path on every interval
+async fn poll_schedule(spec: PollSpec, sender: Arc<RwLock<Sender<String>>>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(spec.interval_secs));
+    // first tick completes immediately - versions are checked on start already
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        if let Err(err) = sender.read().unwrap().send(spec.path.clone()) {
+            error!(
+                "Could not send poll event for '{}' due to: {err:?}",
+                &spec.path
+            )
+        }
+    }
+}
+
+/// Sends the path if the modification time or size of the file changed since the last interval
+async fn poll_modification(spec: PollSpec, sender: Arc<RwLock<Sender<String>>>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(spec.interval_secs));
+    interval.tick().await;
+    let mut state = poll_state(&spec.path).await;
+    loop {
+        interval.tick().await;
+        let new_state = poll_state(&spec.path).await;
+        if new_state == state {
+            continue;
+        }
+
+        state = new_state;
+        if let Err(err) = sender.read().unwrap().send(spec.path.clone()) {
+            error!("Could not send file change event due to: {err:?}")
+        }
+    }
+}
+
+/// Returns the modification time and size of a file (`None` if it does not exist)
+async fn poll_state(path: &str) -> Option<(SystemTime, u64)> {
+    // metadata calls can block for a long time on unreachable network shares
+    let path = path.to_string();
+    tokio::task::spawn_blocking(move || {
+        let metadata = std::fs::metadata(path).ok()?;
+        Some((metadata.modified().ok()?, metadata.len()))
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+/// Returns the poll interval in seconds for sources which need to be polled
+fn poll_interval(source: &VersionSource) -> Option<u64> {
+    match source {
+        VersionSource::Command { interval_secs, .. }
+        | VersionSource::Http { interval_secs, .. }
+        | VersionSource::Process { interval_secs, .. }
+        | VersionSource::Git { interval_secs }
+            if *interval_secs > 0 =>
+        {
+            Some(*interval_secs)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::router::files::{File, WatchMode};
+    use uuid::Uuid;
+
+    fn polled(path: &str, interval_secs: u64) -> File {
+        File {
+            path: path.to_string(),
+            enabled: true,
+            watch_mode: WatchMode::Poll { interval_secs },
+            ..Default::default()
+        }
+    }
+
+    fn specs(files: Vec<File>) -> Vec<PollSpec> {
+        let files = files
+            .into_iter()
+            .map(|file| (Uuid::new_v4(), file))
+            .collect::<Files>();
+        let mut specs = poll_specs(&files);
+        specs.sort_by(|a, b| a.path.cmp(&b.path));
+        specs
+    }
+
+    #[test]
+    fn shared_paths_are_polled_once_with_the_shortest_interval() {
+        let specs = specs(vec![
+            polled("C:\\app\\app.exe", 30),
+            polled("C:/app/app.exe", 10),
+            File {
+                enabled: false,
+                ..polled("C:/app/disabled.exe", 5)
+            },
+        ]);
+        assert_eq!(
+            specs,
+            vec![PollSpec {
+                path: "C:/app/app.exe".to_string(),
+                kind: PollKind::Modification,
+                interval_secs: 10,
+            }]
+        );
+    }
+
+    #[test]
+    fn scheduled_sources_get_their_own_task() {
+        let command = File {
+            path: "app".to_string(),
+            enabled: true,
+            source: VersionSource::Command {
+                args: Vec::new(),
+                working_dir: String::new(),
+                interval_secs: 60,
+                timeout_secs: 10,
+                extractor: Default::default(),
+            },
+            ..Default::default()
+        };
+        let specs = specs(vec![command, polled("C:/app/app.exe", 10)]);
+        assert_eq!(specs.len(), 2);
+        assert_eq!(specs[1].kind, PollKind::Schedule);
+        assert_eq!(specs[1].interval_secs, 60);
+    }
+}
@@ -0,0 +1,157 @@
+use super::file_version_reader;
+use crate::server::{
+    router::{
+        files::HashAlgorithm,
+        processes::{ProcessInfo, ProcessStates},
+    },
+    store,
+};
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use microkv::MicroKV;
+use regex::Regex;
+use std::time::{Duration, UNIX_EPOCH};
+use sysinfo::{PidExt, ProcessExt, System, SystemExt};
+use uuid::Uuid;
+
+static DB_KEY: &str = "processes";
+
+/// Gets all running processes with a matching name and command line.
+///
+/// The name is compared case insensitive, a missing `.exe` extension is ignored.
+pub fn get_processes(name: &str, cmdline_pattern: &str) -> Result<Vec<ProcessInfo>, String> {
+    let cmdline_regex = match cmdline_pattern.is_empty() {
+        true => None,
+        false => Some(
+            Regex::new(cmdline_pattern)
+                .map_err(|err| format!("[Get Processes] Invalid regex: {err}"))?,
+        ),
+    };
+    let name = name.to_lowercase();
+    let name = name.trim_end_matches(".exe");
+
+    let mut system = System::new();
+    system.refresh_processes();
+
+    let mut processes = system
+        .processes()
+        .iter()
+        .filter(|(_pid, process)| {
+            process.name().to_lowercase().trim_end_matches(".exe") == name
+                && match &cmdline_regex {
+                    Some(regex) => regex.is_match(&process.cmd().join(" ")),
+                    None => true,
+                }
+        })
+        .map(|(pid, process)| {
+            let exe = process.exe().to_string_lossy().to_string();
+            ProcessInfo {
+                pid: pid.as_u32(),
+                stale: is_stale_binary(pid.as_u32(), &exe, process.start_time()),
+                start_time_utc: DateTime::<Utc>::from(
+                    UNIX_EPOCH + Duration::from_secs(process.start_time()),
+                )
+                .to_string(),
+                start_time: process.start_time(),
+                exe,
+            }
+        })
+        .collect::<Vec<_>>();
+    processes.sort_by_key(|process| process.pid);
+
+    Ok(processes)
+}
+
+/// Gets the executable version of the first matching process.
+///
+/// Returns a warning if one of the processes still runs an outdated binary.
+pub fn get_process_version(
+    processes: &[ProcessInfo],
+    algorithm: &HashAlgorithm,
+) -> Result<(String, Option<String>), String> {
+    let process = processes
+        .first()
+        .ok_or_else(|| "[Get Processes] No matching process running.".to_string())?;
+    let version = file_version_reader::get_file_version(&process.exe, algorithm)?;
+
+    let stale = processes
+        .iter()
+        .filter(|process| process.stale)
+        .map(|process| process.pid.to_string())
+        .collect::<Vec<_>>();
+    let warning = match stale.is_empty() {
+        true => None,
+        false => Some(format!(
+            "[Get Processes] Binary updated on disk, but process {} still runs the old one.",
+            stale.join(", ")
+        )),
+    };
+
+    Ok((version, warning))
+}
+
+/// Stores the running processes and detects restarts since the last check.
+pub fn update_process_state(db: &MicroKV, uuid: &Uuid, name: &str, processes: &[ProcessInfo]) {
+    let mut states = store::get::<ProcessStates>(db, DB_KEY).unwrap_or_default();
+    let state = states.entry(*uuid).or_default();
+    // states of older versions only know the current processes
+    if state.last_seen.is_empty() {
+        state.last_seen = state.processes.clone();
+    }
+
+    // a process restarted if a known process is gone and another one is running instead, also
+    // if it was down in between (compared to the processes which were seen last)
+    let restarted = !state.last_seen.is_empty()
+        && !processes.is_empty()
+        && state.last_seen.iter().any(|old| {
+            !processes
+                .iter()
+                .any(|new| new.pid == old.pid && new.start_time == old.start_time)
+        });
+    if restarted {
+        info!(
+            "[Processes] Process '{name}' restarted (pid {} -> {})",
+            pid_list(&state.last_seen),
+            pid_list(processes)
+        );
+        state.restarts += 1;
+        state.last_restart_utc = chrono::offset::Utc::now().to_string();
+    }
+    state.processes = processes.to_vec();
+    if !processes.is_empty() {
+        state.last_seen = processes.to_vec();
+    }
+
+    if let Err(err) = store::put(db, DB_KEY, &states) {
+        error!("Could not write process state to local DB: {err:?}")
+    }
+}
+
+fn pid_list(processes: &[ProcessInfo]) -> String {
+    processes
+        .iter()
+        .map(|process| process.pid.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Checks if the executable on disk was replaced after the process started.
+fn is_stale_binary(pid: u32, exe: &str, start_time: u64) -> bool {
+    // the kernel marks replaced executables of running processes as deleted
+    #[cfg(target_os = "linux")]
+    if let Ok(link) = std::fs::read_link(format!("/proc/{pid}/exe")) {
+        if link.to_string_lossy().ends_with(" (deleted)") {
+            return true;
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = pid;
+
+    match std::fs::metadata(exe).and_then(|metadata| metadata.modified()) {
+        Ok(modified) => match modified.duration_since(UNIX_EPOCH) {
+            Ok(modified) => modified.as_secs() > start_time,
+            Err(_) => false,
+        },
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,485 @@
+/// Offset of the first hive bin; all cell offsets are relative to it.
+const HIVE_BINS_OFFSET: usize = 4096;
+/// Max data size stored in a single cell, bigger values are split into "db" segments.
+const BIG_DATA_SEGMENT_SIZE: usize = 16344;
+
+/// Key name is stored as ASCII (extended) string instead of UTF-16LE
+const KEY_COMP_NAME: u16 = 0x0020;
+/// Value name is stored as ASCII (extended) string instead of UTF-16LE
+const VALUE_COMP_NAME: u16 = 0x0001;
+
+/// Gets a value from an offline registry hive file (regf format).
+///
+/// The key path is relative to the hive root, e.g. `Microsoft\Windows\CurrentVersion\Uninstall\{GUID}`
+/// for the `SOFTWARE` hive. An empty value name or `(Default)` reads the default value of the key.
+pub fn get_registry_value(path: &str, key_path: &str, value_name: &str) -> Result<String, String> {
+    let data = std::fs::read(path).map_err(|err| format!("[Get Registry Value] {err}"))?;
+    let hive = Hive::parse(&data)?;
+
+    let key = hive.find_key(key_path)?;
+    hive.read_value(key, value_name)
+}
+
+/// Parsed view on the raw bytes of a registry hive file.
+struct Hive<'a> {
+    data: &'a [u8],
+    root_offset: u32,
+}
+
+impl<'a> Hive<'a> {
+    fn parse(data: &'a [u8]) -> Result<Self, String> {
+        if data.len() < HIVE_BINS_OFFSET || &data[0..4] != b"regf" {
+            return Err("[Get Registry Value] File is not a registry hive.".to_string());
+        }
+
+        Ok(Hive {
+            data,
+            root_offset: read_u32(data, 0x24)?,
+        })
+    }
+
+    /// Returns the data of an allocated cell (without the size header)
+    fn cell(&self, offset: u32) -> Result<&'a [u8], String> {
+        let start = HIVE_BINS_OFFSET + offset as usize;
+        let size = read_u32(self.data, start)? as i32;
+        // allocated cells have a negative size
+        if size >= 0 {
+            return Err(format!(
+                "[Get Registry Value] Cell at offset {offset:#x} is not allocated."
+            ));
+        }
+
+        self.data
+            .get(start + 4..start + size.unsigned_abs() as usize)
+            .ok_or_else(|| {
+                format!("[Get Registry Value] Cell at offset {offset:#x} exceeds hive size.")
+            })
+    }
+
+    /// Walks down the key path from the root key and returns the offset of the target key node
+    fn find_key(&self, key_path: &str) -> Result<u32, String> {
+        let mut key = self.root_offset;
+
+        for name in key_path.split(['\\', '/']) {
+            if name.is_empty() {
+                continue;
+            }
+
+            let mut found = None;
+            for subkey in self.subkeys(key)? {
+                if key_name(self.cell(subkey)?)?.eq_ignore_ascii_case(name) {
+                    found = Some(subkey);
+                    break;
+                }
+            }
+
+            match found {
+                Some(subkey) => key = subkey,
+                None => {
+                    return Err(format!(
+                        "[Get Registry Value] Key '{name}' not found in path '{key_path}'."
+                    ))
+                }
+            }
+        }
+
+        Ok(key)
+    }
+
+    /// Returns the offsets of all subkey nodes of a key node
+    fn subkeys(&self, key: u32) -> Result<Vec<u32>, String> {
+        let node = self.cell(key)?;
+        if node.get(0..2) != Some(&b"nk"[..]) {
+            return Err("[Get Registry Value] Invalid key node.".to_string());
+        }
+
+        let mut subkeys = Vec::new();
+        if read_u32(node, 0x14)? > 0 {
+            self.collect_subkey_list(read_u32(node, 0x1C)?, &mut subkeys, false)?;
+        }
+        Ok(subkeys)
+    }
+
+    fn collect_subkey_list(
+        &self,
+        list: u32,
+        subkeys: &mut Vec<u32>,
+        nested: bool,
+    ) -> Result<(), String> {
+        let list = self.cell(list)?;
+        let count = read_u16(list, 2)? as usize;
+
+        match list.get(0..2) {
+            // fast leaf and hash leaf: offset followed by name hint
+            Some(b"lf") | Some(b"lh") => {
+                for i in 0..count {
+                    subkeys.push(read_u32(list, 4 + i * 8)?);
+                }
+            }
+            // index leaf: offsets only
+            Some(b"li") => {
+                for i in 0..count {
+                    subkeys.push(read_u32(list, 4 + i * 4)?);
+                }
+            }
+            // index root: offsets of further subkey lists (which are never index roots again)
+            Some(b"ri") if !nested => {
+                for i in 0..count {
+                    self.collect_subkey_list(read_u32(list, 4 + i * 4)?, subkeys, true)?;
+                }
+            }
+            _ => return Err("[Get Registry Value] Invalid subkey list.".to_string()),
+        }
+
+        Ok(())
+    }
+
+    /// Reads a value of a key node and formats it as string
+    fn read_value(&self, key: u32, value_name: &str) -> Result<String, String> {
+        let node = self.cell(key)?;
+        let value_count = read_u32(node, 0x24)? as usize;
+        let values = match value_count {
+            0 => &[][..],
+            _ => self.cell(read_u32(node, 0x28)?)?,
+        };
+        let value_name = match value_name {
+            "(Default)" => "",
+            name => name,
+        };
+
+        for i in 0..value_count {
+            let value = self.cell(read_u32(values, i * 4)?)?;
+            if value.get(0..2) != Some(&b"vk"[..]) {
+                return Err("[Get Registry Value] Invalid value node.".to_string());
+            }
+
+            let name_length = read_u16(value, 0x02)? as usize;
+            let flags = read_u16(value, 0x10)?;
+            let name = read_bytes(value, 0x14, name_length)?;
+            let name = match flags & VALUE_COMP_NAME {
+                0 => decode_utf16(name),
+                _ => decode_latin1(name),
+            };
+            if !name.eq_ignore_ascii_case(value_name) {
+                continue;
+            }
+
+            let data = self.value_data(value)?;
+            return format_value(read_u32(value, 0x0C)?, &data);
+        }
+
+        Err(format!(
+            "[Get Registry Value] Value '{value_name}' not found."
+        ))
+    }
+
+    /// Returns the raw data of a value node
+    fn value_data(&self, value: &[u8]) -> Result<Vec<u8>, String> {
+        let size = read_u32(value, 0x04)?;
+        let offset = read_u32(value, 0x08)?;
+
+        // small data (up to 4 bytes) is stored in the offset field itself
+        if size & 0x8000_0000 != 0 {
+            let size = (size & 0x7FFF_FFFF) as usize;
+            return Ok(read_bytes(value, 0x08, size.min(4))?.to_vec());
+        }
+
+        let size = size as usize;
+        let cell = self.cell(offset)?;
+        if size > BIG_DATA_SEGMENT_SIZE && cell.get(0..2) == Some(&b"db"[..]) {
+            let segment_count = read_u16(cell, 0x02)? as usize;
+            let segments = self.cell(read_u32(cell, 0x04)?)?;
+
+            let mut data = Vec::with_capacity(size.min(self.data.len()));
+            for i in 0..segment_count {
+                let segment = self.cell(read_u32(segments, i * 4)?)?;
+                let length = (size - data.len()).min(BIG_DATA_SEGMENT_SIZE);
+                data.extend_from_slice(read_bytes(segment, 0, length)?);
+            }
+            return Ok(data);
+        }
+
+        Ok(read_bytes(cell, 0, size)?.to_vec())
+    }
+}
+
+/// Returns the name of a key node
+fn key_name(node: &[u8]) -> Result<String, String> {
+    let flags = read_u16(node, 0x02)?;
+    let name_length = read_u16(node, 0x48)? as usize;
+    let name = read_bytes(node, 0x4C, name_length)?;
+
+    match flags & KEY_COMP_NAME {
+        0 => Ok(decode_utf16(name)),
+        _ => Ok(decode_latin1(name)),
+    }
+}
+
+/// Formats registry value data according to its type
+fn format_value(data_type: u32, data: &[u8]) -> Result<String, String> {
+    match data_type {
+        // REG_SZ | REG_EXPAND_SZ
+        1 | 2 => Ok(decode_utf16(data)),
+        // REG_DWORD
+        4 => Ok(read_u32(data, 0)?.to_string()),
+        // REG_DWORD_BIG_ENDIAN
+        5 => Ok(u32::from_be_bytes(read_bytes(data, 0, 4)?.try_into().unwrap()).to_string()),
+        // REG_MULTI_SZ
+        7 => Ok(decode_utf16(data)
+            .split('\0')
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join(";")),
+        // REG_QWORD
+        11 => Ok(u64::from_le_bytes(read_bytes(data, 0, 8)?.try_into().unwrap()).to_string()),
+        // REG_BINARY and others
+        _ => Ok(data.iter().map(|b| format!("{b:02x}")).collect()),
+    }
+}
+
+fn read_bytes(data: &[u8], offset: usize, length: usize) -> Result<&[u8], String> {
+    data.get(offset..offset + length)
+        .ok_or_else(|| "[Get Registry Value] Unexpected end of hive data.".to_string())
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, String> {
+    Ok(u16::from_le_bytes(
+        read_bytes(data, offset, 2)?.try_into().unwrap(),
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, String> {
+    Ok(u32::from_le_bytes(
+        read_bytes(data, offset, 4)?.try_into().unwrap(),
+    ))
+}
+
+fn decode_latin1(data: &[u8]) -> String {
+    data.iter().map(|&b| b as char).collect()
+}
+
+fn decode_utf16(data: &[u8]) -> String {
+    let units = data
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect::<Vec<_>>();
+
+    String::from_utf16_lossy(&units)
+        .trim_end_matches('\0')
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a hive file cell by cell, cells must be added before the cells referencing them
+    struct HiveBuilder {
+        data: Vec<u8>,
+    }
+
+    impl HiveBuilder {
+        fn new() -> Self {
+            let mut data = vec![0; HIVE_BINS_OFFSET];
+            data[0..4].copy_from_slice(b"regf");
+            data.extend_from_slice(b"hbin");
+            data.resize(HIVE_BINS_OFFSET + 0x20, 0);
+            HiveBuilder { data }
+        }
+
+        /// Appends an allocated cell and returns its offset
+        fn cell(&mut self, content: &[u8]) -> u32 {
+            let offset = (self.data.len() - HIVE_BINS_OFFSET) as u32;
+            let size = -(content.len() as i32 + 4);
+            self.data.extend_from_slice(&size.to_le_bytes());
+            self.data.extend_from_slice(content);
+            offset
+        }
+
+        fn key(&mut self, name: &str, subkeys: &[u32], values: &[u32]) -> u32 {
+            let subkey_list = match subkeys.is_empty() {
+                true => 0xFFFF_FFFF,
+                false => {
+                    let mut list = b"lh".to_vec();
+                    list.extend_from_slice(&(subkeys.len() as u16).to_le_bytes());
+                    for subkey in subkeys {
+                        list.extend_from_slice(&subkey.to_le_bytes());
+                        list.extend_from_slice(&[0; 4]); // name hash
+                    }
+                    self.cell(&list)
+                }
+            };
+            let value_list = match values.is_empty() {
+                true => 0xFFFF_FFFF,
+                false => {
+                    let list = values
+                        .iter()
+                        .flat_map(|v| v.to_le_bytes())
+                        .collect::<Vec<_>>();
+                    self.cell(&list)
+                }
+            };
+
+            let mut node = vec![0; 0x4C];
+            node[0..2].copy_from_slice(b"nk");
+            node[0x02..0x04].copy_from_slice(&KEY_COMP_NAME.to_le_bytes());
+            node[0x14..0x18].copy_from_slice(&(subkeys.len() as u32).to_le_bytes());
+            node[0x1C..0x20].copy_from_slice(&subkey_list.to_le_bytes());
+            node[0x24..0x28].copy_from_slice(&(values.len() as u32).to_le_bytes());
+            node[0x28..0x2C].copy_from_slice(&value_list.to_le_bytes());
+            node[0x48..0x4A].copy_from_slice(&(name.len() as u16).to_le_bytes());
+            node.extend_from_slice(name.as_bytes());
+            self.cell(&node)
+        }
+
+        fn value(&mut self, name: &str, data_type: u32, data: &[u8]) -> u32 {
+            // up to 4 bytes are stored inline in the offset field
+            let (size, offset) = match data.len() <= 4 {
+                true => {
+                    let mut inline = [0u8; 4];
+                    inline[..data.len()].copy_from_slice(data);
+                    (data.len() as u32 | 0x8000_0000, u32::from_le_bytes(inline))
+                }
+                false => (data.len() as u32, self.cell(data)),
+            };
+
+            let mut node = vec![0; 0x14];
+            node[0..2].copy_from_slice(b"vk");
+            node[0x02..0x04].copy_from_slice(&(name.len() as u16).to_le_bytes());
+            node[0x04..0x08].copy_from_slice(&size.to_le_bytes());
+            node[0x08..0x0C].copy_from_slice(&offset.to_le_bytes());
+            node[0x0C..0x10].copy_from_slice(&data_type.to_le_bytes());
+            node[0x10..0x12].copy_from_slice(&VALUE_COMP_NAME.to_le_bytes());
+            node.extend_from_slice(name.as_bytes());
+            self.cell(&node)
+        }
+
+        fn finish(mut self, root: u32) -> Vec<u8> {
+            self.data[0x24..0x28].copy_from_slice(&root.to_le_bytes());
+            self.data
+        }
+    }
+
+    fn utf16(value: &str) -> Vec<u8> {
+        value
+            .encode_utf16()
+            .chain([0])
+            .flat_map(|unit| unit.to_le_bytes())
+            .collect()
+    }
+
+    /// SOFTWARE like hive with `Vendor\App` (version, build and default value) and `Vendor\Empty`
+    fn fixture() -> Vec<u8> {
+        let mut hive = HiveBuilder::new();
+        let version = hive.value("DisplayVersion", 1, &utf16("1.2.3"));
+        let build = hive.value("Build", 4, &42u32.to_le_bytes());
+        let default = hive.value("", 1, &utf16("Example App"));
+        let app = hive.key("App", &[], &[version, build, default]);
+        let empty = hive.key("Empty", &[], &[]);
+        let vendor = hive.key("Vendor", &[app, empty], &[]);
+        let root = hive.key("ROOT", &[vendor], &[]);
+        hive.finish(root)
+    }
+
+    fn value(data: &[u8], key_path: &str, value_name: &str) -> Result<String, String> {
+        let hive = Hive::parse(data)?;
+        let key = hive.find_key(key_path)?;
+        hive.read_value(key, value_name)
+    }
+
+    #[test]
+    fn reads_string_value() {
+        let hive = fixture();
+        assert_eq!(
+            value(&hive, "Vendor\\App", "DisplayVersion").unwrap(),
+            "1.2.3"
+        );
+        // key and value names are case insensitive, `/` works as separator
+        assert_eq!(
+            value(&hive, "vendor/app", "displayversion").unwrap(),
+            "1.2.3"
+        );
+    }
+
+    #[test]
+    fn reads_dword_value() {
+        assert_eq!(value(&fixture(), "Vendor\\App", "Build").unwrap(), "42");
+    }
+
+    #[test]
+    fn reads_default_value() {
+        let hive = fixture();
+        assert_eq!(value(&hive, "Vendor\\App", "").unwrap(), "Example App");
+        assert_eq!(
+            value(&hive, "Vendor\\App", "(Default)").unwrap(),
+            "Example App"
+        );
+    }
+
+    #[test]
+    fn reads_hive_file() {
+        let path = std::env::temp_dir().join(format!("hive_{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, fixture()).unwrap();
+
+        let result = get_registry_value(&path.to_string_lossy(), "Vendor\\App", "Build");
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(result.unwrap(), "42");
+    }
+
+    #[test]
+    fn missing_key_or_value_is_an_error() {
+        let hive = fixture();
+        let err = value(&hive, "Vendor\\Missing", "DisplayVersion").unwrap_err();
+        assert!(err.contains("Key 'Missing' not found"), "{err}");
+        assert!(value(&hive, "Vendor\\Empty", "DisplayVersion").is_err());
+        assert!(value(&hive, "Vendor\\App", "Missing").is_err());
+        assert!(get_registry_value("/nonexistent/hive", "Vendor", "").is_err());
+    }
+
+    #[test]
+    fn truncated_hive_is_an_error() {
+        let hive = fixture();
+        for length in 0..hive.len() {
+            assert!(
+                value(&hive[..length], "Vendor\\App", "DisplayVersion").is_err(),
+                "hive truncated to {length} bytes"
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_hive_is_an_error() {
+        let mut hive = fixture();
+        hive[0..4].copy_from_slice(b"xxxx");
+        assert!(value(&hive, "Vendor\\App", "Build").is_err());
+
+        // root key pointing outside of the hive
+        let mut hive = fixture();
+        hive[0x24..0x28].copy_from_slice(&0x7FFF_FFF0u32.to_le_bytes());
+        assert!(value(&hive, "Vendor\\App", "Build").is_err());
+
+        // unallocated root cell
+        let mut hive = fixture();
+        let root = read_u32(&hive, 0x24).unwrap() as usize + HIVE_BINS_OFFSET;
+        hive[root..root + 4].copy_from_slice(&100i32.to_le_bytes());
+        assert!(value(&hive, "Vendor\\App", "Build").is_err());
+    }
+
+    #[test]
+    fn cyclic_subkey_list_is_an_error() {
+        let mut hive = HiveBuilder::new();
+        // index root which references itself
+        let offset = (hive.data.len() - HIVE_BINS_OFFSET) as u32;
+        let mut list = b"ri".to_vec();
+        list.extend_from_slice(&1u16.to_le_bytes());
+        list.extend_from_slice(&offset.to_le_bytes());
+        hive.cell(&list);
+        let root = hive.key("ROOT", &[0], &[]);
+        let mut data = hive.finish(root);
+
+        // point the root key to the index root instead of the generated list
+        let node = read_u32(&data, 0x24).unwrap() as usize + HIVE_BINS_OFFSET + 4;
+        data[node + 0x1C..node + 0x20].copy_from_slice(&offset.to_le_bytes());
+        assert!(value(&data, "Vendor", "").is_err());
+    }
+}
@@ -0,0 +1,316 @@
+use super::{mqtt_client::MqttClient, run_scan_job};
+use crate::server::{
+    router::scans::{ScanSettings, ScanStatus, MAX_SCAN_WORKERS},
+    store::{self, AppState},
+};
+use log::{error, info};
+use microkv::MicroKV;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex, RwLock},
+};
+use tokio::runtime::Handle;
+use uuid::Uuid;
+
+static DB_KEY_SETTINGS: &str = "scan_settings";
+
+/// Scan of all enabled entries of a path
+#[derive(Debug)]
+pub struct ScanJob {
+    /// Path with forward slashes
+    pub path: String,
+    /// Publish versions even if they did not change
+    pub publish_unchanged: bool,
+    /// Only scan entries of this group (empty for all entries)
+    pub group: String,
+    /// Retry of a single entry after a failed attempt
+    pub retry: Option<(Uuid, ScanAttempt)>,
+}
+
+/// Attempt of a scan, retries of older scans are dropped if a new scan of the entry was started
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScanAttempt {
+    pub generation: u64,
+    pub attempt: u32,
+}
+
+impl ScanJob {
+    /// Scan of all entries of a path
+    pub fn path(path: &str, publish_unchanged: bool) -> Self {
+        ScanJob {
+            path: path.replace('\\', "/"),
+            publish_unchanged,
+            group: String::new(),
+            retry: None,
+        }
+    }
+
+    /// Merges a new request into this queued job, so the path is scanned only once
+    fn merge(&mut self, job: ScanJob) {
+        // a full scan of the path replaces retries, only retries of the same entry are kept
+        self.retry = match (self.retry.take(), job.retry) {
+            (Some((queued, _)), Some((uuid, attempt))) if queued == uuid => Some((uuid, attempt)),
+            _ => None,
+        };
+        self.publish_unchanged |= job.publish_unchanged;
+        if self.group != job.group {
+            self.group.clear();
+        }
+    }
+}
+
+/// Runs scans on dedicated worker threads, so blocking file IO and hashing do not stall the async
+/// runtime (and the http server).
+#[derive(Clone)]
+pub struct ScanPool {
+    shared: Arc<Shared>,
+    store: Arc<RwLock<MicroKV>>,
+    mqtt_client: MqttClient,
+    runtime: Handle,
+    status: Arc<RwLock<ScanStatus>>,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    available: Condvar,
+}
+
+struct Queue {
+    jobs: VecDeque<ScanJob>,
+    /// configured number of workers
+    workers: usize,
+    /// number of running worker threads (above the configured number during a resize)
+    threads: usize,
+    /// paths which are scanned right now, a path is never scanned by two workers at once
+    running: Vec<String>,
+    completed: u64,
+    deduplicated: u64,
+}
+
+impl Queue {
+    /// Appends a job, a job for an already queued path is merged into the queued one. Returns
+    /// `false` if the job was merged.
+    fn push(&mut self, job: ScanJob) -> bool {
+        match self.jobs.iter_mut().find(|queued| queued.path == job.path) {
+            Some(queued) => {
+                queued.merge(job);
+                self.deduplicated += 1;
+                false
+            }
+            None => {
+                self.jobs.push_back(job);
+                true
+            }
+        }
+    }
+}
+
+impl ScanPool {
+    /// Init scan worker pool
+    pub fn init(app_state: &Arc<AppState>, mqtt_client: &MqttClient) -> Self {
+        let settings = store::get::<ScanSettings>(&app_state.db.read().unwrap(), DB_KEY_SETTINGS)
+            .unwrap_or_default();
+
+        let pool = ScanPool {
+            shared: Arc::new(Shared {
+                queue: Mutex::new(Queue {
+                    jobs: VecDeque::new(),
+                    workers: 0,
+                    threads: 0,
+                    running: Vec::new(),
+                    completed: 0,
+                    deduplicated: 0,
+                }),
+                available: Condvar::new(),
+            }),
+            store: app_state.db.clone(),
+            mqtt_client: mqtt_client.clone(),
+            runtime: Handle::current(),
+            status: app_state.scan_status.clone(),
+        };
+        pool.resize(settings.workers);
+
+        pool
+    }
+
+    /// Update the number of workers if the scan settings have changed
+    pub fn refresh(&self) {
+        let settings = store::get::<ScanSettings>(&self.store.read().unwrap(), DB_KEY_SETTINGS)
+            .unwrap_or_default();
+        if self.shared.queue.lock().unwrap().workers != settings.workers {
+            info!("Scan workers changed to {}.", settings.workers);
+            self.resize(settings.workers);
+        }
+    }
+
+    /// Queues a scan, requests for an already queued path are merged into the queued scan
+    pub fn enqueue(&self, job: ScanJob) {
+        let mut queue = self.shared.queue.lock().unwrap();
+        if queue.push(job) {
+            self.shared.available.notify_one();
+        }
+        self.update_status(&queue);
+    }
+
+    fn resize(&self, workers: usize) {
+        let mut queue = self.shared.queue.lock().unwrap();
+        queue.workers = workers.clamp(1, MAX_SCAN_WORKERS);
+
+        while queue.threads < queue.workers {
+            let pool = self.clone();
+            let spawned = std::thread::Builder::new()
+                .name("scan-worker".to_string())
+                .spawn(move || pool.work());
+            if let Err(err) = spawned {
+                error!("Could not start scan worker due to: {err:?}");
+                break;
+            }
+            queue.threads += 1;
+        }
+        // surplus workers stop after their current scan
+        self.shared.available.notify_all();
+        self.update_status(&queue);
+    }
+
+    /// Worker loop, takes the next queued job until the pool gets smaller
+    fn work(self) {
+        // mqtt publishing and deployment tracking spawn tasks on the async runtime
+        let _runtime = self.runtime.enter();
+        let mut mqtt_client = self.mqtt_client.clone();
+
+        loop {
+            let job = {
+                let mut queue = self.shared.queue.lock().unwrap();
+                loop {
+                    if queue.threads > queue.workers {
+                        queue.threads -= 1;
+                        return;
+                    }
+                    let next = queue
+                        .jobs
+                        .iter()
+                        .position(|job| !queue.running.contains(&job.path));
+                    if let Some(job) = next.and_then(|index| queue.jobs.remove(index)) {
+                        queue.running.push(job.path.clone());
+                        self.update_status(&queue);
+                        break job;
+                    }
+                    queue = self.shared.available.wait(queue).unwrap();
+                }
+            };
+
+            run_scan_job(&self.store, &mut mqtt_client, &self, &job);
+
+            let mut queue = self.shared.queue.lock().unwrap();
+            queue.running.retain(|path| *path != job.path);
+            queue.completed += 1;
+            self.update_status(&queue);
+            // queued scans of the same path can run now
+            self.shared.available.notify_all();
+        }
+    }
+
+    fn update_status(&self, queue: &Queue) {
+        *self.status.write().unwrap() = ScanStatus {
+            workers: queue.workers,
+            queued: queue.jobs.len(),
+            running: queue.running.len(),
+            completed: queue.completed,
+            deduplicated: queue.deduplicated,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty_queue() -> Queue {
+        Queue {
+            jobs: VecDeque::new(),
+            workers: 1,
+            threads: 0,
+            running: Vec::new(),
+            completed: 0,
+            deduplicated: 0,
+        }
+    }
+
+    fn retry(uuid: Uuid, attempt: u32) -> ScanJob {
+        ScanJob {
+            retry: Some((
+                uuid,
+                ScanAttempt {
+                    generation: 1,
+                    attempt,
+                },
+            )),
+            ..ScanJob::path("C:/app/app.exe", false)
+        }
+    }
+
+    #[test]
+    fn merges_jobs_of_the_same_path() {
+        let mut queue = empty_queue();
+        assert!(queue.push(ScanJob::path("C:/app/app.exe", false)));
+        assert!(queue.push(ScanJob::path("C:/app/other.dll", false)));
+        // paths are compared with forward slashes
+        assert!(!queue.push(ScanJob::path("C:\\app\\app.exe", false)));
+
+        assert_eq!(queue.jobs.len(), 2);
+        assert_eq!(queue.deduplicated, 1);
+        assert_eq!(queue.jobs[0].path, "C:/app/app.exe");
+    }
+
+    #[test]
+    fn merged_jobs_publish_unchanged_versions_if_one_requested_it() {
+        let mut queue = empty_queue();
+        queue.push(ScanJob::path("C:/app/app.exe", false));
+        queue.push(ScanJob::path("C:/app/app.exe", true));
+        queue.push(ScanJob::path("C:/app/app.exe", false));
+        assert!(queue.jobs[0].publish_unchanged);
+    }
+
+    #[test]
+    fn merged_jobs_of_different_groups_scan_all_entries() {
+        let group = |group: &str| ScanJob {
+            group: group.to_string(),
+            ..ScanJob::path("C:/app/app.exe", false)
+        };
+
+        let mut queue = empty_queue();
+        queue.push(group("plant"));
+        queue.push(group("plant"));
+        assert_eq!(queue.jobs[0].group, "plant");
+        queue.push(group("office"));
+        assert_eq!(queue.jobs[0].group, "");
+    }
+
+    #[test]
+    fn only_retries_of_the_same_entry_stay_retries() {
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+
+        // the newer attempt of the same entry is kept
+        let mut queue = empty_queue();
+        queue.push(retry(first, 1));
+        queue.push(retry(first, 2));
+        assert_eq!(
+            queue.jobs[0]
+                .retry
+                .map(|(uuid, attempt)| (uuid, attempt.attempt)),
+            Some((first, 2))
+        );
+
+        // retries of different entries become a full scan of the path
+        queue.push(retry(second, 1));
+        assert!(queue.jobs[0].retry.is_none());
+
+        // a full scan replaces a retry and is not narrowed by a later retry
+        let mut queue = empty_queue();
+        queue.push(retry(first, 1));
+        queue.push(ScanJob::path("C:/app/app.exe", false));
+        assert!(queue.jobs[0].retry.is_none());
+        queue.push(retry(first, 2));
+        assert!(queue.jobs[0].retry.is_none());
+    }
+}
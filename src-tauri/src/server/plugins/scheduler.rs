@@ -0,0 +1,164 @@
+use super::{parse_utc_timestamp, rescan_files, scan_pool::ScanPool};
+use crate::server::{
+    router::schedules::{parse_cron, Schedules},
+    store::{self, AppState},
+};
+use chrono::{DateTime, Local, Utc};
+use log::{error, info};
+use microkv::MicroKV;
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+static DB_KEY: &str = "schedules";
+
+/// Max time between two checks for due schedules (e.g. after system sleep or clock changes)
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+/// Triggers full rescans of all files or a group of files based on cron expressions.
+pub struct Scheduler {
+    store: Arc<RwLock<MicroKV>>,
+    scan_pool: ScanPool,
+    task: Option<JoinHandle<()>>,
+    current_schedules: Schedules,
+}
+
+/// Enabled schedule with a parsed cron expression
+struct Job {
+    id: Uuid,
+    name: String,
+    cron: cron::Schedule,
+    group: String,
+    force_publish: bool,
+    next_run: Option<DateTime<Utc>>,
+    last_run: Option<DateTime<Utc>>,
+}
+
+impl Scheduler {
+    /// Init scheduler plugin
+    pub fn init(app_state: &Arc<AppState>, scan_pool: &ScanPool) -> Self {
+        let current_schedules =
+            store::get::<Schedules>(&app_state.db.read().unwrap(), DB_KEY).unwrap_or_default();
+
+        let mut scheduler = Scheduler {
+            store: app_state.db.clone(),
+            scan_pool: scan_pool.clone(),
+            task: None,
+            current_schedules,
+        };
+        scheduler.start();
+
+        scheduler
+    }
+
+    /// Restart the scheduler if the schedule config has changed
+    pub fn refresh(&mut self) {
+        let new_schedules =
+            store::get::<Schedules>(&self.store.read().unwrap(), DB_KEY).unwrap_or_default();
+        if self.current_schedules != new_schedules {
+            info!("Scheduler refreshed with new config.");
+            self.current_schedules = new_schedules;
+            self.start();
+        }
+    }
+
+    fn start(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+
+        let store = self.store.clone();
+        let scan_pool = self.scan_pool.clone();
+        self.task = Some(tokio::spawn(run_schedules(store, scan_pool)));
+    }
+}
+
+async fn run_schedules(store: Arc<RwLock<MicroKV>>, scan_pool: ScanPool) {
+    let schedules = store::get::<Schedules>(&store.read().unwrap(), DB_KEY).unwrap_or_default();
+
+    let now = Utc::now();
+    let mut jobs = Vec::new();
+    for schedule in schedules.into_values().filter(|schedule| schedule.enabled) {
+        let cron = match parse_cron(&schedule.cron) {
+            Ok(cron) => cron,
+            Err(err) => {
+                error!("[Schedules] Schedule '{}' skipped: {err}", &schedule.name);
+                continue;
+            }
+        };
+        // a stored next run in the past was missed while the application was stopped,
+        // it gets caught up once right away
+        let next_run =
+            parse_utc_timestamp(&schedule.next_run_utc).or_else(|| next_occurrence(&cron, now));
+
+        jobs.push(Job {
+            id: schedule.id,
+            name: schedule.name,
+            cron,
+            group: schedule.group,
+            force_publish: schedule.force_publish,
+            next_run,
+            last_run: None,
+        });
+    }
+    store_run_times(&store, &jobs);
+
+    loop {
+        let next_run = match jobs.iter().filter_map(|job| job.next_run).min() {
+            Some(next_run) => next_run,
+            None => return,
+        };
+        let wait = (next_run - Utc::now()).to_std().unwrap_or_default();
+        tokio::time::sleep(wait.min(MAX_SLEEP)).await;
+
+        let now = Utc::now();
+        let mut changed = false;
+        for job in jobs
+            .iter_mut()
+            .filter(|job| job.next_run.is_some_and(|next_run| next_run <= now))
+        {
+            info!("[Schedules] Running scheduled rescan '{}'", &job.name);
+            rescan_files(&store, &scan_pool, &job.group, job.force_publish);
+
+            job.last_run = Some(now);
+            job.next_run = next_occurrence(&job.cron, Utc::now());
+            changed = true;
+        }
+
+        if changed {
+            store_run_times(&store, &jobs);
+        }
+    }
+}
+
+/// Returns the next occurrence of a cron expression (evaluated in local time)
+fn next_occurrence(cron: &cron::Schedule, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    cron.after(&after.with_timezone(&Local))
+        .next()
+        .map(|next| next.with_timezone(&Utc))
+}
+
+/// Stores the last and next run times, so they are shown in the api and survive restarts
+fn store_run_times(store: &Arc<RwLock<MicroKV>>, jobs: &[Job]) {
+    let lock = store.write().unwrap();
+    let mut schedules = store::get::<Schedules>(&lock, DB_KEY).unwrap_or_default();
+
+    for job in jobs {
+        if let Some(schedule) = schedules.get_mut(&job.id) {
+            schedule.next_run_utc = job
+                .next_run
+                .map(|next_run| next_run.to_string())
+                .unwrap_or_default();
+            if let Some(last_run) = job.last_run {
+                schedule.last_run_utc = last_run.to_string();
+            }
+        }
+    }
+
+    if let Err(err) = store::put(&lock, DB_KEY, &schedules) {
+        error!("Could not write schedule run times to local DB: {err:?}")
+    }
+}
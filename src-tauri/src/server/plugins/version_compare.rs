@@ -0,0 +1,434 @@
+use crate::server::router::history::VersionChange;
+use std::cmp::Ordering;
+
+/// Version which can be ordered: numeric parts (e.g. `7.2.0.1`, `2023-02-28`) and optional
+/// SemVer pre-release identifiers, package versions additionally have an epoch and a revision.
+#[derive(Debug)]
+struct Version {
+    epoch: u64,
+    parts: Vec<u64>,
+    pre_release: Vec<Identifier>,
+    revision: Vec<RevisionPart>,
+}
+
+/// SemVer pre-release identifier, numeric identifiers have a lower precedence than alphanumeric ones
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Identifier {
+    Numeric(u64),
+    Alphanumeric(String),
+}
+
+/// Part of a package revision (`1ubuntu2` is `1`, `ubuntu`, `2`)
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum RevisionPart {
+    Number(u64),
+    Text(String),
+}
+
+/// Classifies a new version compared to the previous one.
+///
+/// `modified` tells if the source was modified since the last check, which turns an unchanged
+/// version into a reinstall.
+pub fn classify_change(previous: &str, current: &str, modified: bool) -> VersionChange {
+    if previous.is_empty() {
+        return VersionChange::FirstSeen;
+    }
+    if previous == current {
+        return match modified {
+            true => VersionChange::Reinstall,
+            false => VersionChange::Unchanged,
+        };
+    }
+
+    match compare_versions(previous, current) {
+        Some(Ordering::Less) => VersionChange::Upgrade,
+        Some(Ordering::Greater) => VersionChange::Downgrade,
+        // same precedence with different notation or build metadata (e.g. `1.0` and `1.0.0+2`)
+        Some(Ordering::Equal) => VersionChange::Reinstall,
+        None => VersionChange::Changed,
+    }
+}
+
+/// Compares two versions, returns `None` if one of them is no known version format (e.g. a hash).
+///
+/// Supports Windows four-part versions (`7.2.0.1`), SemVer with pre-release and build metadata
+/// (`v1.2.0-rc.1+build.5`), date based versions (`2023-02-28`, `2023.02.28.1`, `20230228`) and
+/// package versions with epoch and revision (`1:2.3-1ubuntu2`, `2.3-1.el8`).
+pub fn compare_versions(a: &str, b: &str) -> Option<Ordering> {
+    Some(parse(a)?.cmp(&parse(b)?))
+}
+
+/// Checks a version against a requirement like `>=1.2, <2.0`, `^1.4`, `~7.2` or `7.2.*`.
+///
+/// Comparators are separated by commas and all need to match. A version without operator is
+/// handled like a caret requirement (`1.4` equals `^1.4`). Returns `None` if the version or the
+/// requirement can not be parsed.
+pub fn matches_requirement(version: &str, requirement: &str) -> Option<bool> {
+    let version = parse(version)?;
+
+    for comparator in requirement.split(',').map(str::trim) {
+        if comparator.is_empty() {
+            continue;
+        }
+        if !matches_comparator(&version, comparator)? {
+            return Some(false);
+        }
+    }
+    Some(true)
+}
+
+fn matches_comparator(version: &Version, comparator: &str) -> Option<bool> {
+    let operator = [">=", "<=", ">", "<", "=", "^", "~"]
+        .into_iter()
+        .find(|operator| comparator.starts_with(operator))
+        .unwrap_or("");
+    let value = comparator[operator.len()..].trim();
+    if value == "*" {
+        return Some(true);
+    }
+
+    // wildcards (`1.2.*`, `1.2.x`) match all versions with the given parts
+    let (value, wildcard) = match value
+        .strip_suffix(".*")
+        .or_else(|| value.strip_suffix(".x"))
+    {
+        Some(value) => (value, true),
+        None => (value, false),
+    };
+    let base = parse(value)?;
+    let given = base.parts.len();
+
+    let matches = match operator {
+        ">=" => *version >= base,
+        "<=" => *version <= base,
+        ">" => *version > base,
+        "<" => *version < base,
+        "=" if !wildcard => *version == base,
+        "=" => *version >= base && *version < base.bump(given - 1),
+        "~" => *version >= base && *version < base.bump(1.min(given - 1)),
+        // caret: changes of the first non-zero part are not compatible
+        _ => {
+            let index = match wildcard {
+                true => given - 1,
+                false => base
+                    .parts
+                    .iter()
+                    .position(|part| *part != 0)
+                    .unwrap_or(given - 1),
+            };
+            *version >= base && *version < base.bump(index)
+        }
+    };
+    Some(matches)
+}
+
+impl Version {
+    /// Returns the next version which increments the part at the index (`1.2.3` -> `1.3`)
+    fn bump(&self, index: usize) -> Version {
+        let mut parts = self.parts.clone();
+        parts.resize(index + 1, 0);
+        parts[index] += 1;
+
+        Version {
+            epoch: self.epoch,
+            parts,
+            pre_release: Vec::new(),
+            revision: Vec::new(),
+        }
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        if self.epoch != other.epoch {
+            return self.epoch.cmp(&other.epoch);
+        }
+
+        // missing parts count as zero (`1.2` equals `1.2.0.0`)
+        let length = self.parts.len().max(other.parts.len());
+        let part = |version: &Version, i: usize| version.parts.get(i).copied().unwrap_or(0);
+        for i in 0..length {
+            match part(self, i).cmp(&part(other, i)) {
+                Ordering::Equal => continue,
+                ordering => return ordering,
+            }
+        }
+
+        // a pre-release has a lower precedence than the release itself
+        let ordering = match (self.pre_release.is_empty(), other.pre_release.is_empty()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) => self.pre_release.cmp(&other.pre_release),
+        };
+        ordering.then_with(|| self.revision.cmp(&other.revision))
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn parse(version: &str) -> Option<Version> {
+    let version = version.trim();
+    // package versions can start with an epoch (`1:2.3-1`)
+    let (epoch, version) = match version.split_once(':') {
+        Some((epoch, version)) => (Some(epoch.parse::<u64>().ok()?), version),
+        None => (None, version),
+    };
+    let version = version.strip_prefix(['v', 'V']).unwrap_or(version);
+    // build metadata is ignored for ordering
+    let version = version
+        .split_once('+')
+        .map_or(version, |(version, _)| version);
+
+    if let Some(version) = parse_date(version) {
+        return Some(Version {
+            epoch: epoch.unwrap_or(0),
+            ..version
+        });
+    }
+
+    // the package revision follows the last hyphen, it is not a pre-release
+    let (version, revision) = match version.rsplit_once('-') {
+        Some((upstream, revision)) if epoch.is_some() || is_package_revision(revision) => {
+            (upstream, parse_revision(revision)?)
+        }
+        _ => (version, Vec::new()),
+    };
+
+    let (core, pre_release) = match version.split_once('-') {
+        Some((core, pre_release)) => (core, Some(pre_release)),
+        None => (version, None),
+    };
+    let parts = parse_numbers(core)?;
+    if parts.len() > 4 {
+        return None;
+    }
+
+    let pre_release = match pre_release {
+        Some(pre_release) => pre_release
+            .split('.')
+            .map(|identifier| match identifier.parse::<u64>() {
+                Ok(number) => Some(Identifier::Numeric(number)),
+                Err(_) if is_identifier(identifier) => {
+                    Some(Identifier::Alphanumeric(identifier.to_string()))
+                }
+                Err(_) => None,
+            })
+            .collect::<Option<Vec<_>>>()?,
+        None => Vec::new(),
+    };
+
+    Some(Version {
+        epoch: epoch.unwrap_or(0),
+        parts,
+        pre_release,
+        revision,
+    })
+}
+
+/// Package revisions start with a number (`1`, `1ubuntu2`, `2.el8`), SemVer pre-releases are
+/// expected to start with a letter (`rc.1`)
+fn is_package_revision(value: &str) -> bool {
+    value.starts_with(|c: char| c.is_ascii_digit())
+}
+
+/// Splits a package revision into numbers and text (`1ubuntu2.1` is `1`, `ubuntu`, `2`, `.`, `1`)
+fn parse_revision(revision: &str) -> Option<Vec<RevisionPart>> {
+    if revision.is_empty()
+        || !revision
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '+' | '~'))
+    {
+        return None;
+    }
+
+    let mut parts = Vec::new();
+    let mut rest = revision;
+    while let Some(first) = rest.chars().next() {
+        let digits = first.is_ascii_digit();
+        let end = rest
+            .find(|c: char| c.is_ascii_digit() != digits)
+            .unwrap_or(rest.len());
+        let part = match digits {
+            true => RevisionPart::Number(rest[..end].parse().ok()?),
+            false => RevisionPart::Text(rest[..end].to_string()),
+        };
+        parts.push(part);
+        rest = &rest[end..];
+    }
+    Some(parts)
+}
+
+/// Parses `YYYY-MM-DD` dates with an optional build counter (`2023-02-28.2`, `2023-02-28_2`)
+fn parse_date(version: &str) -> Option<Version> {
+    let bytes = version.as_bytes();
+    if bytes.len() < 10 || bytes[4] != b'-' || bytes[7] != b'-' || !version.is_char_boundary(10) {
+        return None;
+    }
+
+    let mut parts = parse_numbers(&version[..10].replace('-', "."))?;
+    match version[10..].chars().next() {
+        None => {}
+        Some('.') | Some('-') | Some('_') => parts.extend(parse_numbers(&version[11..])?),
+        Some(_) => return None,
+    }
+
+    Some(Version {
+        epoch: 0,
+        parts,
+        pre_release: Vec::new(),
+        revision: Vec::new(),
+    })
+}
+
+fn parse_numbers(value: &str) -> Option<Vec<u64>> {
+    value
+        .split('.')
+        .map(|part| match part.chars().all(|c| c.is_ascii_digit()) {
+            true => part.parse::<u64>().ok(),
+            false => None,
+        })
+        .collect()
+}
+
+fn is_identifier(value: &str) -> bool {
+    !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_numeric_parts() {
+        assert_eq!(
+            compare_versions("7.2.0.1", "7.10.0.0"),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            compare_versions("7.2.0.10", "7.2.0.9"),
+            Some(Ordering::Greater)
+        );
+        // missing parts count as zero
+        assert_eq!(compare_versions("1.2", "1.2.0.0"), Some(Ordering::Equal));
+        assert_eq!(compare_versions("v1.2.0", "1.2.1"), Some(Ordering::Less));
+        assert_eq!(
+            compare_versions("2023-02-28", "2023-03-01"),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            compare_versions("20230228", "2023.02.28.1"),
+            Some(Ordering::Greater)
+        );
+        // content hashes and too many parts can not be ordered
+        assert_eq!(compare_versions("1a2b3c4d", "1.0"), None);
+        assert_eq!(compare_versions("1.2.3.4.5", "1.0"), None);
+    }
+
+    #[test]
+    fn orders_pre_releases() {
+        let ordered = [
+            "1.0.0-alpha",
+            "1.0.0-alpha.1",
+            "1.0.0-alpha.beta",
+            "1.0.0-beta",
+            "1.0.0-beta.2",
+            "1.0.0-beta.11",
+            "1.0.0-rc.1",
+            "1.0.0",
+        ];
+        for pair in ordered.windows(2) {
+            assert_eq!(
+                compare_versions(pair[0], pair[1]),
+                Some(Ordering::Less),
+                "{} < {}",
+                pair[0],
+                pair[1]
+            );
+        }
+        // build metadata is ignored
+        assert_eq!(
+            compare_versions("1.0.0-rc.1+build.5", "1.0.0-rc.1"),
+            Some(Ordering::Equal)
+        );
+    }
+
+    #[test]
+    fn classifies_upgrades_and_downgrades() {
+        assert_eq!(classify_change("", "1.0", false), VersionChange::FirstSeen);
+        assert_eq!(classify_change("1.0", "1.1", false), VersionChange::Upgrade);
+        assert_eq!(
+            classify_change("1.1", "1.0", false),
+            VersionChange::Downgrade
+        );
+        assert_eq!(
+            classify_change("2.0.0", "2.0.0-rc.1", false),
+            VersionChange::Downgrade
+        );
+        assert_eq!(
+            classify_change("1.0", "1.0", false),
+            VersionChange::Unchanged
+        );
+        assert_eq!(
+            classify_change("1.0", "1.0", true),
+            VersionChange::Reinstall
+        );
+        assert_eq!(
+            classify_change("1.0", "1.0.0", false),
+            VersionChange::Reinstall
+        );
+        assert_eq!(
+            classify_change("1a2b3c", "4d5e6f", false),
+            VersionChange::Changed
+        );
+    }
+
+    #[test]
+    fn compares_package_versions() {
+        assert_eq!(
+            classify_change("1:2.3-1ubuntu2", "1:2.3-1ubuntu3", false),
+            VersionChange::Upgrade
+        );
+        assert_eq!(
+            classify_change("2.3-1ubuntu2", "2.3-1ubuntu1", false),
+            VersionChange::Downgrade
+        );
+        // the epoch comes first
+        assert_eq!(
+            compare_versions("2:1.0-1", "1:9.9-1"),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            compare_versions("1:2.3-1ubuntu2", "2.4"),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(compare_versions("0:2.3-1", "2.3-1"), Some(Ordering::Equal));
+        // revisions are compared after the upstream version
+        assert_eq!(compare_versions("2.3-1", "2.3"), Some(Ordering::Greater));
+        assert_eq!(
+            compare_versions("2.3-2.el8", "2.4-1.el8"),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            compare_versions("1:2.3-1ubuntu2.1", "1:2.3-1ubuntu2"),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            compare_versions("1:2.3-rc.1-1", "1:2.3-1"),
+            Some(Ordering::Less)
+        );
+        assert_eq!(compare_versions("a:2.3-1", "2.3-1"), None);
+    }
+}
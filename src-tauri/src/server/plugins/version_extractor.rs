@@ -0,0 +1,41 @@
+use crate::server::router::files::VersionExtractor;
+use regex::Regex;
+
+/// Extracts a version from a text output (e.g. command stdout or http response body).
+pub fn extract_version(output: &str, extractor: &VersionExtractor) -> Result<String, String> {
+    let version = match extractor {
+        VersionExtractor::Raw => output.trim().to_string(),
+        VersionExtractor::Regex { pattern } => {
+            let regex = Regex::new(pattern)
+                .map_err(|err| format!("[Extract Version] Invalid regex: {err}"))?;
+            let captures = regex.captures(output).ok_or_else(|| {
+                format!("[Extract Version] Pattern '{pattern}' does not match output.")
+            })?;
+
+            // use first capture group if available, otherwise the whole match
+            captures
+                .get(1)
+                .or_else(|| captures.get(0))
+                .map(|m| m.as_str().trim().to_string())
+                .unwrap_or_default()
+        }
+        VersionExtractor::JsonPointer { pointer } => {
+            let json = serde_json::from_str::<serde_json::Value>(output)
+                .map_err(|err| format!("[Extract Version] Output is not valid json: {err}"))?;
+            match json.pointer(pointer) {
+                Some(serde_json::Value::String(version)) => version.clone(),
+                Some(value) => value.to_string(),
+                None => {
+                    return Err(format!(
+                        "[Extract Version] No value found at json pointer '{pointer}'."
+                    ))
+                }
+            }
+        }
+    };
+
+    if version.is_empty() {
+        return Err("[Extract Version] Extracted version is empty.".to_string());
+    }
+    Ok(version)
+}
@@ -0,0 +1,442 @@
+use crate::server::{
+    router::{
+        files::{FileLifecycle, Files},
+        settings::DBError,
+    },
+    store::{self, AppState},
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::{delete, get},
+    Json, Router,
+};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+static DB_KEY: &str = "baselines";
+static DB_KEY_FILES: &str = "files";
+
+/// exports all routes from this module as router
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/baselines", get(baselines_index).post(baselines_create))
+        .route("/baselines/:id", delete(baselines_delete))
+        .route("/baselines/:id/diff", get(baselines_diff))
+}
+
+/// List all baselines.
+///
+/// Returns all captured baselines with the state of their files.
+#[utoipa::path(
+        get,
+        context_path = "/api",
+        path = "/baselines",
+        tag = "baselines",
+        responses(
+            (status = 200, description = "List all baselines successfully", body = [Baseline])
+        )
+    )]
+pub async fn baselines_index(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let mut baselines = store::get::<Baselines>(&state.db.read().unwrap(), DB_KEY)
+        .unwrap_or_default()
+        .into_values()
+        .collect::<Vec<_>>();
+    baselines.sort_by(|a, b| a.created_utc.cmp(&b.created_utc));
+
+    (StatusCode::OK, Json(baselines))
+}
+
+/// Body params for capturing a new baseline
+#[derive(Debug, Deserialize, IntoParams, ToSchema)]
+pub struct BaselineCreateParams {
+    /// Name of the baseline
+    #[schema(example = "Before maintenance 2023-03")]
+    name: String,
+}
+
+/// Capture a new baseline.
+///
+/// Freezes the name, version, hash, lifecycle state and timestamp of the last scan of every file.
+#[utoipa::path(
+        post,
+        context_path = "/api",
+        path = "/baselines",
+        tag = "baselines",
+        request_body = BaselineCreateParams,
+        responses(
+            (status = 201, description = "Baseline captured successfully", body = Baseline),
+            (status = 500, description = "Error on DB write operation", body = DBError, example = json!(DBError::WriteError(String::from("Could not write data to file"))))
+        )
+    )]
+pub async fn baselines_create(
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<BaselineCreateParams>,
+) -> impl IntoResponse {
+    let lock = state.db.write().unwrap();
+    let files = store::get::<Files>(&lock, DB_KEY_FILES).unwrap();
+
+    let mut entries = files
+        .into_values()
+        .map(|file| BaselineEntry {
+            id: file.id,
+            name: file.name,
+            path: file.path,
+            version: file.last_version,
+            hash: file.last_hash,
+            lifecycle: file.lifecycle,
+            timestamp_utc: file.last_update_utc,
+        })
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let baseline = Baseline {
+        id: Uuid::new_v4(),
+        name: input.name,
+        created_utc: chrono::offset::Utc::now().to_string(),
+        files: entries,
+    };
+
+    let mut baselines = store::get::<Baselines>(&lock, DB_KEY).unwrap_or_default();
+    baselines.insert(baseline.id, baseline.clone());
+
+    info!(
+        "[Baselines] Baseline '{}' captured with {} files",
+        &baseline.name,
+        baseline.files.len()
+    );
+
+    if let Err(err) = store::put(&lock, DB_KEY, &baselines) {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(DBError::WriteError(format!("{:?}", err))),
+        )
+            .into_response();
+    };
+
+    (StatusCode::CREATED, Json(baseline)).into_response()
+}
+
+/// Delete a baseline.
+///
+/// Deletes a single baseline by its id.
+#[utoipa::path(
+        delete,
+        context_path = "/api",
+        path = "/baselines/{id}",
+        tag = "baselines",
+        params(
+            ("id" = Uuid, Path, description = "Baseline id")
+        ),
+        responses(
+            (status = 204, description = "Baseline deleted successfully"),
+            (status = 404, description = "Baseline not found", body = DBError, example = json!(DBError::KeyNotFound(String::from("key not found in storage")))),
+            (status = 500, description = "Error on DB write operation", body = DBError, example = json!(DBError::WriteError(String::from("Could not write data to file"))))
+        )
+    )]
+pub async fn baselines_delete(
+    Path(id): Path<Uuid>,
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let lock = state.db.write().unwrap();
+    let mut baselines = store::get::<Baselines>(&lock, DB_KEY).unwrap_or_default();
+
+    if baselines.remove(&id).is_none() {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(DBError::KeyNotFound("key not found in storage".to_string())),
+        )
+            .into_response();
+    }
+
+    match store::put(&lock, DB_KEY, &baselines) {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(DBError::WriteError(format!("{:?}", err))),
+        )
+            .into_response(),
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct DiffQuery {
+    /// Export format of the diff (defaults to json)
+    format: Option<DiffFormat>,
+}
+
+/// Diff export format
+#[derive(Deserialize, ToSchema, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffFormat {
+    Json,
+    Csv,
+}
+
+/// Compare a baseline with the current state.
+///
+/// Lists all files whose version or hash changed since the baseline was captured, files which
+/// disappeared from or appeared on disk, and entries which were added or removed.
+#[utoipa::path(
+        get,
+        context_path = "/api",
+        path = "/baselines/{id}/diff",
+        tag = "baselines",
+        params(
+            ("id" = Uuid, Path, description = "Baseline id"),
+            DiffQuery
+        ),
+        responses(
+            (status = 200, description = "Diff created successfully (json or csv)", body = BaselineDiff),
+            (status = 404, description = "Baseline not found", body = DBError, example = json!(DBError::KeyNotFound(String::from("key not found in storage"))))
+        )
+    )]
+pub async fn baselines_diff(
+    Path(id): Path<Uuid>,
+    query: Query<DiffQuery>,
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let lock = state.db.read().unwrap();
+    let baseline = match store::get::<Baselines>(&lock, DB_KEY)
+        .unwrap_or_default()
+        .remove(&id)
+    {
+        Some(baseline) => baseline,
+        None => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(DBError::KeyNotFound("key not found in storage".to_string())),
+            )
+                .into_response()
+        }
+    };
+    let files = store::get::<Files>(&lock, DB_KEY_FILES).unwrap();
+    let diff = create_diff(baseline, files);
+
+    match query.format.unwrap_or(DiffFormat::Json) {
+        DiffFormat::Json => (StatusCode::OK, Json(diff)).into_response(),
+        DiffFormat::Csv => (
+            StatusCode::OK,
+            [
+                (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"baseline-{id}-diff.csv\""),
+                ),
+            ],
+            diff_to_csv(&diff),
+        )
+            .into_response(),
+    }
+}
+
+/// Compares the baseline entries with the current files by their id
+fn create_diff(baseline: Baseline, mut files: Files) -> BaselineDiff {
+    let mut changes = Vec::new();
+
+    for entry in baseline.files {
+        match files.remove(&entry.id) {
+            Some(file) => {
+                let kind = if on_disk(entry.lifecycle) && !on_disk(file.lifecycle) {
+                    DriftKind::DisappearedFromDisk
+                } else if !on_disk(entry.lifecycle) && on_disk(file.lifecycle) {
+                    DriftKind::AppearedOnDisk
+                } else if file.last_version != entry.version {
+                    DriftKind::VersionChanged
+                } else if file.last_hash != entry.hash {
+                    DriftKind::HashChanged
+                } else {
+                    continue;
+                };
+                changes.push(DriftEntry {
+                    id: entry.id,
+                    name: file.name,
+                    path: file.path,
+                    kind,
+                    baseline_version: entry.version,
+                    current_version: file.last_version,
+                    baseline_hash: entry.hash,
+                    current_hash: file.last_hash,
+                    baseline_lifecycle: Some(entry.lifecycle),
+                    current_lifecycle: Some(file.lifecycle),
+                });
+            }
+            None => changes.push(DriftEntry {
+                id: entry.id,
+                name: entry.name,
+                path: entry.path,
+                kind: DriftKind::Disappeared,
+                baseline_version: entry.version,
+                current_version: String::new(),
+                baseline_hash: entry.hash,
+                current_hash: String::new(),
+                baseline_lifecycle: Some(entry.lifecycle),
+                current_lifecycle: None,
+            }),
+        }
+    }
+
+    // remaining files were added after the baseline was captured
+    for file in files.into_values() {
+        changes.push(DriftEntry {
+            id: file.id,
+            name: file.name,
+            path: file.path,
+            kind: DriftKind::Appeared,
+            baseline_version: String::new(),
+            current_version: file.last_version,
+            baseline_hash: String::new(),
+            current_hash: file.last_hash,
+            baseline_lifecycle: None,
+            current_lifecycle: Some(file.lifecycle),
+        });
+    }
+    changes.sort_by(|a, b| a.name.cmp(&b.name));
+
+    BaselineDiff {
+        baseline_id: baseline.id,
+        baseline_name: baseline.name,
+        baseline_created_utc: baseline.created_utc,
+        changes,
+    }
+}
+
+/// Missing files and files whose directory does not exist yet are not on disk
+fn on_disk(lifecycle: FileLifecycle) -> bool {
+    matches!(lifecycle, FileLifecycle::Present | FileLifecycle::Restored)
+}
+
+fn diff_to_csv(diff: &BaselineDiff) -> String {
+    let mut csv = String::from(
+        "id,name,path,change,baseline_version,current_version,baseline_hash,current_hash,baseline_lifecycle,current_lifecycle\r\n",
+    );
+    for change in &diff.changes {
+        let fields = [
+            change.id.to_string(),
+            change.name.clone(),
+            change.path.clone(),
+            enum_name(&change.kind),
+            change.baseline_version.clone(),
+            change.current_version.clone(),
+            change.baseline_hash.clone(),
+            change.current_hash.clone(),
+            enum_name(&change.baseline_lifecycle),
+            enum_name(&change.current_lifecycle),
+        ];
+        csv.push_str(
+            &fields
+                .iter()
+                .map(|field| csv_field(field))
+                .collect::<Vec<_>>()
+                .join(","),
+        );
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+/// Serialized name of an enum value (empty for none)
+fn enum_name<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(|value| value.to_string()))
+        .unwrap_or_default()
+}
+
+/// Quotes a csv field if it contains separators, quotes or line breaks. Fields which spreadsheets
+/// would evaluate as formula get a leading `'`.
+fn csv_field(value: &str) -> String {
+    let value = match value.starts_with(['=', '+', '-', '@']) {
+        true => format!("'{value}"),
+        false => value.to_string(),
+    };
+    match value.contains([',', '"', '\r', '\n']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value,
+    }
+}
+
+/// Baseline schema.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct Baseline {
+    pub id: Uuid,
+    #[schema(example = "Before maintenance 2023-03")]
+    pub name: String,
+    #[schema(example = "2023-02-28 12:00:00 UTC")]
+    pub created_utc: String,
+    /// State of all files when the baseline was captured
+    pub files: Vec<BaselineEntry>,
+}
+
+/// Baseline entry schema.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct BaselineEntry {
+    /// File id
+    pub id: Uuid,
+    #[schema(example = "ExampleFile.dll")]
+    pub name: String,
+    #[schema(example = "C:\\win\\doof")]
+    pub path: String,
+    #[schema(example = "7.2.0.0")]
+    pub version: String,
+    /// Content hash of the file (empty for sources without a local file)
+    pub hash: String,
+    /// Lifecycle state of the file (if it existed on disk)
+    #[serde(default)]
+    pub lifecycle: FileLifecycle,
+    /// Timestamp of the last scan
+    #[schema(example = "2023-02-28 12:00:00 UTC")]
+    pub timestamp_utc: String,
+}
+
+/// Baseline diff schema.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct BaselineDiff {
+    pub baseline_id: Uuid,
+    pub baseline_name: String,
+    pub baseline_created_utc: String,
+    /// Changed, appeared and disappeared files
+    pub changes: Vec<DriftEntry>,
+}
+
+/// Drift entry schema.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct DriftEntry {
+    /// File id
+    pub id: Uuid,
+    pub name: String,
+    pub path: String,
+    pub kind: DriftKind,
+    pub baseline_version: String,
+    pub current_version: String,
+    pub baseline_hash: String,
+    pub current_hash: String,
+    /// Lifecycle state in the baseline (none if the entry was added afterwards)
+    pub baseline_lifecycle: Option<FileLifecycle>,
+    /// Current lifecycle state (none if the entry was removed)
+    pub current_lifecycle: Option<FileLifecycle>,
+}
+
+/// Drift kind schema.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum DriftKind {
+    /// Version changed (the hash may have changed as well)
+    VersionChanged,
+    /// Only the content hash changed
+    HashChanged,
+    /// File was added after the baseline was captured
+    Appeared,
+    /// File was removed after the baseline was captured
+    Disappeared,
+    /// File existed on disk when the baseline was captured and is missing now
+    DisappearedFromDisk,
+    /// File was missing on disk when the baseline was captured and exists now
+    AppearedOnDisk,
+}
+
+pub type Baselines = HashMap<Uuid, Baseline>;
@@ -0,0 +1,126 @@
+use crate::server::{
+    router::files::Files,
+    store::{self, AppState},
+};
+use axum::{extract::State, http::StatusCode, response::IntoResponse, routing::get, Json, Router};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+static DB_KEY: &str = "files";
+
+/// exports all routes from this module as router
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new().route("/compliance", get(compliance_index))
+}
+
+/// Get the compliance report.
+///
+/// Summarizes the compliance state of all enabled files evaluated on their last scan.
+#[utoipa::path(
+        get,
+        context_path = "/api",
+        path = "/compliance",
+        tag = "compliance",
+        responses(
+            (status = 200, description = "Get compliance report successfully", body = ComplianceReport)
+        )
+    )]
+pub async fn compliance_index(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let files = store::get::<Files>(&state.db.read().unwrap(), DB_KEY).unwrap();
+
+    let mut report = ComplianceReport::default();
+    for file in files.into_values().filter(|file| file.enabled) {
+        match file.compliance {
+            Compliance::Compliant => report.compliant += 1,
+            Compliance::NonCompliant => report.non_compliant += 1,
+            Compliance::Unknown => report.unknown += 1,
+        }
+        report.files.push(FileCompliance {
+            id: file.id,
+            name: file.name,
+            version: file.last_version,
+            policy: file.policy,
+            compliance: file.compliance,
+        });
+    }
+    report.files.sort_by(|a, b| a.name.cmp(&b.name));
+
+    (StatusCode::OK, Json(report))
+}
+
+/// Compliance policy schema.
+///
+/// Defines the approved versions of a file.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CompliancePolicy {
+    /// No approved versions defined
+    #[default]
+    None,
+    /// Exactly one approved version
+    Exact {
+        #[schema(example = "7.2.0.0")]
+        version: String,
+    },
+    /// Version requirement with comma separated comparators (`>=`, `<=`, `>`, `<`, `=`, `^`, `~`, `*`)
+    Requirement {
+        #[schema(example = ">=7.2, <8")]
+        requirement: String,
+    },
+    /// Inclusive version range, an empty bound is open
+    Range {
+        #[schema(example = "7.2.0.0")]
+        min: String,
+        #[schema(example = "7.9.0.0")]
+        max: String,
+    },
+    /// List of approved versions
+    Allowlist {
+        #[schema(example = json!(["7.2.0.0", "7.2.1.0"]))]
+        versions: Vec<String>,
+    },
+}
+
+/// Compliance state schema.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Compliance {
+    /// Version is approved by the policy
+    Compliant,
+    /// Version is not approved by the policy
+    NonCompliant,
+    /// No policy, no version or the version can not be compared
+    #[default]
+    Unknown,
+}
+
+/// Compliance report schema.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Default)]
+pub struct ComplianceReport {
+    /// Number of compliant files
+    #[schema(example = "12")]
+    pub compliant: u32,
+    /// Number of non-compliant files
+    #[schema(example = "1")]
+    pub non_compliant: u32,
+    /// Number of files with unknown compliance
+    #[schema(example = "3")]
+    pub unknown: u32,
+    /// Compliance state of each enabled file
+    pub files: Vec<FileCompliance>,
+}
+
+/// File compliance schema.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct FileCompliance {
+    pub id: Uuid,
+    #[schema(example = "ExampleFile.dll")]
+    pub name: String,
+    /// Version of the last scan
+    #[schema(example = "7.2.0.0")]
+    pub version: String,
+    pub policy: CompliancePolicy,
+    pub compliance: Compliance,
+}
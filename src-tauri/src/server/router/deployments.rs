@@ -0,0 +1,250 @@
+use crate::server::{
+    router::{history::VersionChange, settings::DBError},
+    store::{self, AppState},
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+static DB_KEY: &str = "deployments";
+static DB_KEY_SETTINGS: &str = "deployment_settings";
+
+/// exports all routes from this module as router
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/deployments", get(deployments_index))
+        .route("/deployments/:id", get(deployments_show))
+        .route(
+            "/settings/deployments",
+            get(deployment_settings_index).patch(deployment_settings_update),
+        )
+}
+
+/// List all deployments.
+///
+/// Returns the latest deployments (newest first) with the version changes of their files.
+#[utoipa::path(
+        get,
+        context_path = "/api",
+        path = "/deployments",
+        tag = "deployments",
+        responses(
+            (status = 200, description = "List deployments successfully", body = [Deployment])
+        )
+    )]
+pub async fn deployments_index(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let mut deployments =
+        store::get::<Deployments>(&state.db.read().unwrap(), DB_KEY).unwrap_or_default();
+    deployments.reverse();
+
+    (StatusCode::OK, Json(deployments))
+}
+
+/// Show a deployment.
+///
+/// Returns a single deployment by its id.
+#[utoipa::path(
+        get,
+        context_path = "/api",
+        path = "/deployments/{id}",
+        tag = "deployments",
+        params(
+            ("id" = Uuid, Path, description = "Deployment id")
+        ),
+        responses(
+            (status = 200, description = "Show deployment successfully", body = Deployment),
+            (status = 404, description = "Deployment not found", body = DBError, example = json!(DBError::KeyNotFound(String::from("key not found in storage"))))
+        )
+    )]
+pub async fn deployments_show(
+    Path(id): Path<Uuid>,
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let deployments =
+        store::get::<Deployments>(&state.db.read().unwrap(), DB_KEY).unwrap_or_default();
+
+    match deployments
+        .into_iter()
+        .find(|deployment| deployment.id == id)
+    {
+        Some(deployment) => (StatusCode::OK, Json(deployment)).into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(DBError::KeyNotFound("key not found in storage".to_string())),
+        )
+            .into_response(),
+    }
+}
+
+/// Show deployment settings.
+///
+/// Returns how version changes are grouped into deployments.
+#[utoipa::path(
+        get,
+        context_path = "/api",
+        path = "/settings/deployments",
+        tag = "deployments",
+        responses(
+            (status = 200, description = "Show deployment settings successfully", body = DeploymentSettings)
+        )
+    )]
+pub async fn deployment_settings_index(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let settings = store::get::<DeploymentSettings>(&state.db.read().unwrap(), DB_KEY_SETTINGS)
+        .unwrap_or_default();
+
+    (StatusCode::OK, Json(settings))
+}
+
+/// Parameters for updating the deployment settings
+#[derive(Debug, Deserialize, IntoParams, ToSchema)]
+pub struct DeploymentSettingsUpdateParams {
+    /// Group version changes into deployments
+    #[schema(example = "true")]
+    enabled: Option<bool>,
+    /// Seconds without further changes after which a deployment is closed
+    #[schema(example = "30")]
+    window_secs: Option<u64>,
+    /// Separate deployments for each file group
+    #[schema(example = "false")]
+    by_group: Option<bool>,
+    /// MQTT topic of the deployment summaries (empty to disable them)
+    #[schema(example = "deployments")]
+    mqtt_topic: Option<String>,
+    /// Only publish the deployment summary instead of a message for each changed file
+    #[schema(example = "true")]
+    summary_only: Option<bool>,
+}
+
+/// Update deployment settings.
+///
+/// Changes apply to deployments which are started afterwards.
+#[utoipa::path(
+        patch,
+        context_path = "/api",
+        path = "/settings/deployments",
+        tag = "deployments",
+        request_body = DeploymentSettingsUpdateParams,
+        responses(
+            (status = 200, description = "Deployment settings updated successfully", body = DeploymentSettings),
+            (status = 500, description = "Error on DB write operation", body = DBError, example = json!(DBError::WriteError(String::from("Could not write data to file"))))
+        )
+    )]
+pub async fn deployment_settings_update(
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<DeploymentSettingsUpdateParams>,
+) -> impl IntoResponse {
+    let lock = state.db.write().unwrap();
+    let mut settings = store::get::<DeploymentSettings>(&lock, DB_KEY_SETTINGS).unwrap_or_default();
+
+    if let Some(enabled) = input.enabled {
+        settings.enabled = enabled;
+    }
+
+    if let Some(window_secs) = input.window_secs {
+        settings.window_secs = window_secs;
+    }
+
+    if let Some(by_group) = input.by_group {
+        settings.by_group = by_group;
+    }
+
+    if let Some(mqtt_topic) = input.mqtt_topic {
+        settings.mqtt_topic = mqtt_topic;
+    }
+
+    if let Some(summary_only) = input.summary_only {
+        settings.summary_only = summary_only;
+    }
+
+    info!("[Deployments] Settings changed to: {:?}", &settings);
+
+    match store::put(&lock, DB_KEY_SETTINGS, &settings) {
+        Ok(()) => (StatusCode::OK, Json(settings)).into_response(),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(DBError::WriteError(format!("{:?}", err))),
+        )
+            .into_response(),
+    }
+}
+
+/// Deployment settings schema.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct DeploymentSettings {
+    pub enabled: bool,
+    /// Seconds without further changes after which a deployment is closed
+    #[schema(example = "30")]
+    pub window_secs: u64,
+    /// Separate deployments for each file group
+    pub by_group: bool,
+    /// MQTT topic of the deployment summaries (empty to disable them)
+    #[schema(example = "deployments")]
+    pub mqtt_topic: String,
+    /// Only publish the deployment summary instead of a message for each changed file (ignored
+    /// while the summaries are disabled)
+    #[serde(default = "default_summary_only")]
+    pub summary_only: bool,
+}
+
+fn default_summary_only() -> bool {
+    true
+}
+
+impl Default for DeploymentSettings {
+    fn default() -> Self {
+        DeploymentSettings {
+            enabled: true,
+            window_secs: 30,
+            by_group: false,
+            mqtt_topic: "deployments".to_string(),
+            summary_only: true,
+        }
+    }
+}
+
+/// Deployment schema.
+///
+/// Version changes of multiple files which happened in a short time (e.g. by one installer run).
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct Deployment {
+    pub id: Uuid,
+    /// File group of the deployment (empty if not grouped by file group)
+    #[schema(example = "backend")]
+    pub group: String,
+    /// Timestamp of the first change
+    #[schema(example = "2023-02-28 12:00:00 UTC")]
+    pub start_utc: String,
+    /// Timestamp of the last change
+    #[schema(example = "2023-02-28 12:00:12 UTC")]
+    pub end_utc: String,
+    /// Version changes in the order they were detected
+    pub transitions: Vec<FileTransition>,
+}
+
+/// File transition schema.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct FileTransition {
+    /// File id
+    pub id: Uuid,
+    #[schema(example = "ExampleFile.dll")]
+    pub name: String,
+    #[schema(example = "7.1.3.0")]
+    pub previous_version: String,
+    #[schema(example = "7.2.0.0")]
+    pub version: String,
+    pub change: VersionChange,
+    #[schema(example = "2023-02-28 12:00:05 UTC")]
+    pub timestamp_utc: String,
+}
+
+/// Closed deployments, oldest first
+pub type Deployments = Vec<Deployment>;
@@ -1,4 +1,11 @@
-use crate::server::store::AppState;
+use super::{
+    compliance::{Compliance, CompliancePolicy},
+    settings::{Broker, MqttQos},
+};
+use crate::server::{
+    plugins::{command_reader, compliance_checker},
+    store::{self, AppState},
+};
 use axum::{
     extract::{Path, State, TypedHeader},
     headers,
@@ -43,11 +50,7 @@ pub fn routes() -> Router<Arc<AppState>> {
         )
     )]
 pub async fn files_index(State(state): State<Arc<AppState>>) -> impl IntoResponse {
-    let files = state
-        .db
-        .read()
-        .unwrap()
-        .get_unwrap::<Files>(DB_KEY)
+    let files = store::get::<Files>(&state.db.read().unwrap(), DB_KEY)
         .unwrap()
         .values()
         .cloned()
@@ -71,6 +74,29 @@ pub struct FileCreateParams {
     /// Mqtt topic on which the current file version gets sent
     #[schema(example = "eh/test/topic")]
     mqtt_topic: String,
+    /// Source from which the version gets read (defaults to the file itself)
+    source: Option<VersionSource>,
+    /// Algorithm for content hashes of files without file properties (defaults to sha256)
+    hash_algorithm: Option<HashAlgorithm>,
+    /// Approved versions of this file (defaults to no policy)
+    policy: Option<CompliancePolicy>,
+    /// How the file gets watched for changes (defaults to native file system events)
+    watch_mode: Option<WatchMode>,
+    /// Group of the file, used for scheduled rescans
+    #[schema(example = "backend")]
+    group: Option<String>,
+    /// Quiet period in milliseconds after the last change event before the file gets checked
+    /// (0 for the default of 500 ms)
+    #[schema(example = "2000")]
+    debounce_ms: Option<u64>,
+    /// Follow renames of the file to a new name inside of the same directory
+    #[schema(example = "false")]
+    follow_renames: Option<bool>,
+    /// Quality of service of the version messages (defaults to the broker setting)
+    mqtt_qos: Option<MqttQos>,
+    /// Publish the version messages as retained messages (defaults to the broker setting)
+    #[schema(example = "true")]
+    mqtt_retain: Option<bool>,
 }
 /// Add a new file.
 ///
@@ -100,25 +126,35 @@ pub async fn files_create(
         last_version: "".to_string(),
         path: input.path,
         mqtt_topic: input.mqtt_topic,
+        source: input.source.unwrap_or_default(),
+        hash_algorithm: input.hash_algorithm.unwrap_or_default(),
+        policy: input.policy.unwrap_or_default(),
+        compliance: Compliance::Unknown,
+        last_hash: "".to_string(),
+        watch_mode: input.watch_mode.unwrap_or_default(),
+        group: input.group.unwrap_or_default(),
+        debounce_ms: input.debounce_ms.unwrap_or_default(),
+        follow_renames: input.follow_renames.unwrap_or(false),
+        mqtt_qos: input.mqtt_qos,
+        mqtt_retain: input.mqtt_retain,
+        lifecycle: FileLifecycle::Present,
+        missing_since_utc: "".to_string(),
+        command_error: None,
     };
 
     // update hash map
     let lock = state.db.write().unwrap();
-    let mut files = lock.get_unwrap::<Files>(DB_KEY).unwrap();
+    let mut files = store::get::<Files>(&lock, DB_KEY).unwrap();
 
     files.insert(file.id, file.clone());
 
     // log new file entry
     info!("[Files] New file added: {:?}", &file);
 
-    if let Err(err) = lock.put(DB_KEY, &files) {
+    if let Err(err) = store::put(&lock, DB_KEY, &files) {
         return (
             StatusCode::INTERNAL_SERVER_ERROR,
-            Json(DBError::WriteError(format!(
-                "{:?}",
-                err.msg
-                    .unwrap_or("Could not write data to file".to_string())
-            ))),
+            Json(DBError::WriteError(format!("{:?}", err))),
         )
             .into_response();
     };
@@ -150,6 +186,28 @@ pub struct FileUpdateParams {
     /// Latest file version
     #[schema(example = "7.2.0.0")]
     last_version: Option<String>,
+    /// Source from which the version gets read
+    source: Option<VersionSource>,
+    /// Algorithm for content hashes of files without file properties
+    hash_algorithm: Option<HashAlgorithm>,
+    /// Approved versions of this file
+    policy: Option<CompliancePolicy>,
+    /// How the file gets watched for changes
+    watch_mode: Option<WatchMode>,
+    /// Group of the file, used for scheduled rescans
+    #[schema(example = "backend")]
+    group: Option<String>,
+    /// Quiet period in milliseconds after the last change event (0 for the default)
+    #[schema(example = "2000")]
+    debounce_ms: Option<u64>,
+    /// Follow renames of the file to a new name inside of the same directory
+    #[schema(example = "true")]
+    follow_renames: Option<bool>,
+    /// Quality of service of the version messages
+    mqtt_qos: Option<MqttQos>,
+    /// Publish the version messages as retained messages
+    #[schema(example = "true")]
+    mqtt_retain: Option<bool>,
 }
 /// Update a file.
 ///
@@ -174,7 +232,7 @@ async fn files_update(
     Json(input): Json<FileUpdateParams>,
 ) -> impl IntoResponse {
     let lock = state.db.write().unwrap();
-    let mut files = lock.get_unwrap::<Files>(DB_KEY).unwrap();
+    let mut files = store::get::<Files>(&lock, DB_KEY).unwrap();
 
     if let Some(file) = files.get_mut(&id) {
         if let Some(name) = input.name {
@@ -186,8 +244,12 @@ async fn files_update(
         }
 
         if let Some(enabled) = input.enabled {
-            // only enable file if file path is valid
-            if std::path::Path::new(&file.path).exists() {
+            // only enable file if file path is valid (command binaries can be found in the search path)
+            let path = match file.source {
+                VersionSource::Command { .. } => command_reader::resolve_binary(&file.path),
+                _ => std::path::PathBuf::from(&file.path),
+            };
+            if !file.source.has_local_path() || path.exists() {
                 file.enabled = enabled;
             } else {
                 file.enabled = false;
@@ -210,6 +272,50 @@ async fn files_update(
             file.last_version = last_version;
         }
 
+        if let Some(source) = input.source {
+            // the error of a previous command source does not apply to a new source
+            if source != file.source {
+                file.command_error = None;
+            }
+            file.source = source;
+        }
+
+        if let Some(hash_algorithm) = input.hash_algorithm {
+            file.hash_algorithm = hash_algorithm;
+        }
+
+        if let Some(policy) = input.policy {
+            file.policy = policy;
+            // the last version is checked against the new policy right away, missing files stay unknown
+            if file.lifecycle != FileLifecycle::Missing {
+                file.compliance = compliance_checker::evaluate(&file.policy, &file.last_version);
+            }
+        }
+
+        if let Some(watch_mode) = input.watch_mode {
+            file.watch_mode = watch_mode;
+        }
+
+        if let Some(group) = input.group {
+            file.group = group;
+        }
+
+        if let Some(debounce_ms) = input.debounce_ms {
+            file.debounce_ms = debounce_ms;
+        }
+
+        if let Some(follow_renames) = input.follow_renames {
+            file.follow_renames = follow_renames;
+        }
+
+        if let Some(mqtt_qos) = input.mqtt_qos {
+            file.mqtt_qos = Some(mqtt_qos);
+        }
+
+        if let Some(mqtt_retain) = input.mqtt_retain {
+            file.mqtt_retain = Some(mqtt_retain);
+        }
+
         // log changes
         info!("[Files] File config changed to: {:?}", &file);
     } else {
@@ -222,15 +328,11 @@ async fn files_update(
     }
 
     // write to file db
-    match lock.put(DB_KEY, &files) {
+    match store::put(&lock, DB_KEY, &files) {
         Ok(()) => (StatusCode::OK, Json(files.get(&id))).into_response(),
         Err(err) => (
             StatusCode::INTERNAL_SERVER_ERROR,
-            Json(DBError::WriteError(format!(
-                "{:?}",
-                err.msg
-                    .unwrap_or("Could not write file data to file".to_string())
-            ))),
+            Json(DBError::WriteError(format!("{:?}", err))),
         )
             .into_response(),
     }
@@ -258,7 +360,7 @@ async fn files_delete(
     State(state): State<Arc<AppState>>,
 ) -> impl IntoResponse {
     let lock = state.db.write().unwrap();
-    let mut files = lock.get_unwrap::<Files>(DB_KEY).unwrap();
+    let mut files = store::get::<Files>(&lock, DB_KEY).unwrap();
 
     // try to remove locally
     if let None = files.remove(&id) {
@@ -273,14 +375,10 @@ async fn files_delete(
     info!("[Files] File with id '{id}' deleted.");
 
     // write to file db
-    if let Err(err) = lock.put(DB_KEY, &files) {
+    if let Err(err) = store::put(&lock, DB_KEY, &files) {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            Json(DBError::WriteError(format!(
-                "{:?}",
-                err.msg
-                    .unwrap_or("Could not write file data to file".to_string())
-            ))),
+            Json(DBError::WriteError(format!("{:?}", err))),
         )
             .into_response()
     } else {
@@ -297,11 +395,7 @@ async fn files_index_sse(
 ) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
     // A `Stream` that repeats an event every second
     let stream = stream::repeat_with(move || {
-        let files = state
-            .db
-            .read()
-            .unwrap()
-            .get_unwrap::<Files>(DB_KEY)
+        let files = store::get::<Files>(&state.db.read().unwrap(), DB_KEY)
             .unwrap()
             .values()
             .cloned()
@@ -330,6 +424,34 @@ pub struct File {
     pub last_version: String,    // latest file version
     pub path: String,
     pub mqtt_topic: String,
+    #[serde(default)]
+    pub source: VersionSource,
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
+    #[serde(default)]
+    pub policy: CompliancePolicy,
+    #[serde(default)]
+    pub compliance: Compliance, // result of the policy evaluation on the last scan
+    #[serde(default)]
+    pub last_hash: String, // content hash of the local file on the last scan
+    #[serde(default)]
+    pub watch_mode: WatchMode,
+    #[serde(default)]
+    pub group: String,
+    #[serde(default)]
+    pub debounce_ms: u64, // quiet period after change events, 0 for the default
+    #[serde(default)]
+    pub follow_renames: bool,
+    #[serde(default)]
+    pub lifecycle: FileLifecycle,
+    #[serde(default)]
+    pub missing_since_utc: String, // timestamp UTC since the file is missing (empty if present)
+    #[serde(default)]
+    pub mqtt_qos: Option<MqttQos>, // overrides the broker setting
+    #[serde(default)]
+    pub mqtt_retain: Option<bool>, // overrides the broker setting
+    #[serde(default)]
+    pub command_error: Option<CommandError>, // error of the last run of a command source
 }
 
 impl PartialEq for File {
@@ -338,9 +460,240 @@ impl PartialEq for File {
             && self.mqtt_topic == other.mqtt_topic
             && self.name == other.name
             && self.path == other.path
+            && self.source == other.source
+            && self.hash_algorithm == other.hash_algorithm
+            && self.policy == other.policy
+            && self.watch_mode == other.watch_mode
+            && self.group == other.group
+            && self.debounce_ms == other.debounce_ms
+            && self.follow_renames == other.follow_renames
+            && self.mqtt_qos == other.mqtt_qos
+            && self.mqtt_retain == other.mqtt_retain
+    }
+}
+
+/// Version source schema.
+///
+/// Defines how the version of a configured file gets read. The file path is always used as the
+/// watched location.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum VersionSource {
+    /// File version from the file properties (`.exe`, `.dll`) or content hash for other files
+    #[default]
+    File,
+    /// Value from an offline Windows registry hive file (regf format)
+    RegistryHive {
+        /// Key path relative to the hive root
+        #[schema(example = "Microsoft\\Windows\\CurrentVersion\\Uninstall\\{GUID}")]
+        key_path: String,
+        /// Name of the value inside the key (empty for the default value)
+        #[schema(example = "DisplayVersion")]
+        value_name: String,
+    },
+    /// Output of a command. The file path is the executed binary.
+    Command {
+        /// Command line arguments
+        #[schema(example = json!(["--version"]))]
+        args: Vec<String>,
+        /// Working directory of the command (empty for the binary directory)
+        #[schema(example = "C:\\win")]
+        working_dir: String,
+        /// Max runtime of the command in seconds
+        #[schema(example = "10")]
+        timeout_secs: u64,
+        /// Interval in seconds in which the command gets executed (0 to run on binary changes only)
+        #[schema(example = "3600")]
+        interval_secs: u64,
+        /// Extracts the version from the command output
+        extractor: VersionExtractor,
+    },
+    /// Response of an http endpoint (e.g. `/health` or `/api/info`). The file path is the url.
+    Http {
+        /// Http method
+        #[schema(example = "GET")]
+        method: String,
+        /// Additional request headers
+        #[schema(example = json!({"Accept": "application/json"}))]
+        headers: HashMap<String, String>,
+        /// Request timeout in seconds
+        #[schema(example = "10")]
+        timeout_secs: u64,
+        /// Interval in seconds in which the endpoint gets polled
+        #[schema(example = "300")]
+        interval_secs: u64,
+        /// Extracts the version from the response body
+        extractor: VersionExtractor,
+    },
+    /// Executable of a running process. The file path is the process name.
+    Process {
+        /// Regular expression which the process command line needs to match (empty matches all)
+        #[schema(example = "--service")]
+        cmdline_pattern: String,
+        /// Interval in seconds in which the running processes get checked
+        #[schema(example = "60")]
+        interval_secs: u64,
+    },
+    /// Version of an installed package. The file path is the package database or export file.
+    Package {
+        /// Name of the installed package
+        #[schema(example = "openssl")]
+        package_name: String,
+        /// Format of the package database file
+        format: PackageFormat,
+    },
+    /// Checked out revision of a git working copy. The file path is the repository directory.
+    Git {
+        /// Interval in seconds in which the working tree gets checked for uncommitted changes
+        /// (0 to check on HEAD, index and ref changes only, edits of the working tree are not
+        /// watched)
+        #[schema(example = "600")]
+        interval_secs: u64,
+    },
+}
+
+/// Package database format schema.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PackageFormat {
+    /// dpkg status file (e.g. `/var/lib/dpkg/status`)
+    Dpkg,
+    /// RPM database export (`rpm -qa --queryformat '%{NAME} %{VERSION}-%{RELEASE}\n'`)
+    RpmExport,
+    /// Csv inventory export with `Name` and `Version` columns (e.g. `Get-Package | Export-Csv`)
+    Inventory,
+}
+
+impl File {
+    /// Quality of service and retain flag of the version messages (file overrides or broker defaults)
+    pub fn mqtt_options(&self, broker: &Broker) -> (MqttQos, bool) {
+        (
+            self.mqtt_qos.unwrap_or(broker.qos),
+            self.mqtt_retain.unwrap_or(broker.retain),
+        )
+    }
+}
+
+/// File lifecycle schema.
+///
+/// Tracks if a local file was deleted (e.g. during an uninstall) and if it came back.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FileLifecycle {
+    /// File exists
+    #[default]
+    Present,
+    /// File was deleted or renamed (or its directory exists without it), see `missing_since_utc`
+    Missing,
+    /// File exists again after it was missing
+    Restored,
+    /// Directory of the file does not exist yet (e.g. product is not installed yet)
+    WaitingForPath,
+}
+
+/// Watch mode schema.
+///
+/// File system events are not delivered for some paths (e.g. network shares, container bind
+/// mounts), those can be polled for modification time and size changes instead.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum WatchMode {
+    /// Native file system events
+    #[default]
+    Native,
+    /// Polling of the modification time and size
+    Poll {
+        /// Interval in seconds in which the file gets checked
+        #[schema(example = "30")]
+        interval_secs: u64,
+    },
+    /// Native file system events and polling
+    Both {
+        /// Interval in seconds in which the file gets checked
+        #[schema(example = "300")]
+        interval_secs: u64,
+    },
+}
+
+impl WatchMode {
+    /// Returns true if native file system events are used
+    pub fn uses_events(&self) -> bool {
+        !matches!(self, WatchMode::Poll { .. })
+    }
+
+    /// Returns the poll interval in seconds if the file gets polled
+    pub fn poll_interval(&self) -> Option<u64> {
+        match self {
+            WatchMode::Native => None,
+            WatchMode::Poll { interval_secs } | WatchMode::Both { interval_secs } => {
+                Some((*interval_secs).max(1))
+            }
+        }
+    }
+}
+
+/// Hash algorithm schema.
+///
+/// Used for the content hash of files without file properties.
+#[derive(
+    Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq, Eq, PartialOrd, Ord, Default,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Sha1,
+    Md5,
+    Blake3,
+}
+
+impl VersionSource {
+    /// Returns true if the file path points to a local file or directory (and not e.g. to an url).
+    pub fn has_local_path(&self) -> bool {
+        !matches!(
+            self,
+            VersionSource::Http { .. } | VersionSource::Process { .. }
+        )
     }
 }
 
+/// Command error schema.
+///
+/// Typed error of the last run of a command source (none after a successful run).
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandError {
+    /// Command could not be started
+    Spawn { message: String },
+    /// Command did not finish within the configured timeout (in seconds)
+    Timeout { secs: u64 },
+    /// Command finished with a non-zero exit code (none if terminated by a signal)
+    ExitCode { code: Option<i32>, stderr: String },
+    /// Version could not be extracted from the command output
+    Output { message: String },
+}
+
+/// Version extractor schema.
+///
+/// Defines how a version gets extracted from a text output.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum VersionExtractor {
+    /// Whole output (trimmed)
+    #[default]
+    Raw,
+    /// First capture group (or whole match) of a regular expression
+    Regex {
+        #[schema(example = "version (\\d+\\.\\d+\\.\\d+)")]
+        pattern: String,
+    },
+    /// Value at a json pointer of a json output
+    JsonPointer {
+        #[schema(example = "/version")]
+        pointer: String,
+    },
+}
+
 pub type Files = HashMap<Uuid, File>;
 
 /// File DB operation errors
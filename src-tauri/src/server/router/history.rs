@@ -0,0 +1,85 @@
+use crate::server::store::{self, AppState};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+static DB_KEY: &str = "history";
+
+/// exports all routes from this module as router
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new().route("/files/:id/history", get(history_index))
+}
+
+/// List the version history of a file.
+///
+/// Returns the latest version changes (oldest first) of a specific file by its id.
+#[utoipa::path(
+        get,
+        context_path = "/api",
+        path = "/files/{id}/history",
+        tag = "history",
+        params(
+            ("id" = Uuid, Path, description = "File database id")
+        ),
+        responses(
+            (status = 200, description = "List version history successfully", body = [VersionHistoryEntry])
+        )
+    )]
+pub async fn history_index(
+    Path(id): Path<Uuid>,
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let mut history =
+        store::get::<VersionHistory>(&state.db.read().unwrap(), DB_KEY).unwrap_or_default();
+
+    (
+        StatusCode::OK,
+        Json(history.remove(&id).unwrap_or_default()),
+    )
+}
+
+/// Version history entry schema.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct VersionHistoryEntry {
+    /// New version
+    #[schema(example = "7.2.0.0")]
+    pub version: String,
+    /// Version before the change (empty if the version was seen for the first time)
+    #[schema(example = "7.1.3.0")]
+    pub previous_version: String,
+    /// Classification of the change
+    pub change: VersionChange,
+    /// Timestamp of the change
+    #[schema(example = "2023-02-28 12:00:00 UTC")]
+    pub timestamp_utc: String,
+}
+
+/// Version change schema.
+///
+/// Classification of a new version compared to the previous one.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum VersionChange {
+    /// No previous version known
+    FirstSeen,
+    /// Version is higher than the previous one
+    Upgrade,
+    /// Version is lower than the previous one
+    Downgrade,
+    /// Same version was written again (file modified since the last check)
+    Reinstall,
+    /// Same version without any modification (e.g. on application start or polling)
+    Unchanged,
+    /// Versions can not be ordered (e.g. content hashes)
+    Changed,
+}
+
+pub type VersionHistory = HashMap<Uuid, Vec<VersionHistoryEntry>>;
@@ -0,0 +1,48 @@
+use crate::server::store::AppState;
+use axum::{extract::State, http::StatusCode, response::IntoResponse, routing::get, Json, Router};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+/// exports all routes from this module as router
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new().route("/metrics", get(metrics_index))
+}
+
+/// Get file watcher metrics.
+///
+/// Returns the debounce counters of the file watcher since the application start.
+#[utoipa::path(
+        get,
+        context_path = "/api",
+        path = "/metrics",
+        tag = "metrics",
+        responses(
+            (status = 200, description = "Get metrics successfully", body = DebounceMetrics)
+        )
+    )]
+pub async fn metrics_index(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let metrics = state.debounce_metrics.read().unwrap().clone();
+
+    (StatusCode::OK, Json(metrics))
+}
+
+/// Debounce metrics schema.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Default)]
+pub struct DebounceMetrics {
+    /// File change events passed to the debouncer
+    #[schema(example = "1200")]
+    pub events: u64,
+    /// Events which were merged into a later execution
+    #[schema(example = "1150")]
+    pub suppressed: u64,
+    /// Executed version checks
+    #[schema(example = "50")]
+    pub executions: u64,
+    /// Executions forced by the max wait time during continuous events
+    #[schema(example = "3")]
+    pub forced_executions: u64,
+    /// Paths with a pending execution
+    #[schema(example = "1")]
+    pub pending: u64,
+}
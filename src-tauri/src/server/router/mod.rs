@@ -2,15 +2,35 @@ use super::store::AppState;
 use axum::Router;
 use std::sync::Arc;
 
+pub mod baselines;
+pub mod compliance;
+pub mod deployments;
 pub mod files;
+pub mod history;
 pub mod info;
 pub mod logs;
+pub mod metrics;
+pub mod outbox;
+pub mod processes;
+pub mod revisions;
+pub mod scans;
+pub mod schedules;
 pub mod settings;
 
 pub fn routes() -> Router<Arc<AppState>> {
     Router::new()
         .merge(info::routes())
+        .merge(baselines::routes())
+        .merge(compliance::routes())
+        .merge(deployments::routes())
         .merge(files::routes())
+        .merge(history::routes())
         .merge(logs::routes())
+        .merge(metrics::routes())
+        .merge(outbox::routes())
+        .merge(processes::routes())
+        .merge(revisions::routes())
+        .merge(scans::routes())
+        .merge(schedules::routes())
         .merge(settings::routes())
 }
@@ -0,0 +1,275 @@
+use crate::server::{
+    router::settings::{DBError, MqttQos},
+    store::{self, AppState},
+};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, sync::Arc};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+static DB_KEY: &str = "mqtt_outbox";
+static DB_KEY_SETTINGS: &str = "outbox_settings";
+
+/// exports all routes from this module as router
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/outbox", get(outbox_index).delete(outbox_purge))
+        .route(
+            "/settings/outbox",
+            get(outbox_settings_index).patch(outbox_settings_update),
+        )
+}
+
+/// List queued MQTT messages.
+///
+/// Returns the messages which wait for a broker connection (oldest first) and the dead letters.
+#[utoipa::path(
+        get,
+        context_path = "/api",
+        path = "/outbox",
+        tag = "outbox",
+        responses(
+            (status = 200, description = "List outbox successfully", body = Outbox)
+        )
+    )]
+pub async fn outbox_index(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let outbox = store::get::<Outbox>(&state.db.read().unwrap(), DB_KEY).unwrap_or_default();
+
+    (StatusCode::OK, Json(outbox))
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct OutboxPurgeQuery {
+    /// Messages to remove (defaults to all)
+    target: Option<OutboxPurgeTarget>,
+}
+
+/// Outbox purge target
+#[derive(Deserialize, ToSchema, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OutboxPurgeTarget {
+    #[default]
+    All,
+    Queued,
+    DeadLetters,
+}
+
+/// Purge queued MQTT messages.
+///
+/// Removes queued messages and/or dead letters without publishing them.
+#[utoipa::path(
+        delete,
+        context_path = "/api",
+        path = "/outbox",
+        tag = "outbox",
+        params(OutboxPurgeQuery),
+        responses(
+            (status = 204, description = "Outbox purged successfully"),
+            (status = 500, description = "Error on DB write operation", body = DBError, example = json!(DBError::WriteError(String::from("Could not write data to file"))))
+        )
+    )]
+pub async fn outbox_purge(
+    Query(query): Query<OutboxPurgeQuery>,
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let lock = state.db.write().unwrap();
+    let mut outbox = store::get::<Outbox>(&lock, DB_KEY).unwrap_or_default();
+
+    let target = query.target.unwrap_or_default();
+    if matches!(target, OutboxPurgeTarget::All | OutboxPurgeTarget::Queued) {
+        outbox.messages.clear();
+    }
+    if matches!(
+        target,
+        OutboxPurgeTarget::All | OutboxPurgeTarget::DeadLetters
+    ) {
+        outbox.dead_letters.clear();
+    }
+
+    info!("[Outbox] Purged {:?} messages", target);
+
+    match store::put(&lock, DB_KEY, &outbox) {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(DBError::WriteError(format!("{:?}", err))),
+        )
+            .into_response(),
+    }
+}
+
+/// Show outbox settings.
+///
+/// Returns the limits of the MQTT outbox.
+#[utoipa::path(
+        get,
+        context_path = "/api",
+        path = "/settings/outbox",
+        tag = "outbox",
+        responses(
+            (status = 200, description = "Show outbox settings successfully", body = OutboxSettings)
+        )
+    )]
+pub async fn outbox_settings_index(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let settings = store::get::<OutboxSettings>(&state.db.read().unwrap(), DB_KEY_SETTINGS)
+        .unwrap_or_default();
+
+    (StatusCode::OK, Json(settings))
+}
+
+/// Parameters for updating the outbox settings
+#[derive(Debug, Deserialize, IntoParams, ToSchema)]
+pub struct OutboxSettingsUpdateParams {
+    /// Queue messages while the broker is not connected
+    #[schema(example = "true")]
+    enabled: Option<bool>,
+    /// Max number of queued messages
+    #[schema(example = "1000")]
+    max_messages: Option<usize>,
+    /// Seconds after which a queued message expires
+    #[schema(example = "604800")]
+    max_age_secs: Option<u64>,
+    /// Sends without acknowledgement of the broker after which a message is given up
+    #[schema(example = "5")]
+    max_attempts: Option<u32>,
+    dead_letter_policy: Option<DeadLetterPolicy>,
+}
+
+/// Update outbox settings.
+///
+/// Limits are applied on the next queued message or replay.
+#[utoipa::path(
+        patch,
+        context_path = "/api",
+        path = "/settings/outbox",
+        tag = "outbox",
+        request_body = OutboxSettingsUpdateParams,
+        responses(
+            (status = 200, description = "Outbox settings updated successfully", body = OutboxSettings),
+            (status = 500, description = "Error on DB write operation", body = DBError, example = json!(DBError::WriteError(String::from("Could not write data to file"))))
+        )
+    )]
+pub async fn outbox_settings_update(
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<OutboxSettingsUpdateParams>,
+) -> impl IntoResponse {
+    let lock = state.db.write().unwrap();
+    let mut settings = store::get::<OutboxSettings>(&lock, DB_KEY_SETTINGS).unwrap_or_default();
+
+    if let Some(enabled) = input.enabled {
+        settings.enabled = enabled;
+    }
+
+    if let Some(max_messages) = input.max_messages {
+        settings.max_messages = max_messages.max(1);
+    }
+
+    if let Some(max_age_secs) = input.max_age_secs {
+        settings.max_age_secs = max_age_secs;
+    }
+
+    if let Some(max_attempts) = input.max_attempts {
+        settings.max_attempts = max_attempts.max(1);
+    }
+
+    if let Some(dead_letter_policy) = input.dead_letter_policy {
+        settings.dead_letter_policy = dead_letter_policy;
+    }
+
+    info!("[Outbox] Settings changed to: {:?}", &settings);
+
+    match store::put(&lock, DB_KEY_SETTINGS, &settings) {
+        Ok(()) => (StatusCode::OK, Json(settings)).into_response(),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(DBError::WriteError(format!("{:?}", err))),
+        )
+            .into_response(),
+    }
+}
+
+/// Outbox settings schema.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct OutboxSettings {
+    /// Queue messages while the broker is not connected (otherwise they are dropped)
+    pub enabled: bool,
+    /// Max number of queued messages, the oldest message is given up on overflow
+    #[schema(example = "1000")]
+    pub max_messages: usize,
+    /// Seconds after which a queued message expires (0 to keep messages until they are sent)
+    #[schema(example = "604800")]
+    pub max_age_secs: u64,
+    /// Sends without acknowledgement of the broker after which a message is given up
+    #[schema(example = "5")]
+    pub max_attempts: u32,
+    pub dead_letter_policy: DeadLetterPolicy,
+}
+
+impl Default for OutboxSettings {
+    fn default() -> Self {
+        OutboxSettings {
+            enabled: true,
+            max_messages: 1000,
+            max_age_secs: 7 * 24 * 60 * 60,
+            max_attempts: 5,
+            dead_letter_policy: DeadLetterPolicy::Keep,
+        }
+    }
+}
+
+/// Dead letter policy schema.
+///
+/// What happens to messages which expired, overflowed the outbox or failed too often.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DeadLetterPolicy {
+    /// Keep the message as dead letter for inspection
+    #[default]
+    Keep,
+    /// Discard the message
+    Drop,
+}
+
+/// Outbox schema.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Default)]
+pub struct Outbox {
+    /// Messages which wait for a broker connection or for the acknowledgement of the broker, oldest
+    /// first
+    pub messages: Vec<OutboxMessage>,
+    /// Messages which were given up, oldest first
+    pub dead_letters: Vec<OutboxMessage>,
+}
+
+/// Outbox message schema.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct OutboxMessage {
+    pub id: Uuid,
+    #[schema(example = "files/ExampleFile")]
+    pub topic: String,
+    /// Message as it was created (incl. its original timestamp), `null` for an empty message which
+    /// clears a retained message
+    #[schema(value_type = Object)]
+    pub payload: serde_json::Value,
+    pub qos: MqttQos,
+    pub retain: bool,
+    #[schema(example = "2023-02-28 12:00:00 UTC")]
+    pub queued_utc: String,
+    /// Sends without acknowledgement of the broker
+    #[schema(example = "0")]
+    pub attempts: u32,
+    /// Why the message was given up (dead letters only)
+    #[serde(default)]
+    #[schema(example = "Expired")]
+    pub reason: String,
+    /// User properties of MQTT 5 messages (e.g. `fileId` and `change`)
+    #[serde(default)]
+    pub properties: BTreeMap<String, String>,
+}
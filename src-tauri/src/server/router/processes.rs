@@ -0,0 +1,67 @@
+use crate::server::store::{self, AppState};
+use axum::{extract::State, http::StatusCode, response::IntoResponse, routing::get, Json, Router};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+static DB_KEY: &str = "processes";
+
+/// exports all routes from this module as router
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new().route("/processes", get(processes_index))
+}
+
+/// List running processes of all process sources.
+///
+/// Returns the matching processes and restart events for each file with a process version source.
+#[utoipa::path(
+        get,
+        context_path = "/api",
+        path = "/processes",
+        tag = "processes",
+        responses(
+            (status = 200, description = "List process states successfully", body = HashMap<Uuid, ProcessState>)
+        )
+    )]
+pub async fn processes_index(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let processes =
+        store::get::<ProcessStates>(&state.db.read().unwrap(), DB_KEY).unwrap_or_default();
+
+    (StatusCode::OK, Json(processes))
+}
+
+/// Process state schema.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Default)]
+pub struct ProcessState {
+    /// Currently running processes
+    pub processes: Vec<ProcessInfo>,
+    /// Processes of the last check which found the process running (kept while it is down)
+    #[serde(default)]
+    pub last_seen: Vec<ProcessInfo>,
+    /// Number of detected restarts since the process got monitored
+    pub restarts: u32,
+    /// Timestamp of the last detected restart
+    #[schema(example = "2023-02-28 12:00:00 UTC")]
+    pub last_restart_utc: String,
+}
+
+/// Running process schema.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq)]
+pub struct ProcessInfo {
+    /// Process id
+    #[schema(example = "4242")]
+    pub pid: u32,
+    /// Path of the executable
+    #[schema(example = "C:\\Program Files\\Example\\service.exe")]
+    pub exe: String,
+    /// Process start time
+    #[schema(example = "2023-02-28 12:00:00 UTC")]
+    pub start_time_utc: String,
+    /// Process start time in seconds since unix epoch
+    pub start_time: u64,
+    /// Executable on disk was updated after the process started
+    pub stale: bool,
+}
+
+pub type ProcessStates = HashMap<Uuid, ProcessState>;
@@ -0,0 +1,53 @@
+use crate::server::store::{self, AppState};
+use axum::{extract::State, http::StatusCode, response::IntoResponse, routing::get, Json, Router};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+static DB_KEY: &str = "revisions";
+
+/// exports all routes from this module as router
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new().route("/revisions", get(revisions_index))
+}
+
+/// List checked out revisions of all git sources.
+///
+/// Returns the commit, branch, nearest tag and dirty state for each file with a git version source.
+#[utoipa::path(
+        get,
+        context_path = "/api",
+        path = "/revisions",
+        tag = "revisions",
+        responses(
+            (status = 200, description = "List git revisions successfully", body = HashMap<Uuid, GitRevision>)
+        )
+    )]
+pub async fn revisions_index(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let revisions =
+        store::get::<GitRevisions>(&state.db.read().unwrap(), DB_KEY).unwrap_or_default();
+
+    (StatusCode::OK, Json(revisions))
+}
+
+/// Git revision schema.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq, Default)]
+pub struct GitRevision {
+    /// Id of the checked out commit
+    #[schema(example = "1a2b3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d")]
+    pub commit: String,
+    /// Checked out branch (empty for a detached HEAD)
+    #[schema(example = "main")]
+    pub branch: String,
+    /// Nearest tag in the commit history (empty if there is none)
+    #[schema(example = "v1.2.0")]
+    pub tag: String,
+    /// Number of commits since the nearest tag
+    #[schema(example = "3")]
+    pub distance: u32,
+    /// Working tree has uncommitted changes of tracked files
+    pub dirty: bool,
+}
+
+pub type GitRevisions = HashMap<Uuid, GitRevision>;
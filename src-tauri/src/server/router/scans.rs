@@ -0,0 +1,138 @@
+use crate::server::{
+    router::settings::DBError,
+    store::{self, AppState},
+};
+use axum::{extract::State, http::StatusCode, response::IntoResponse, routing::get, Json, Router};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
+
+static DB_KEY_SETTINGS: &str = "scan_settings";
+
+/// Max number of scan workers
+pub const MAX_SCAN_WORKERS: usize = 32;
+
+/// exports all routes from this module as router
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new().route("/scans", get(scans_index)).route(
+        "/settings/scan",
+        get(scan_settings_index).patch(scan_settings_update),
+    )
+}
+
+/// Get scan queue status.
+///
+/// Returns the number of queued and running scans of the scan worker pool.
+#[utoipa::path(
+        get,
+        context_path = "/api",
+        path = "/scans",
+        tag = "scans",
+        responses(
+            (status = 200, description = "Get scan status successfully", body = ScanStatus)
+        )
+    )]
+pub async fn scans_index(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let status = state.scan_status.read().unwrap().clone();
+
+    (StatusCode::OK, Json(status))
+}
+
+/// Show scan settings.
+///
+/// Returns the settings of the scan worker pool.
+#[utoipa::path(
+        get,
+        context_path = "/api",
+        path = "/settings/scan",
+        tag = "scans",
+        responses(
+            (status = 200, description = "Show scan settings successfully", body = ScanSettings)
+        )
+    )]
+pub async fn scan_settings_index(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let settings =
+        store::get::<ScanSettings>(&state.db.read().unwrap(), DB_KEY_SETTINGS).unwrap_or_default();
+
+    (StatusCode::OK, Json(settings))
+}
+
+/// Parameters for updating the scan settings
+#[derive(Debug, Deserialize, IntoParams, ToSchema)]
+pub struct ScanSettingsUpdateParams {
+    /// Number of files which are scanned concurrently (1 - 32)
+    #[schema(example = "4")]
+    workers: Option<usize>,
+}
+
+/// Update scan settings.
+///
+/// Changes the number of scan workers, running scans are finished first.
+#[utoipa::path(
+        patch,
+        context_path = "/api",
+        path = "/settings/scan",
+        tag = "scans",
+        request_body = ScanSettingsUpdateParams,
+        responses(
+            (status = 200, description = "Scan settings updated successfully", body = ScanSettings),
+            (status = 500, description = "Error on DB write operation", body = DBError, example = json!(DBError::WriteError(String::from("Could not write data to file"))))
+        )
+    )]
+pub async fn scan_settings_update(
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<ScanSettingsUpdateParams>,
+) -> impl IntoResponse {
+    let lock = state.db.write().unwrap();
+    let mut settings = store::get::<ScanSettings>(&lock, DB_KEY_SETTINGS).unwrap_or_default();
+
+    if let Some(workers) = input.workers {
+        settings.workers = workers.clamp(1, MAX_SCAN_WORKERS);
+    }
+
+    info!("[Scans] Settings changed to: {:?}", &settings);
+
+    match store::put(&lock, DB_KEY_SETTINGS, &settings) {
+        Ok(()) => (StatusCode::OK, Json(settings)).into_response(),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(DBError::WriteError(format!("{:?}", err))),
+        )
+            .into_response(),
+    }
+}
+
+/// Scan settings schema.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq)]
+pub struct ScanSettings {
+    /// Number of files which are scanned concurrently
+    #[schema(example = "2")]
+    pub workers: usize,
+}
+
+impl Default for ScanSettings {
+    fn default() -> Self {
+        ScanSettings { workers: 2 }
+    }
+}
+
+/// Scan status schema.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Default)]
+pub struct ScanStatus {
+    /// Number of scan workers
+    #[schema(example = "2")]
+    pub workers: usize,
+    /// Paths waiting for a scan
+    #[schema(example = "12")]
+    pub queued: usize,
+    /// Paths which are scanned right now
+    #[schema(example = "2")]
+    pub running: usize,
+    /// Finished scans since the application start
+    #[schema(example = "340")]
+    pub completed: u64,
+    /// Scan requests which were merged into an already queued scan of the same path
+    #[schema(example = "85")]
+    pub deduplicated: u64,
+}
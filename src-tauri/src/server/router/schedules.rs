@@ -0,0 +1,320 @@
+use crate::server::{
+    router::settings::DBError,
+    store::{self, AppState},
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{get, patch},
+    Json, Router,
+};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, str::FromStr, sync::Arc};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+static DB_KEY: &str = "schedules";
+
+/// exports all routes from this module as router
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/schedules", get(schedules_index).post(schedules_create))
+        .route(
+            "/schedules/:id",
+            patch(schedules_update).delete(schedules_delete),
+        )
+}
+
+/// List all schedules.
+///
+/// Returns all rescan schedules with their last and next run times.
+#[utoipa::path(
+        get,
+        context_path = "/api",
+        path = "/schedules",
+        tag = "schedules",
+        responses(
+            (status = 200, description = "List all schedules successfully", body = [Schedule])
+        )
+    )]
+pub async fn schedules_index(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let schedules = store::get::<Schedules>(&state.db.read().unwrap(), DB_KEY)
+        .unwrap_or_default()
+        .into_values()
+        .collect::<Vec<_>>();
+
+    (StatusCode::OK, Json(schedules))
+}
+
+/// Body params for creating a new schedule
+#[derive(Debug, Deserialize, IntoParams, ToSchema)]
+pub struct ScheduleCreateParams {
+    /// Name of the schedule
+    #[schema(example = "Nightly rescan")]
+    name: String,
+    /// Cron expression in local time (`min hour day month weekday`, optionally with seconds)
+    /// Weekdays are best given as names (`Mon-Fri`), numbers start with 1 for Sunday.
+    #[schema(example = "0 2 * * *")]
+    cron: String,
+    /// Group of files which get rescanned (empty for all files)
+    #[schema(example = "")]
+    group: Option<String>,
+    /// Publish versions even if they did not change
+    #[schema(example = "true")]
+    force_publish: Option<bool>,
+    /// Schedule enable state
+    #[schema(example = "true")]
+    enabled: bool,
+}
+
+/// Add a new schedule.
+///
+/// Adds a cron schedule which triggers a rescan of all files or the files of a group.
+#[utoipa::path(
+        post,
+        context_path = "/api",
+        path = "/schedules",
+        tag = "schedules",
+        request_body = ScheduleCreateParams,
+        responses(
+            (status = 201, description = "Schedule added successfully", body = Schedule),
+            (status = 400, description = "Invalid cron expression", body = ScheduleError, example = json!(ScheduleError::InvalidCron(String::from("Invalid cron expression")))),
+            (status = 500, description = "Error on DB write operation", body = DBError, example = json!(DBError::WriteError(String::from("Could not write data to file"))))
+        )
+    )]
+pub async fn schedules_create(
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<ScheduleCreateParams>,
+) -> impl IntoResponse {
+    if let Err(err) = parse_cron(&input.cron) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ScheduleError::InvalidCron(err)),
+        )
+            .into_response();
+    }
+
+    let schedule = Schedule {
+        id: Uuid::new_v4(),
+        name: input.name,
+        cron: input.cron,
+        group: input.group.unwrap_or_default(),
+        force_publish: input.force_publish.unwrap_or(false),
+        enabled: input.enabled,
+        last_run_utc: "".to_string(),
+        next_run_utc: "".to_string(),
+    };
+
+    let lock = state.db.write().unwrap();
+    let mut schedules = store::get::<Schedules>(&lock, DB_KEY).unwrap_or_default();
+    schedules.insert(schedule.id, schedule.clone());
+
+    info!("[Schedules] New schedule added: {:?}", &schedule);
+
+    if let Err(err) = store::put(&lock, DB_KEY, &schedules) {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(DBError::WriteError(format!("{:?}", err))),
+        )
+            .into_response();
+    };
+
+    (StatusCode::CREATED, Json(schedule)).into_response()
+}
+
+/// Parameters for updating a schedule
+#[derive(Debug, Deserialize, IntoParams, ToSchema)]
+pub struct ScheduleUpdateParams {
+    /// Name of the schedule
+    #[schema(example = "Nightly rescan")]
+    name: Option<String>,
+    /// Cron expression in local time (`min hour day month weekday`, optionally with seconds)
+    /// Weekdays are best given as names (`Mon-Fri`), numbers start with 1 for Sunday.
+    #[schema(example = "0 2 * * *")]
+    cron: Option<String>,
+    /// Group of files which get rescanned (empty for all files)
+    #[schema(example = "backend")]
+    group: Option<String>,
+    /// Publish versions even if they did not change
+    #[schema(example = "false")]
+    force_publish: Option<bool>,
+    /// Schedule enable state
+    #[schema(example = "false")]
+    enabled: Option<bool>,
+}
+
+/// Update a schedule.
+///
+/// Update a specific schedule by its id.
+#[utoipa::path(
+        patch,
+        context_path = "/api",
+        path = "/schedules/{id}",
+        tag = "schedules",
+        request_body = ScheduleUpdateParams,
+        params(
+            ("id" = Uuid, Path, description = "Schedule id")
+        ),
+        responses(
+            (status = 200, description = "Schedule updated successfully", body = Schedule),
+            (status = 400, description = "Invalid cron expression", body = ScheduleError, example = json!(ScheduleError::InvalidCron(String::from("Invalid cron expression")))),
+            (status = 404, description = "No schedule with this id found", body = DBError, example = json!(DBError::KeyNotFound(String::from("key not found in storage"))))
+        )
+    )]
+pub async fn schedules_update(
+    Path(id): Path<Uuid>,
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<ScheduleUpdateParams>,
+) -> impl IntoResponse {
+    let lock = state.db.write().unwrap();
+    let mut schedules = store::get::<Schedules>(&lock, DB_KEY).unwrap_or_default();
+
+    let schedule = match schedules.get_mut(&id) {
+        Some(schedule) => schedule,
+        None => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(DBError::KeyNotFound("key not found in storage".to_string())),
+            )
+                .into_response()
+        }
+    };
+
+    if let Some(name) = input.name {
+        schedule.name = name;
+    }
+
+    if let Some(cron) = input.cron {
+        if let Err(err) = parse_cron(&cron) {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ScheduleError::InvalidCron(err)),
+            )
+                .into_response();
+        }
+        // next run gets calculated from the new expression
+        schedule.cron = cron;
+        schedule.next_run_utc = "".to_string();
+    }
+
+    if let Some(group) = input.group {
+        schedule.group = group;
+    }
+
+    if let Some(force_publish) = input.force_publish {
+        schedule.force_publish = force_publish;
+    }
+
+    if let Some(enabled) = input.enabled {
+        schedule.enabled = enabled;
+        schedule.next_run_utc = "".to_string();
+    }
+
+    info!("[Schedules] Schedule changed to: {:?}", &schedule);
+    let schedule = schedule.clone();
+
+    match store::put(&lock, DB_KEY, &schedules) {
+        Ok(()) => (StatusCode::OK, Json(schedule)).into_response(),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(DBError::WriteError(format!("{:?}", err))),
+        )
+            .into_response(),
+    }
+}
+
+/// Delete a schedule.
+///
+/// Deletes a single schedule by its id.
+#[utoipa::path(
+        delete,
+        context_path = "/api",
+        path = "/schedules/{id}",
+        tag = "schedules",
+        params(
+            ("id" = Uuid, Path, description = "Schedule id")
+        ),
+        responses(
+            (status = 204, description = "Schedule deleted successfully"),
+            (status = 404, description = "Schedule not found", body = DBError, example = json!(DBError::KeyNotFound(String::from("key not found in storage")))),
+            (status = 500, description = "Error on DB write operation", body = DBError, example = json!(DBError::WriteError(String::from("Could not write data to file"))))
+        )
+    )]
+pub async fn schedules_delete(
+    Path(id): Path<Uuid>,
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let lock = state.db.write().unwrap();
+    let mut schedules = store::get::<Schedules>(&lock, DB_KEY).unwrap_or_default();
+
+    if schedules.remove(&id).is_none() {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(DBError::KeyNotFound("key not found in storage".to_string())),
+        )
+            .into_response();
+    }
+
+    match store::put(&lock, DB_KEY, &schedules) {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(DBError::WriteError(format!("{:?}", err))),
+        )
+            .into_response(),
+    }
+}
+
+/// Parses a cron expression, standard five field expressions get a leading seconds field.
+pub fn parse_cron(expression: &str) -> Result<cron::Schedule, String> {
+    let expression = match expression.split_whitespace().count() {
+        5 => format!("0 {}", expression.trim()),
+        _ => expression.trim().to_string(),
+    };
+
+    cron::Schedule::from_str(&expression)
+        .map_err(|err| format!("Invalid cron expression '{expression}': {err}"))
+}
+
+/// Schedule schema.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct Schedule {
+    pub id: Uuid,
+    #[schema(example = "Nightly rescan")]
+    pub name: String,
+    /// Cron expression in local time
+    #[schema(example = "0 2 * * *")]
+    pub cron: String,
+    /// Group of files which get rescanned (empty for all files)
+    pub group: String,
+    /// Publish versions even if they did not change
+    pub force_publish: bool,
+    pub enabled: bool,
+    /// Timestamp of the last run
+    #[schema(example = "2023-02-28 02:00:00 UTC")]
+    pub last_run_utc: String,
+    /// Timestamp of the next run
+    #[schema(example = "2023-03-01 02:00:00 UTC")]
+    pub next_run_utc: String,
+}
+
+impl PartialEq for Schedule {
+    fn eq(&self, other: &Self) -> bool {
+        self.cron == other.cron
+            && self.group == other.group
+            && self.force_publish == other.force_publish
+            && self.enabled == other.enabled
+    }
+}
+
+/// Schedule errors
+#[derive(Serialize, Deserialize, ToSchema)]
+pub enum ScheduleError {
+    /// Cron expression can not be parsed.
+    #[schema(example = "Invalid cron expression")]
+    InvalidCron(String),
+}
+
+pub type Schedules = HashMap<Uuid, Schedule>;
@@ -1,4 +1,4 @@
-use crate::server::store::AppState;
+use crate::server::store::{self, AppState};
 use axum::{
     extract::{State, TypedHeader},
     headers,
@@ -15,7 +15,7 @@ use log::{error, info};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::sync::Arc;
-use std::{convert::Infallible, time::Duration};
+use std::{collections::BTreeMap, convert::Infallible, time::Duration};
 use tokio_stream::StreamExt as _;
 use utoipa::{IntoParams, ToSchema};
 
@@ -43,16 +43,13 @@ pub fn routes() -> Router<Arc<AppState>> {
         )
     )]
 pub async fn settings_index(State(state): State<Arc<AppState>>) -> impl IntoResponse {
-    match state.db.read().unwrap().get_unwrap::<Broker>(DB_KEY) {
+    match store::get::<Broker>(&state.db.read().unwrap(), DB_KEY) {
         Ok(broker) => (StatusCode::OK, Json(broker)).into_response(),
         Err(err) => {
             error!("Error: {err:?}");
             (
                 StatusCode::NOT_FOUND,
-                Json(DBError::KeyNotFound(format!(
-                    "{:?}",
-                    err.msg.unwrap_or("key not found in storage".to_string())
-                ))),
+                Json(DBError::KeyNotFound(format!("{:?}", err))),
             )
                 .into_response()
         }
@@ -83,9 +80,32 @@ pub struct BrokerUpdateParams {
     /// MQTT broker auth password
     #[schema(example = "not1234")]
     password: Option<String>,
-    /// MQTT broker protocol
+    /// MQTT broker protocol (`mqtt://`, `mqtts://`, `ws://` or `wss://`)
     #[schema(example = "mqtt://")]
     protocol: Option<String>,
+    /// Default quality of service of published messages
+    qos: Option<MqttQos>,
+    /// Publish version messages as retained messages by default
+    #[schema(example = "true")]
+    retain: Option<bool>,
+    /// Clear the retained message of a file when it gets deleted
+    #[schema(example = "true")]
+    clear_retained_on_delete: Option<bool>,
+    /// Online/offline status messages of this device
+    status: Option<BrokerStatus>,
+    /// Certificates and options of `mqtts://` and `wss://` connections
+    tls: Option<BrokerTls>,
+    /// Path of the websocket endpoint (`ws://` and `wss://` only)
+    #[schema(example = "/mqtt")]
+    ws_path: Option<String>,
+    /// Additional headers of the websocket handshake (`ws://` and `wss://` only), e.g. for the
+    /// authentication at a reverse proxy
+    #[schema(example = json!({"Authorization": "Bearer <token>"}))]
+    ws_headers: Option<BTreeMap<String, String>>,
+    /// MQTT protocol version
+    protocol_version: Option<MqttVersion>,
+    /// Message properties of MQTT 5 connections
+    v5: Option<BrokerV5>,
 }
 /// Update broker settings.
 ///
@@ -107,7 +127,7 @@ async fn settings_update(
     Json(input): Json<BrokerUpdateParams>,
 ) -> impl IntoResponse {
     let lock = state.db.write().unwrap();
-    let data = lock.get_unwrap::<Broker>(DB_KEY);
+    let data = store::get::<Broker>(&lock, DB_KEY);
     match data {
         Ok(mut broker) => {
             // check for changes on each provided input param
@@ -143,6 +163,42 @@ async fn settings_update(
                 broker.port = port;
             }
 
+            if let Some(qos) = input.qos {
+                broker.qos = qos;
+            }
+
+            if let Some(retain) = input.retain {
+                broker.retain = retain;
+            }
+
+            if let Some(clear_retained_on_delete) = input.clear_retained_on_delete {
+                broker.clear_retained_on_delete = clear_retained_on_delete;
+            }
+
+            if let Some(status) = input.status {
+                broker.status = status;
+            }
+
+            if let Some(tls) = input.tls {
+                broker.tls = tls;
+            }
+
+            if let Some(ws_path) = input.ws_path {
+                broker.ws_path = ws_path;
+            }
+
+            if let Some(ws_headers) = input.ws_headers {
+                broker.ws_headers = ws_headers;
+            }
+
+            if let Some(protocol_version) = input.protocol_version {
+                broker.protocol_version = protocol_version;
+            }
+
+            if let Some(v5) = input.v5 {
+                broker.v5 = v5;
+            }
+
             // reset connected state until new broker instance updates its state
             broker.connected = false;
             broker.state = "Reconnecting..".to_string();
@@ -151,15 +207,11 @@ async fn settings_update(
             info!("[Settings] Broker settings changed to: {:?}", broker);
 
             // write to file db
-            match lock.put(DB_KEY, &broker) {
+            match store::put(&lock, DB_KEY, &broker) {
                 Ok(()) => (StatusCode::OK, Json(broker)).into_response(),
                 Err(err) => (
                     StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(DBError::WriteError(format!(
-                        "{:?}",
-                        err.msg
-                            .unwrap_or("Could not write data to file".to_string())
-                    ))),
+                    Json(DBError::WriteError(format!("{:?}", err))),
                 )
                     .into_response(),
             }
@@ -168,10 +220,7 @@ async fn settings_update(
             error!("Error: {err:?}");
             (
                 StatusCode::NOT_FOUND,
-                Json(DBError::KeyNotFound(format!(
-                    "{:?}",
-                    err.msg.unwrap_or("key not found in storage".to_string())
-                ))),
+                Json(DBError::KeyNotFound(format!("{:?}", err))),
             )
                 .into_response()
         }
@@ -187,7 +236,7 @@ async fn settings_index_sse(
 ) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
     // A `Stream` that repeats an event every second
     let stream = stream::repeat_with(move || {
-        let broker = state.db.read().unwrap().get_unwrap::<Broker>(DB_KEY);
+        let broker = store::get::<Broker>(&state.db.read().unwrap(), DB_KEY);
         match broker {
             Ok(broker) => Event::default().data(json!(broker).to_string()),
             Err(err) => {
@@ -220,6 +269,24 @@ pub struct Broker {
     pub username: String,
     pub state: String,
     pub connected: bool,
+    #[serde(default)]
+    pub qos: MqttQos, // default quality of service, can be overridden per file
+    #[serde(default)]
+    pub retain: bool, // default retain flag, can be overridden per file
+    #[serde(default)]
+    pub clear_retained_on_delete: bool,
+    #[serde(default)]
+    pub status: BrokerStatus,
+    #[serde(default)]
+    pub tls: BrokerTls,
+    #[serde(default)]
+    pub ws_path: String, // websocket endpoint, defaults to /mqtt
+    #[serde(default)]
+    pub ws_headers: BTreeMap<String, String>, // additional websocket handshake headers
+    #[serde(default)]
+    pub protocol_version: MqttVersion,
+    #[serde(default)]
+    pub v5: BrokerV5,
 }
 
 impl PartialEq for Broker {
@@ -232,9 +299,142 @@ impl PartialEq for Broker {
             && self.protocol == other.protocol
             && self.username == other.username
             && self.password == other.password
+            && self.status == other.status
+            && self.tls == other.tls
+            && self.ws_path == other.ws_path
+            && self.ws_headers == other.ws_headers
+            && self.protocol_version == other.protocol_version
+            && self.v5 == other.v5
+    }
+}
+
+/// Broker status message schema.
+///
+/// Retained status of this device, set to online after connecting and to offline on shutdown
+/// (or by the broker as last will if the connection is lost).
+///
+/// Disabled by default.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq)]
+pub struct BrokerStatus {
+    pub enabled: bool,
+    /// Status topic, `{device_id}` and `{device_group}` are replaced by the device values
+    #[schema(example = "{device_id}/status")]
+    pub topic: String,
+    /// State of the status message while the device is connected
+    #[schema(example = "online")]
+    pub online_payload: String,
+    /// State of the status message after a shutdown or a lost connection
+    #[schema(example = "offline")]
+    pub offline_payload: String,
+}
+
+impl Default for BrokerStatus {
+    fn default() -> Self {
+        BrokerStatus {
+            enabled: false,
+            topic: "{device_id}/status".to_string(),
+            online_payload: "online".to_string(),
+            offline_payload: "offline".to_string(),
+        }
     }
 }
 
+/// Broker TLS schema.
+///
+/// Used for `mqtts://` and `wss://` connections. Certificates and keys can be given as file path or PEM text.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq)]
+#[serde(default)]
+pub struct BrokerTls {
+    /// CA certificates which are trusted in addition to (or instead of) the system certificates
+    #[schema(example = "C:/ProgramData/certs/plant-ca.pem")]
+    pub ca: String,
+    /// Trust the certificates of the operating system
+    pub native_roots: bool,
+    /// Client certificate chain for mutual TLS
+    #[schema(example = "C:/ProgramData/certs/client.pem")]
+    pub client_cert: String,
+    /// Private key of the client certificate (PKCS#8, PKCS#1 or SEC1)
+    #[schema(example = "C:/ProgramData/certs/client.key")]
+    pub client_key: String,
+    /// PKCS#12 file with client certificate and key (used instead of `client_cert`/`client_key`)
+    #[schema(example = "C:/ProgramData/certs/client.p12")]
+    pub client_pkcs12: String,
+    pub client_pkcs12_password: String,
+    /// Server name which is sent (SNI) and verified instead of the host (e.g. if the host is an IP
+    /// address)
+    #[schema(example = "broker.plant.local")]
+    pub server_name: String,
+    pub min_version: TlsVersion,
+    /// INSECURE, lab setups only: accept any broker certificate without verification
+    pub insecure: bool,
+}
+
+impl Default for BrokerTls {
+    fn default() -> Self {
+        BrokerTls {
+            ca: String::new(),
+            native_roots: true,
+            client_cert: String::new(),
+            client_key: String::new(),
+            client_pkcs12: String::new(),
+            client_pkcs12_password: String::new(),
+            server_name: String::new(),
+            min_version: TlsVersion::default(),
+            insecure: false,
+        }
+    }
+}
+
+/// Minimum TLS version schema.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TlsVersion {
+    #[default]
+    Tls12,
+    Tls13,
+}
+
+/// MQTT protocol version schema.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MqttVersion {
+    /// MQTT 3.1.1
+    #[default]
+    V311,
+    /// MQTT 5, published messages carry message properties
+    V5,
+}
+
+/// Broker MQTT 5 schema.
+///
+/// Properties of published messages, used if the protocol version is `v5`. Messages carry the
+/// content type `application/json` and the user properties `deviceId`, `fileId` and `change`.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct BrokerV5 {
+    /// Seconds after which the broker discards an undelivered message (0 to keep it)
+    #[schema(example = "86400")]
+    pub message_expiry_secs: u32,
+    /// Topic for replies to published messages (empty for none), `{device_id}` and
+    /// `{device_group}` are replaced by the device values
+    #[schema(example = "{device_id}/replies")]
+    pub response_topic: String,
+}
+
+/// MQTT quality of service schema.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+#[allow(clippy::enum_variant_names)] // names of the MQTT spec
+pub enum MqttQos {
+    /// QoS 0, messages get lost if the broker or a subscriber is not available
+    #[default]
+    AtMostOnce,
+    /// QoS 1, messages are delivered at least once (duplicates are possible)
+    AtLeastOnce,
+    /// QoS 2, messages are delivered exactly once
+    ExactlyOnce,
+}
+
 /// File DB operation errors
 #[derive(Serialize, Deserialize, ToSchema)]
 pub enum DBError {
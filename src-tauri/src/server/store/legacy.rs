@@ -0,0 +1,92 @@
+use super::{get, put, DB_KEY_BROKER, DB_KEY_FILES};
+use crate::server::router::{
+    files::{File, Files},
+    settings::Broker,
+};
+use microkv::MicroKV;
+use serde::Deserialize;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// File layout stored by application versions up to `0.0.3-rc4` (bincode encoded).
+#[derive(Deserialize)]
+struct FileV0 {
+    id: Uuid,
+    name: String,
+    enabled: bool,
+    last_update_utc: String,
+    update_state: String,
+    last_version: String,
+    path: String,
+    mqtt_topic: String,
+}
+
+/// Broker layout stored by application versions up to `0.0.3-rc4` (bincode encoded).
+#[derive(Deserialize)]
+struct BrokerV0 {
+    client_id: String,
+    device_group: String,
+    device_id: String,
+    host: String,
+    password: String,
+    port: u16,
+    protocol: String,
+    username: String,
+    state: String,
+    connected: bool,
+}
+
+/// Converts data stored in the old binary layout to the current json layout.
+pub fn migrate(db: &MicroKV) {
+    if get::<Files>(db, DB_KEY_FILES).is_err() {
+        if let Ok(old_files) = db.get_unwrap::<HashMap<Uuid, FileV0>>(DB_KEY_FILES) {
+            println!("migrate files state to json layout");
+            let files = old_files
+                .into_iter()
+                .map(|(id, file)| {
+                    (
+                        id,
+                        File {
+                            id: file.id,
+                            name: file.name,
+                            enabled: file.enabled,
+                            last_update_utc: file.last_update_utc,
+                            update_state: file.update_state,
+                            last_version: file.last_version,
+                            path: file.path,
+                            mqtt_topic: file.mqtt_topic,
+                            ..Default::default()
+                        },
+                    )
+                })
+                .collect::<Files>();
+
+            if let Err(err) = put(db, DB_KEY_FILES, &files) {
+                println!("Could not migrate file state: {err:?}")
+            }
+        }
+    }
+
+    if get::<Broker>(db, DB_KEY_BROKER).is_err() {
+        if let Ok(broker) = db.get_unwrap::<BrokerV0>(DB_KEY_BROKER) {
+            println!("migrate broker state to json layout");
+            let broker = Broker {
+                client_id: broker.client_id,
+                device_group: broker.device_group,
+                device_id: broker.device_id,
+                host: broker.host,
+                password: broker.password,
+                port: broker.port,
+                protocol: broker.protocol,
+                username: broker.username,
+                state: broker.state,
+                connected: broker.connected,
+                ..Default::default()
+            };
+
+            if let Err(err) = put(db, DB_KEY_BROKER, &broker) {
+                println!("Could not migrate broker state: {err:?}")
+            }
+        }
+    }
+}
@@ -1,10 +1,15 @@
-use crate::server::router::{files::Files, settings::Broker};
+use crate::server::router::{
+    files::Files, metrics::DebounceMetrics, scans::ScanStatus, settings::Broker,
+};
 use microkv::MicroKV;
+use serde::{de::DeserializeOwned, Serialize};
 use std::{
     path::Path,
     sync::{Arc, RwLock},
 };
 
+mod legacy;
+
 pub static FILE_DB_PATH: &str = "C:/ProgramData/Tauri/EH File Version Monitor";
 pub static FILE_DB_NAME: &str = "application_db";
 
@@ -14,6 +19,8 @@ static DB_KEY_BROKER: &str = "broker";
 /// Shared application state
 pub struct AppState {
     pub db: Arc<RwLock<MicroKV>>,
+    pub debounce_metrics: Arc<RwLock<DebounceMetrics>>,
+    pub scan_status: Arc<RwLock<ScanStatus>>,
 }
 
 pub fn init_state() -> Arc<AppState> {
@@ -23,6 +30,9 @@ pub fn init_state() -> Arc<AppState> {
         .expect("Failed to create MicroKV from a stored file or create MicroKV for this file")
         .set_auto_commit(true);
 
+    // convert data stored by older application versions
+    legacy::migrate(&database);
+
     // init content in file db if file or keys do not exist
     init_file_state_if_necessary(&database);
     init_broker_state_if_necessary(&database);
@@ -30,21 +40,44 @@ pub fn init_state() -> Arc<AppState> {
     // create app state
     return Arc::new(AppState {
         db: Arc::new(RwLock::new(database)),
+        debounce_metrics: Arc::new(RwLock::new(DebounceMetrics::default())),
+        scan_status: Arc::new(RwLock::new(ScanStatus::default())),
     });
 }
 
+/// Reads a value from the local file db.
+///
+/// Values are stored as json strings, so new optional fields do not break existing databases.
+pub fn get<T: DeserializeOwned>(db: &MicroKV, key: &str) -> Result<T, String> {
+    let value = db
+        .get_unwrap::<String>(key)
+        .map_err(|err| err.msg.unwrap_or("key not found in storage".to_string()))?;
+
+    serde_json::from_str::<T>(&value).map_err(|err| format!("Could not parse '{key}': {err}"))
+}
+
+/// Writes a value to the local file db.
+pub fn put<T: Serialize>(db: &MicroKV, key: &str, value: &T) -> Result<(), String> {
+    let value = serde_json::to_string(value).map_err(|err| err.to_string())?;
+
+    db.put(key, &value).map_err(|err| {
+        err.msg
+            .unwrap_or("Could not write data to file".to_string())
+    })
+}
+
 fn init_file_state_if_necessary(db: &MicroKV) {
-    if db.get_unwrap::<Files>(DB_KEY_FILES).is_err() {
+    if get::<Files>(db, DB_KEY_FILES).is_err() {
         println!("need to update inital files state");
 
-        if let Err(err) = db.put(DB_KEY_FILES, &Files::new()) {
+        if let Err(err) = put(db, DB_KEY_FILES, &Files::new()) {
             println!("Could not initialize file state: {err:?}")
         }
     }
 }
 
 fn init_broker_state_if_necessary(db: &MicroKV) {
-    if db.get_unwrap::<Broker>(DB_KEY_BROKER).is_err() {
+    if get::<Broker>(db, DB_KEY_BROKER).is_err() {
         println!("need to update inital broker state");
         let broker = Broker {
             client_id: "eh-mqtt-client-1".to_string(),
@@ -53,7 +86,7 @@ fn init_broker_state_if_necessary(db: &MicroKV) {
             protocol: "mqtt://".to_string(),
             ..Default::default()
         };
-        if let Err(err) = db.put(DB_KEY_BROKER, &broker) {
+        if let Err(err) = put(db, DB_KEY_BROKER, &broker) {
             println!("Could not initialize broker state: {err:?}")
         }
     }